@@ -0,0 +1,582 @@
+//! Analyzes the access pattern of a trace file: its footprint, reuse distances and
+//! working set sizes, and suggests the cache capacities to benchmark with.
+
+use std::{collections::HashMap, fmt, ops::Range, path::Path};
+
+use anyhow::Context;
+
+use crate::{
+    parser::{binary, TraceFormat, TraceOperation},
+    trace_file::TraceDescriptor,
+    trace_reader::TraceInput,
+};
+
+/// The default number of requests in a window of the working set sizes.
+pub const DEFAULT_WINDOW_SIZE: u64 = 100_000;
+
+/// The access pattern of a trace file.
+///
+/// Every key in the `start..start+len` range of a request counts as an access.
+/// Deletes are not accesses; they remove the key so that the next access to it is
+/// cold.
+#[derive(Debug)]
+pub struct TraceAnalysis {
+    pub format: TraceFormat,
+    /// `true` if the trace records the operations, so that the reads, writes and
+    /// deletes are counted.
+    pub has_operations: bool,
+    pub num_requests: u64,
+    pub num_writes: u64,
+    pub num_deletes: u64,
+    /// The number of requests for more than one key.
+    pub num_range_requests: u64,
+    pub max_range_len: usize,
+    pub num_accesses: u64,
+    /// The number of accesses to keys that were never accessed or were deleted.
+    pub num_cold_accesses: u64,
+    /// The number of unique keys, i.e. the footprint of the trace.
+    pub num_keys: usize,
+    /// The number of keys accessed only once.
+    pub num_one_hit_wonders: usize,
+    pub window_size: u64,
+    pub working_sets: Vec<WorkingSet>,
+    /// The number of accesses by reuse distance, which is the number of distinct
+    /// keys accessed since the last access to the same key.
+    reuse_distances: Vec<u64>,
+}
+
+/// The keys accessed in a window of consecutive requests.
+#[derive(Debug)]
+pub struct WorkingSet {
+    pub first_request: u64,
+    pub num_requests: u64,
+    pub num_keys: usize,
+    /// The number of keys accessed for the first time in the trace.
+    pub num_new_keys: usize,
+    /// The number of unique keys accessed so far.
+    pub footprint: usize,
+}
+
+/// Reads the trace file at `path` and analyzes its access pattern. If `format` is
+/// `None`, the format is detected from the contents of the file.
+pub fn analyze_trace(
+    path: &Path,
+    format: Option<TraceFormat>,
+    window_size: u64,
+) -> anyhow::Result<TraceAnalysis> {
+    let trace = TraceInput::new(path)?;
+    let format = match format {
+        Some(format) => format,
+        None => TraceFormat::detect(trace.peek()?)
+            .with_context(|| format!("Cannot detect the format of {}", path.display()))?,
+    };
+    let has_operations = match format {
+        TraceFormat::Binary => binary::read_columns(trace.peek()?)?.has_operations(),
+        _ => format.has_operations(),
+    };
+    let mut analyzer = Analyzer::new(format, has_operations, window_size.max(1));
+    for entry in format.read_entries(trace.open()?, path, false)? {
+        let entry = entry?;
+        analyzer.request(entry.range(), entry.operation());
+    }
+    Ok(analyzer.finish())
+}
+
+impl TraceAnalysis {
+    pub fn one_hit_wonder_ratio(&self) -> f64 {
+        ratio(self.num_one_hit_wonders as u64, self.num_keys as u64)
+    }
+
+    /// Returns the number of accesses in the buckets of reuse distances `0`, `1`,
+    /// `2..4`, `4..8`, `8..16` and so on. Empty buckets at the end are omitted.
+    pub fn reuse_distance_histogram(&self) -> Vec<(Range<usize>, u64)> {
+        let mut histogram = Vec::new();
+        let mut start = 0;
+        while start < self.reuse_distances.len() {
+            let end = (start * 2).max(start + 1);
+            let count = self.reuse_distances[start..end.min(self.reuse_distances.len())]
+                .iter()
+                .sum();
+            histogram.push((start..end, count));
+            start = end;
+        }
+        histogram
+    }
+
+    /// Returns the hit ratio in percent of an LRU cache with the capacity. An
+    /// access is a hit if its reuse distance is less than the capacity.
+    pub fn lru_hit_ratio(&self, capacity: usize) -> f64 {
+        let hits = self.reuse_distances.iter().take(capacity).sum::<u64>();
+        ratio(hits, self.num_accesses)
+    }
+
+    /// Returns the capacities at fixed fractions of the footprint of the trace.
+    pub fn suggested_capacities(&self) -> Vec<usize> {
+        TraceDescriptor::capacities_for(self.num_keys)
+    }
+}
+
+impl fmt::Display for TraceAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Format: {:?}", self.format)?;
+        writeln!(f, "Requests: {}", self.num_requests)?;
+        if self.has_operations {
+            let num_reads = self.num_requests - self.num_writes - self.num_deletes;
+            writeln!(f, "  Reads: {num_reads}")?;
+            writeln!(f, "  Writes: {}", self.num_writes)?;
+            writeln!(f, "  Deletes: {}", self.num_deletes)?;
+        }
+        let num_key_requests = self.num_requests - self.num_deletes;
+        writeln!(
+            f,
+            "  Range requests: {} ({:.3}%, mean length: {:.3}, max length: {})",
+            self.num_range_requests,
+            ratio(self.num_range_requests, num_key_requests),
+            self.num_accesses as f64 / num_key_requests.max(1) as f64,
+            self.max_range_len
+        )?;
+        writeln!(f, "Accesses: {}", self.num_accesses)?;
+        writeln!(
+            f,
+            "  Cold accesses: {} ({:.3}%)",
+            self.num_cold_accesses,
+            ratio(self.num_cold_accesses, self.num_accesses)
+        )?;
+        writeln!(f, "Unique keys: {}", self.num_keys)?;
+        writeln!(
+            f,
+            "  One-hit wonders: {} ({:.3}%)",
+            self.num_one_hit_wonders,
+            self.one_hit_wonder_ratio()
+        )?;
+
+        writeln!(f)?;
+        writeln!(f, "Reuse Distance, Accesses, Ratio, Cumulative Ratio")?;
+        let mut cumulative = 0;
+        for (distances, count) in self.reuse_distance_histogram() {
+            cumulative += count;
+            let distances = if distances.len() == 1 {
+                distances.start.to_string()
+            } else {
+                format!("{}..{}", distances.start, distances.end)
+            };
+            writeln!(
+                f,
+                "{}, {}, {:.3}, {:.3}",
+                distances,
+                count,
+                ratio(count, self.num_accesses),
+                ratio(cumulative, self.num_accesses)
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "First Request, Requests, Unique Keys, New Keys, Footprint (window: {} requests)",
+            self.window_size
+        )?;
+        for ws in &self.working_sets {
+            writeln!(
+                f,
+                "{}, {}, {}, {}, {}",
+                ws.first_request, ws.num_requests, ws.num_keys, ws.num_new_keys, ws.footprint
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Fraction of Footprint, Capacity, LRU Hit Ratio")?;
+        for fraction in TraceDescriptor::CAPACITY_FRACTIONS {
+            let capacity = ((self.num_keys as f64 * fraction) as usize).max(1);
+            writeln!(
+                f,
+                "{:.2}, {}, {:.3}",
+                fraction,
+                capacity,
+                self.lru_hit_ratio(capacity)
+            )?;
+        }
+        let capacities = self
+            .suggested_capacities()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        write!(f, "\nSuggested capacities = [{}]", capacities.join(", "))
+    }
+}
+
+fn ratio(n: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        n as f64 / total as f64 * 100.0
+    }
+}
+
+struct KeyState {
+    /// The index of the last access to the key in `Analyzer::last_accesses`.
+    last_access: u64,
+    num_accesses: u64,
+    deleted: bool,
+}
+
+struct Analyzer {
+    analysis: TraceAnalysis,
+    keys: HashMap<usize, KeyState>,
+    /// Has 1 at the index of the last access to each key that is not deleted, so
+    /// that the sum over a range of indices is the number of distinct keys
+    /// accessed in it. It is compacted when it grows much larger than the number
+    /// of keys.
+    last_accesses: FenwickTree,
+    window: WorkingSet,
+    /// The index of the first access of the current window in `last_accesses`.
+    window_start: u64,
+}
+
+impl Analyzer {
+    /// The minimum length of `last_accesses` to compact it.
+    const MIN_COMPACTION_LEN: usize = 1024;
+
+    fn new(format: TraceFormat, has_operations: bool, window_size: u64) -> Self {
+        Self {
+            analysis: TraceAnalysis {
+                format,
+                has_operations,
+                num_requests: 0,
+                num_writes: 0,
+                num_deletes: 0,
+                num_range_requests: 0,
+                max_range_len: 0,
+                num_accesses: 0,
+                num_cold_accesses: 0,
+                num_keys: 0,
+                num_one_hit_wonders: 0,
+                window_size,
+                working_sets: Vec::new(),
+                reuse_distances: Vec::new(),
+            },
+            keys: HashMap::new(),
+            last_accesses: FenwickTree::default(),
+            window: WorkingSet::new(0),
+            window_start: 0,
+        }
+    }
+
+    fn request(&mut self, range: Range<usize>, operation: Option<TraceOperation>) {
+        if self.window.num_requests == self.analysis.window_size {
+            self.next_window();
+        }
+        let a = &mut self.analysis;
+        a.num_requests += 1;
+        self.window.num_requests += 1;
+
+        match operation {
+            Some(TraceOperation::Delete) => {
+                a.num_deletes += 1;
+                for key in range {
+                    self.delete(key);
+                }
+                return;
+            }
+            Some(TraceOperation::Write) => a.num_writes += 1,
+            Some(TraceOperation::Read) | None => (),
+        }
+        if range.len() > 1 {
+            a.num_range_requests += 1;
+        }
+        a.max_range_len = a.max_range_len.max(range.len());
+        for key in range {
+            self.access(key);
+        }
+    }
+
+    fn access(&mut self, key: usize) {
+        if self.last_accesses.len() >= (self.keys.len() * 2).max(Self::MIN_COMPACTION_LEN) {
+            self.compact();
+        }
+        let a = &mut self.analysis;
+        let now = self.last_accesses.len() as u64;
+        a.num_accesses += 1;
+
+        match self.keys.get_mut(&key) {
+            None => {
+                a.num_cold_accesses += 1;
+                self.window.num_keys += 1;
+                self.window.num_new_keys += 1;
+                self.keys.insert(
+                    key,
+                    KeyState {
+                        last_access: now,
+                        num_accesses: 1,
+                        deleted: false,
+                    },
+                );
+            }
+            Some(state) => {
+                if state.last_access < self.window_start {
+                    self.window.num_keys += 1;
+                }
+                if state.deleted {
+                    a.num_cold_accesses += 1;
+                    state.deleted = false;
+                } else {
+                    let last = state.last_access as usize;
+                    let distance = self.last_accesses.sum(last + 1..now as usize) as usize;
+                    if a.reuse_distances.len() <= distance {
+                        a.reuse_distances.resize(distance + 1, 0);
+                    }
+                    a.reuse_distances[distance] += 1;
+                    self.last_accesses.sub_one(last);
+                }
+                state.last_access = now;
+                state.num_accesses += 1;
+            }
+        }
+        self.last_accesses.push(1);
+    }
+
+    fn delete(&mut self, key: usize) {
+        if let Some(state) = self.keys.get_mut(&key) {
+            if !state.deleted {
+                state.deleted = true;
+                self.last_accesses.sub_one(state.last_access as usize);
+            }
+        }
+    }
+
+    /// Renumbers the last accesses of the keys to `0..keys.len()` in the same
+    /// order, so that `last_accesses` tracks the footprint instead of growing with
+    /// every access.
+    fn compact(&mut self) {
+        let mut states = self.keys.values_mut().collect::<Vec<_>>();
+        states.sort_unstable_by_key(|state| state.last_access);
+        let window_start = states.partition_point(|state| state.last_access < self.window_start);
+        let values = states
+            .into_iter()
+            .enumerate()
+            .map(|(i, state)| {
+                state.last_access = i as u64;
+                u32::from(!state.deleted)
+            })
+            .collect();
+        self.last_accesses = FenwickTree::from_values(values);
+        self.window_start = window_start as u64;
+    }
+
+    fn next_window(&mut self) {
+        let first_request = self.analysis.num_requests;
+        let mut window = std::mem::replace(&mut self.window, WorkingSet::new(first_request));
+        window.footprint = self.keys.len();
+        self.analysis.working_sets.push(window);
+        self.window_start = self.last_accesses.len() as u64;
+    }
+
+    fn finish(mut self) -> TraceAnalysis {
+        if self.window.num_requests > 0 {
+            self.next_window();
+        }
+        let mut analysis = self.analysis;
+        analysis.num_keys = self.keys.len();
+        analysis.num_one_hit_wonders = self.keys.values().filter(|s| s.num_accesses == 1).count();
+        analysis
+    }
+}
+
+impl WorkingSet {
+    fn new(first_request: u64) -> Self {
+        Self {
+            first_request,
+            num_requests: 0,
+            num_keys: 0,
+            num_new_keys: 0,
+            footprint: 0,
+        }
+    }
+}
+
+/// A Fenwick tree (binary indexed tree) of 0s and 1s that grows as values are
+/// pushed. The counts are kept in `u32` to halve the memory usage; they never
+/// exceed the number of unique keys.
+#[derive(Default)]
+struct FenwickTree {
+    tree: Vec<u32>,
+}
+
+impl FenwickTree {
+    /// Builds the tree of the values in linear time.
+    fn from_values(mut tree: Vec<u32>) -> Self {
+        for i in 1..=tree.len() {
+            let parent = i + lowbit(i);
+            if parent <= tree.len() {
+                tree[parent - 1] += tree[i - 1];
+            }
+        }
+        Self { tree }
+    }
+
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Appends a value. The new node `i` (1-based) covers the values in
+    /// `(i - lowbit(i))..=i`, so it is the value plus the sum of the preceding
+    /// values in that range.
+    fn push(&mut self, value: u32) {
+        let i = self.tree.len() + 1;
+        let first = i - lowbit(i);
+        let node = value + (self.prefix_sum(i - 1) - self.prefix_sum(first));
+        self.tree.push(node);
+    }
+
+    fn sub_one(&mut self, index: usize) {
+        let mut i = index + 1;
+        while i <= self.tree.len() {
+            self.tree[i - 1] -= 1;
+            i += lowbit(i);
+        }
+    }
+
+    /// Returns the sum of the values in the range of indices.
+    fn sum(&self, range: Range<usize>) -> u32 {
+        self.prefix_sum(range.end) - self.prefix_sum(range.start)
+    }
+
+    /// Returns the sum of the first `n` values.
+    fn prefix_sum(&self, n: usize) -> u32 {
+        let mut sum = 0;
+        let mut i = n;
+        while i > 0 {
+            sum += self.tree[i - 1];
+            i -= lowbit(i);
+        }
+        sum
+    }
+}
+
+fn lowbit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a stream of pseudo-random numbers.
+    fn random(seed: u64) -> impl Iterator<Item = u64> {
+        (0u64..).map(move |i| xxhash_rust::xxh3::xxh3_64_with_seed(&i.to_le_bytes(), seed))
+    }
+
+    #[test]
+    fn fenwick_tree_sums() {
+        let mut rng = random(1);
+        let mut rng = || rng.next().unwrap();
+        let mut tree = FenwickTree::default();
+        let mut values = Vec::new();
+        for _ in 0..1_000 {
+            let value = (rng() % 2) as u32;
+            tree.push(value);
+            values.push(value);
+            // Clear a random 1, as an access moves the last access of a key.
+            let index = rng() as usize % values.len();
+            if values[index] == 1 {
+                tree.sub_one(index);
+                values[index] = 0;
+            }
+
+            let start = rng() as usize % values.len();
+            let end = start + rng() as usize % (values.len() - start + 1);
+            assert_eq!(tree.sum(start..end), values[start..end].iter().sum::<u32>());
+        }
+        assert_eq!(tree.prefix_sum(values.len()), values.iter().sum::<u32>());
+
+        let rebuilt = FenwickTree::from_values(values.clone());
+        for n in 0..=values.len() {
+            assert_eq!(rebuilt.prefix_sum(n), tree.prefix_sum(n));
+        }
+    }
+
+    fn analyze(requests: &[(usize, Option<TraceOperation>)]) -> TraceAnalysis {
+        let mut analyzer = Analyzer::new(TraceFormat::Twitter, true, 100);
+        for &(key, operation) in requests {
+            analyzer.request(key..(key + 1), operation);
+        }
+        analyzer.finish()
+    }
+
+    #[test]
+    fn reuse_distances() {
+        let read = |key| (key, Some(TraceOperation::Read));
+        // The distances of the reuses of 0, 1, 1 and 2 are 2, 2, 0 and 2.
+        let analysis = analyze(&[
+            read(0),
+            read(1),
+            read(2),
+            read(0),
+            read(1),
+            read(1),
+            read(2),
+        ]);
+        assert_eq!(analysis.reuse_distances, vec![1, 0, 3]);
+        assert_eq!(analysis.num_cold_accesses, 3);
+        assert_eq!(analysis.lru_hit_ratio(2), ratio(1, 7));
+        assert_eq!(analysis.lru_hit_ratio(3), ratio(4, 7));
+
+        // A deleted key is neither counted in the distances nor reused.
+        let delete = (1, Some(TraceOperation::Delete));
+        let analysis = analyze(&[read(0), read(1), delete, read(2), read(0), read(1)]);
+        assert_eq!(analysis.reuse_distances, vec![0, 1]);
+        assert_eq!(analysis.num_cold_accesses, 4);
+    }
+
+    /// The hit ratios given by the reuse distances are the ones of an LRU cache.
+    #[test]
+    fn lru_hit_ratios() {
+        let keys = random(7)
+            .take(5_000)
+            .map(|n| (n % 64) as usize)
+            .collect::<Vec<_>>();
+        let requests = keys.iter().map(|&key| (key, None)).collect::<Vec<_>>();
+        let analysis = analyze(&requests);
+
+        for capacity in [1, 8, 32, 63] {
+            let mut lru = std::collections::VecDeque::new();
+            let mut hits = 0;
+            for key in &keys {
+                if let Some(i) = lru.iter().position(|k| k == key) {
+                    hits += 1;
+                    lru.remove(i);
+                } else if lru.len() == capacity {
+                    lru.pop_back();
+                }
+                lru.push_front(*key);
+            }
+            assert_eq!(
+                analysis.lru_hit_ratio(capacity),
+                ratio(hits, keys.len() as u64)
+            );
+        }
+    }
+
+    /// The tree of the last accesses is compacted to the footprint, and the
+    /// working sets are still counted by the windows of requests.
+    #[test]
+    fn compact_last_accesses() {
+        let keys = random(3)
+            .take(10_000)
+            .map(|n| (n % 100) as usize)
+            .collect::<Vec<_>>();
+        let mut analyzer = Analyzer::new(TraceFormat::Arc, false, 300);
+        for &key in &keys {
+            analyzer.request(key..(key + 1), None);
+            assert!(analyzer.last_accesses.len() <= Analyzer::MIN_COMPACTION_LEN);
+        }
+        let analysis = analyzer.finish();
+
+        let windows = keys.chunks(300).collect::<Vec<_>>();
+        assert_eq!(analysis.working_sets.len(), windows.len());
+        for (ws, window) in analysis.working_sets.iter().zip(windows) {
+            let unique = window.iter().collect::<std::collections::HashSet<_>>();
+            assert_eq!(ws.num_keys, unique.len());
+        }
+    }
+}
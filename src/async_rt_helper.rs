@@ -10,7 +10,7 @@ use rt_tokio as rt;
 #[cfg(feature = "rt-async-std")]
 use rt_async_std as rt;
 
-pub(crate) use rt::{spawn, yield_now};
+pub(crate) use rt::{spawn, spawn_blocking, yield_now};
 
 #[cfg(feature = "rt-tokio")]
 mod rt_tokio {
@@ -25,6 +25,18 @@ mod rt_tokio {
         tokio::spawn(future)
     }
 
+    /// Runs the blocking function on a thread dedicated to blocking operations and
+    /// returns its result.
+    pub(crate) async fn spawn_blocking<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        tokio::task::spawn_blocking(f)
+            .await
+            .expect("A blocking task panicked")
+    }
+
     pub(crate) async fn yield_now() {
         tokio::task::yield_now().await;
     }
@@ -43,6 +55,16 @@ mod rt_async_std {
         async_std::task::spawn(future)
     }
 
+    /// Runs the blocking function on a thread dedicated to blocking operations and
+    /// returns its result.
+    pub(crate) async fn spawn_blocking<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        async_std::task::spawn_blocking(f).await
+    }
+
     pub(crate) async fn yield_now() {
         async_std::task::yield_now().await;
     }
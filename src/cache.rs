@@ -1,6 +1,7 @@
 use std::{
-    hash::{BuildHasher, Hash, Hasher},
+    hash::{BuildHasher, Hash},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use crate::{
@@ -27,8 +28,70 @@ pub(crate) mod light_cache;
 #[cfg(feature = "light-cache-lru")]
 pub(crate) mod light_cache_lru;
 
-pub(crate) type Key = usize;
-pub(crate) type Value = (u32, Arc<[u8]>);
+/// The types of the keys that the cache drivers can store.
+pub(crate) trait CacheKey: Clone + Eq + Hash + Send + Sync + 'static {
+    /// Creates the key for `block`, one of the numeric keys in the range of the
+    /// trace entry.
+    fn new(config: &Config, entry: &TraceEntry, block: usize) -> Self;
+}
+
+impl CacheKey for usize {
+    fn new(_config: &Config, entry: &TraceEntry, block: usize) -> Self {
+        match entry.tenant() {
+            // Hash the whole numeric key with the tenant, as the key may have no
+            // spare bits for the tenant.
+            Some(tenant) => {
+                xxhash_rust::xxh3::xxh3_64_with_seed(&block.to_le_bytes(), tenant as u64) as usize
+            }
+            None => block,
+        }
+    }
+}
+
+impl CacheKey for String {
+    fn new(config: &Config, entry: &TraceEntry, block: usize) -> Self {
+        match entry.key() {
+            Some(key) => key.to_owned(),
+            None => synthetic_key(config, entry, block),
+        }
+    }
+}
+
+impl CacheKey for Vec<u8> {
+    fn new(config: &Config, entry: &TraceEntry, block: usize) -> Self {
+        match entry.key() {
+            Some(key) => key.as_bytes().to_vec(),
+            None => synthetic_key(config, entry, block).into_bytes(),
+        }
+    }
+}
+
+/// Converts a numeric key into a decimal string, zero-padded to `key_len` bytes
+/// if it is set. The key of a tenant is prefixed with the tenant index.
+fn synthetic_key(config: &Config, entry: &TraceEntry, block: usize) -> String {
+    let key = match config.key_len {
+        Some(len) => format!("{block:0len$}"),
+        None => block.to_string(),
+    };
+    match entry.tenant() {
+        Some(tenant) => format!("{tenant}:{key}"),
+        None => key,
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Value {
+    /// The weight of the entry. Only used when the cache is size aware.
+    pub(crate) weight: u32,
+    /// The time-to-live recorded in the trace. Only used when per-key expiration
+    /// is enabled.
+    #[cfg_attr(
+        any(feature = "moka-v08", feature = "moka-v09", feature = "moka-v010"),
+        allow(dead_code)
+    )]
+    pub(crate) ttl: Option<Duration>,
+    pub(crate) data: Arc<[u8]>,
+}
 
 pub(crate) trait CacheDriver<T> {
     fn get_or_insert(&mut self, entry: &T, report: &mut Report);
@@ -70,12 +133,22 @@ pub(crate) trait AsyncCacheDriver<T> {
     fn eviction_counters(&self) -> Option<Arc<EvictionCounters>>;
 }
 
+/// Processes the commands. `start` is the time the replay started, which is used
+/// to wait for the commands to be due when the trace is replayed by its timestamps.
 pub(crate) fn process_commands(
     commands: Vec<Command>,
     cache: &mut impl CacheDriver<TraceEntry>,
     report: &mut Report,
+    start: Instant,
 ) {
     for command in commands {
+        // Count the requests of each tenant (or class) by the changes of the
+        // counts. Only the entries of the interleaved traces have tenants, and only
+        // the ones of some synthetic workloads have classes.
+        let (tenant, class) = command
+            .entry()
+            .map_or((None, None), |e| (e.tenant(), e.class()));
+        let before = (tenant.is_some() || class.is_some()).then(|| report.counts());
         match command {
             Command::GetOrInsert(entry) => {
                 cache.get_or_insert(&entry, report);
@@ -94,16 +167,40 @@ pub(crate) fn process_commands(
                 cache.invalidate_entries_if(&entry);
             }
             Command::Iterate => cache.iterate(),
+            Command::WaitUntil(due) => {
+                let now = Instant::now();
+                if let Some(delay) = (start + due).checked_duration_since(now) {
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+        if let Some(before) = before {
+            if let Some(tenant) = tenant {
+                report.add_tenant_counts(tenant, before);
+            }
+            if let Some(class) = class {
+                report.add_class_counts(class, before);
+            }
         }
     }
 }
 
+/// Processes the commands. `start` is the time the replay started, which is used
+/// to wait for the commands to be due when the trace is replayed by its timestamps.
 pub(crate) async fn process_commands_async(
     commands: Vec<Command>,
     cache: &mut impl AsyncCacheDriver<TraceEntry>,
     report: &mut Report,
+    start: Instant,
 ) {
     for command in commands {
+        // Count the requests of each tenant (or class) by the changes of the
+        // counts. Only the entries of the interleaved traces have tenants, and only
+        // the ones of some synthetic workloads have classes.
+        let (tenant, class) = command
+            .entry()
+            .map_or((None, None), |e| (e.tenant(), e.class()));
+        let before = (tenant.is_some() || class.is_some()).then(|| report.counts());
         match command {
             Command::GetOrInsert(entry) => {
                 cache.get_or_insert(&entry, report).await;
@@ -122,6 +219,17 @@ pub(crate) async fn process_commands_async(
                 cache.invalidate_entries_if(&entry);
             }
             Command::Iterate => cache.iterate().await,
+            Command::WaitUntil(due) => {
+                async_io::Timer::at(start + due).await;
+            }
+        }
+        if let Some(before) = before {
+            if let Some(tenant) = tenant {
+                report.add_tenant_counts(tenant, before);
+            }
+            if let Some(class) = class {
+                report.add_class_counts(class, before);
+            }
         }
     }
 }
@@ -156,20 +264,29 @@ impl Counters {
 
 const VALUE_LEN: usize = 128;
 
-pub(crate) fn make_value(config: &Config, key: usize, req_id: usize) -> Value {
-    let policy_weight = if config.size_aware {
-        let mut hasher = DefaultHasher.build_hasher();
-        req_id.hash(&mut hasher);
-        // len will be [4 .. 2^16)
-        (hasher.finish() as u16).max(4) as u32
-    } else {
+pub(crate) fn make_value(config: &Config, entry: &TraceEntry, key: usize, req_id: usize) -> Value {
+    // If the trace records the value size, allocate a value of that size and use
+    // it as the weight.
+    let len = entry
+        .value_size()
+        .map_or(VALUE_LEN, |size| (size as usize).max(1));
+    let weight = if !config.size_aware {
         0
+    } else if entry.value_size().is_some() {
+        len as u32
+    } else {
+        // weight will be [4 .. 2^16)
+        (DefaultHasher.hash_one(req_id) as u16).max(4) as u32
     };
-    (policy_weight, do_make_value(key))
+    Value {
+        weight,
+        ttl: entry.ttl().map(|ttl| config.replay_duration(ttl)),
+        data: do_make_value(key, len),
+    }
 }
 
-fn do_make_value(key: usize) -> Arc<[u8]> {
-    let mut value = vec![0; VALUE_LEN].into_boxed_slice();
+fn do_make_value(key: usize, len: usize) -> Arc<[u8]> {
+    let mut value = vec![0; len].into_boxed_slice();
     value[0] = (key % 256) as u8;
     value.into()
 }
@@ -1,20 +1,20 @@
 use hashlink::LruCache;
 use parking_lot::Mutex;
 
-use super::{CacheDriver, Counters, DefaultHasher, Key, Value};
+use super::{CacheDriver, CacheKey, Counters, DefaultHasher, Value};
 use crate::{config::Config, parser::TraceEntry, report::Report};
 
 use std::sync::Arc;
 
-type HashLinkCache = LruCache<Key, Value, DefaultHasher>;
+type HashLinkCache<K> = LruCache<K, Value, DefaultHasher>;
 
 #[derive(Clone)]
-pub struct HashLink {
+pub struct HashLink<K> {
     config: Arc<Config>,
-    cache: Arc<Mutex<HashLinkCache>>,
+    cache: Arc<Mutex<HashLinkCache<K>>>,
 }
 
-impl HashLink {
+impl<K: CacheKey> HashLink<K> {
     pub fn new(config: &Config, capacity: usize) -> Self {
         if let Some(_ttl) = config.ttl {
             todo!()
@@ -32,31 +32,30 @@ impl HashLink {
         }
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.lock().get(key).cloned().is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = super::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = super::make_value(&self.config, entry, block, req_id);
         super::sleep_thread_for_insertion(&self.config);
         self.cache.lock().insert(key, value);
     }
 }
 
-impl CacheDriver<TraceEntry> for HashLink {
+impl<K: CacheKey> CacheDriver<TraceEntry> for HashLink<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -68,14 +67,20 @@ impl CacheDriver<TraceEntry> for HashLink {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
     }
+
+    fn invalidate(&mut self, entry: &TraceEntry) {
+        for block in entry.range() {
+            self.cache
+                .lock()
+                .remove(&K::new(&self.config, entry, block));
+        }
+    }
 }
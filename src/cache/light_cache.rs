@@ -1,18 +1,18 @@
-use super::{CacheDriver, Counters, DefaultHasher, Key, Value};
+use super::{CacheDriver, CacheKey, Counters, DefaultHasher, Value};
 use crate::{config::Config, parser::TraceEntry, report::Report};
 
-use light_cache::LightCache as LightCacheImpl;
 use light_cache::policy::NoopPolicy;
+use light_cache::LightCache as LightCacheImpl;
 
 use std::sync::Arc;
 
 #[derive(Clone)]
-pub struct LightCache {
+pub struct LightCache<K> {
     config: Arc<Config>,
-    cache: LightCacheImpl<Key, Value, DefaultHasher, NoopPolicy>,
+    cache: LightCacheImpl<K, Value, DefaultHasher, NoopPolicy>,
 }
 
-impl LightCache {
+impl<K: CacheKey> LightCache<K> {
     pub fn new(config: &Config, capacity: usize) -> Self {
         if let Some(_ttl) = config.ttl {
             todo!()
@@ -23,35 +23,38 @@ impl LightCache {
 
         Self {
             config: Arc::new(config.clone()),
-            cache: LightCacheImpl::from_parts_with_capacity(NoopPolicy::new(), DefaultHasher, capacity),
+            cache: LightCacheImpl::from_parts_with_capacity(
+                NoopPolicy::new(),
+                DefaultHasher,
+                capacity,
+            ),
         }
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = super::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = super::make_value(&self.config, entry, block, req_id);
         super::sleep_thread_for_insertion(&self.config);
         self.cache.insert(key, value);
     }
 }
 
-impl CacheDriver<TraceEntry> for LightCache {
+impl<K: CacheKey> CacheDriver<TraceEntry> for LightCache<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -63,14 +66,18 @@ impl CacheDriver<TraceEntry> for LightCache {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
     }
+
+    fn invalidate(&mut self, entry: &TraceEntry) {
+        for block in entry.range() {
+            self.cache.remove(&K::new(&self.config, entry, block));
+        }
+    }
 }
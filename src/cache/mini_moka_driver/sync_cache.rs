@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use crate::{
-    cache::{self, CacheDriver, Counters, DefaultHasher, Key, Value},
+    cache::{self, CacheDriver, CacheKey, Counters, DefaultHasher, Value},
     config::Config,
     parser::TraceEntry,
     report::Report,
@@ -17,24 +17,24 @@ use mini_moka::sync::Cache;
 use crate::moka::dash::Cache;
 
 #[derive(Clone)]
-pub struct MiniMokSyncCache {
+pub struct MiniMokSyncCache<K> {
     config: Arc<Config>,
-    cache: Cache<Key, Value, DefaultHasher>,
+    cache: Cache<K, Value, DefaultHasher>,
 }
 
-impl MiniMokSyncCache {
+impl<K: CacheKey> MiniMokSyncCache<K> {
     pub fn new(config: &Config, max_cap: u64, init_cap: usize) -> Self {
         let mut builder = Cache::builder()
             .max_capacity(max_cap)
             .initial_capacity(init_cap);
-        if let Some(ttl) = config.ttl {
+        if let Some(ttl) = config.replay_ttl() {
             builder = builder.time_to_live(ttl);
         }
-        if let Some(tti) = config.tti {
+        if let Some(tti) = config.replay_tti() {
             builder = builder.time_to_idle(tti)
         }
         if config.size_aware {
-            builder = builder.weigher(|_k, (s, _v)| *s);
+            builder = builder.weigher(|_k, v: &Value| v.weight);
         }
 
         Self {
@@ -43,31 +43,30 @@ impl MiniMokSyncCache {
         }
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = cache::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = cache::make_value(&self.config, entry, block, req_id);
         cache::sleep_thread_for_insertion(&self.config);
         self.cache.insert(key, value);
     }
 }
 
-impl CacheDriver<TraceEntry> for MiniMokSyncCache {
+impl<K: CacheKey> CacheDriver<TraceEntry> for MiniMokSyncCache<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -79,12 +78,10 @@ impl CacheDriver<TraceEntry> for MiniMokSyncCache {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -92,7 +89,7 @@ impl CacheDriver<TraceEntry> for MiniMokSyncCache {
 
     fn invalidate(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
-            self.cache.invalidate(&block);
+            self.cache.invalidate(&K::new(&self.config, entry, block));
         }
     }
 
@@ -106,7 +103,7 @@ impl CacheDriver<TraceEntry> for MiniMokSyncCache {
             entry.key();
             count += 1;
 
-            if count % 500 == 0 {
+            if count.is_multiple_of(500) {
                 std::thread::yield_now();
             }
         }
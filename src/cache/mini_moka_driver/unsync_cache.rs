@@ -1,5 +1,5 @@
 use crate::{
-    cache::{self, CacheDriver, Counters, DefaultHasher, Key, Value},
+    cache::{self, CacheDriver, CacheKey, Counters, DefaultHasher, Value},
     config::Config,
     parser::TraceEntry,
     report::Report,
@@ -11,22 +11,22 @@ use mini_moka::unsync::{Cache, CacheBuilder};
 #[cfg(any(feature = "moka-v08", feature = "moka-v09"))]
 use crate::moka::unsync::{Cache, CacheBuilder};
 
-pub struct MiniMokaUnsyncCache {
+pub struct MiniMokaUnsyncCache<K> {
     config: Config,
-    cache: Cache<Key, Value, DefaultHasher>,
+    cache: Cache<K, Value, DefaultHasher>,
 }
 
-impl MiniMokaUnsyncCache {
+impl<K: CacheKey> MiniMokaUnsyncCache<K> {
     pub fn new(config: &Config, max_cap: u64, init_cap: usize) -> Self {
         let mut builder = CacheBuilder::new(max_cap).initial_capacity(init_cap);
-        if let Some(ttl) = config.ttl {
+        if let Some(ttl) = config.replay_ttl() {
             builder = builder.time_to_live(ttl);
         }
-        if let Some(tti) = config.tti {
+        if let Some(tti) = config.replay_tti() {
             builder = builder.time_to_idle(tti)
         }
         if config.size_aware {
-            builder = builder.weigher(|_k, (s, _v)| *s);
+            builder = builder.weigher(|_k, v: &Value| v.weight);
         }
 
         Self {
@@ -35,12 +35,12 @@ impl MiniMokaUnsyncCache {
         }
     }
 
-    fn get(&mut self, key: &usize) -> bool {
+    fn get(&mut self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&mut self, key: usize, req_id: usize) {
-        let value = cache::make_value(&self.config, key, req_id);
+    fn insert(&mut self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = cache::make_value(&self.config, entry, block, req_id);
         cache::sleep_thread_for_insertion(&self.config);
         self.cache.insert(key, value);
     }
@@ -48,25 +48,24 @@ impl MiniMokaUnsyncCache {
     fn invalidate_entries_if(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
             self.cache
-                .invalidate_entries_if(move |_k, (_s, v)| v[0] == (block % 256) as u8)
+                .invalidate_entries_if(move |_k, v| v.data[0] == (block % 256) as u8)
         }
     }
 }
 
-impl CacheDriver<TraceEntry> for MiniMokaUnsyncCache {
+impl<K: CacheKey> CacheDriver<TraceEntry> for MiniMokaUnsyncCache<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -78,12 +77,10 @@ impl CacheDriver<TraceEntry> for MiniMokaUnsyncCache {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -91,7 +88,7 @@ impl CacheDriver<TraceEntry> for MiniMokaUnsyncCache {
 
     fn invalidate(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
-            self.cache.invalidate(&block);
+            self.cache.invalidate(&K::new(&self.config, entry, block));
         }
     }
 
@@ -81,8 +81,11 @@ struct InitClosureError2;
 pub(crate) mod expiry {
     use std::time::{Duration, Instant};
 
+    use crate::cache::Value;
+
     /// Implements `moka::Expiry` trait to support per-entry expiration. Our
-    /// implementation simply emulates the cache level TTL and TTI.
+    /// implementation emulates the cache level TTL and TTI. If the trace records
+    /// a TTL for the entry, it is used instead of the cache level TTL.
     pub(crate) struct MokabenchExpiry {
         ttl: Option<Duration>,
         tti: Option<Duration>,
@@ -92,16 +95,20 @@ pub(crate) mod expiry {
         pub(crate) fn new(ttl: Option<Duration>, tti: Option<Duration>) -> Self {
             Self { ttl, tti }
         }
+
+        fn ttl(&self, value: &Value) -> Option<Duration> {
+            value.ttl.or(self.ttl)
+        }
     }
 
-    impl<K, V> crate::moka::Expiry<K, V> for MokabenchExpiry {
+    impl<K> crate::moka::Expiry<K, Value> for MokabenchExpiry {
         fn expire_after_create(
             &self,
             _key: &K,
-            _value: &V,
+            value: &Value,
             _current_time: Instant,
         ) -> Option<Duration> {
-            match (self.tti, self.ttl) {
+            match (self.tti, self.ttl(value)) {
                 (None, None) => None,
                 (tti @ Some(_), None) => tti,
                 (None, ttl @ Some(_)) => ttl,
@@ -112,12 +119,12 @@ pub(crate) mod expiry {
         fn expire_after_read(
             &self,
             _key: &K,
-            _value: &V,
+            value: &Value,
             current_time: Instant,
             current_duration: Option<Duration>,
             last_modified_at: Instant,
         ) -> Option<Duration> {
-            match (self.tti, self.ttl) {
+            match (self.tti, self.ttl(value)) {
                 // We do not have TTI. Do not modify the current duration.
                 (None, _) => current_duration,
                 // We only have TTI. Return the TTI.
@@ -139,12 +146,12 @@ pub(crate) mod expiry {
         fn expire_after_update(
             &self,
             _key: &K,
-            _value: &V,
+            value: &Value,
             _current_time: Instant,
             current_duration: Option<Duration>,
         ) -> Option<Duration> {
-            if self.ttl.is_some() {
-                self.ttl
+            if let ttl @ Some(_) = self.ttl(value) {
+                ttl
             } else {
                 current_duration
             }
@@ -2,9 +2,10 @@
 
 use super::{GetOrInsertOnce, InitClosureError1, InitClosureError2, InitClosureType};
 use crate::{
-    cache::{self, CacheDriver, Counters, DefaultHasher, Key, Value},
+    cache::{self, CacheDriver, CacheKey, Counters, DefaultHasher, Value},
     config::Config,
     moka::sync::SegmentedCache,
+    op_mix::OpKind,
     parser::TraceEntry,
     report::Report,
     EvictionCounters,
@@ -15,14 +16,14 @@ use std::sync::{
     Arc,
 };
 
-pub(crate) struct MokaSegmentedCache<I> {
+pub(crate) struct MokaSegmentedCache<K, I> {
     config: Arc<Config>,
-    cache: SegmentedCache<Key, Value, DefaultHasher>,
+    cache: SegmentedCache<K, Value, DefaultHasher>,
     insert_once_impl: I,
     eviction_counters: Option<Arc<EvictionCounters>>,
 }
 
-impl<I: Clone> Clone for MokaSegmentedCache<I> {
+impl<K, I: Clone> Clone for MokaSegmentedCache<K, I> {
     fn clone(&self) -> Self {
         Self {
             config: Arc::clone(&self.config),
@@ -33,7 +34,7 @@ impl<I: Clone> Clone for MokaSegmentedCache<I> {
     }
 }
 
-impl MokaSegmentedCache<GetWith> {
+impl<K: CacheKey> MokaSegmentedCache<K, GetWith<K>> {
     pub(crate) fn new(config: &Config, max_cap: u64, init_cap: usize, num_segments: usize) -> Self {
         let (cache, eviction_counters) =
             Self::create_cache(config, max_cap, init_cap, num_segments);
@@ -56,7 +57,7 @@ impl MokaSegmentedCache<GetWith> {
 use entry_api::EntryOrInsertWith;
 
 #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
-impl MokaSegmentedCache<EntryOrInsertWith> {
+impl<K: CacheKey> MokaSegmentedCache<K, EntryOrInsertWith<K>> {
     pub(crate) fn with_entry_api(
         config: &Config,
         max_cap: u64,
@@ -77,14 +78,14 @@ impl MokaSegmentedCache<EntryOrInsertWith> {
     }
 }
 
-impl<I> MokaSegmentedCache<I> {
+impl<K: CacheKey, I> MokaSegmentedCache<K, I> {
     fn create_cache(
         config: &Config,
         max_cap: u64,
         init_cap: usize,
         num_segments: usize,
     ) -> (
-        SegmentedCache<Key, Value, DefaultHasher>,
+        SegmentedCache<K, Value, DefaultHasher>,
         Option<Arc<EvictionCounters>>,
     ) {
         let mut builder = SegmentedCache::builder(num_segments)
@@ -103,27 +104,27 @@ impl<I> MokaSegmentedCache<I> {
             #[cfg(not(any(feature = "moka-v08", feature = "moka-v09", feature = "moka-v010")))]
             {
                 use crate::cache::moka_driver::expiry::MokabenchExpiry;
-                let expiry = MokabenchExpiry::new(config.ttl, config.tti);
+                let expiry = MokabenchExpiry::new(config.replay_ttl(), config.replay_tti());
                 builder = builder.expire_after(expiry);
             }
         }
 
-        if let Some(ttl) = config.ttl {
+        if let Some(ttl) = config.replay_ttl() {
             if !config.per_key_expiration {
                 builder = builder.time_to_live(ttl);
             }
         }
-        if let Some(tti) = config.tti {
+        if let Some(tti) = config.replay_tti() {
             if !config.per_key_expiration {
                 builder = builder.time_to_idle(tti)
             }
         }
 
-        if config.invalidate_entries_if {
+        if config.injects(OpKind::InvalidateEntriesIf) {
             builder = builder.support_invalidation_closures();
         }
         if config.size_aware {
-            builder = builder.weigher(|_k, (s, _v)| *s);
+            builder = builder.weigher(|_k, v: &Value| v.weight);
         }
 
         let cache;
@@ -170,31 +171,30 @@ impl<I> MokaSegmentedCache<I> {
         (cache, eviction_counters)
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = cache::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = cache::make_value(&self.config, entry, block, req_id);
         cache::sleep_thread_for_insertion(&self.config);
         self.cache.insert(key, value);
     }
 }
 
-impl<I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<I> {
+impl<K: CacheKey, I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<K, I> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -206,12 +206,10 @@ impl<I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<I> {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -219,7 +217,7 @@ impl<I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<I> {
 
     fn invalidate(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
-            self.cache.invalidate(&block);
+            self.cache.invalidate(&K::new(&self.config, entry, block));
         }
     }
 
@@ -230,7 +228,7 @@ impl<I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<I> {
     fn invalidate_entries_if(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
             self.cache
-                .invalidate_entries_if(move |_k, (_s, v)| v[0] == (block % 256) as u8)
+                .invalidate_entries_if(move |_k, v| v.data[0] == (block % 256) as u8)
                 .expect("invalidate_entries_if failed");
         }
     }
@@ -240,7 +238,7 @@ impl<I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<I> {
         for _kv in &self.cache {
             count += 1;
 
-            if count % 500 == 0 {
+            if count.is_multiple_of(500) {
                 std::thread::yield_now();
             }
         }
@@ -255,23 +253,25 @@ impl<I: GetOrInsertOnce> CacheDriver<TraceEntry> for MokaSegmentedCache<I> {
 // GetWith (implements GetOrInsertOnce)
 //
 #[derive(Clone)]
-pub(crate) struct GetWith {
-    cache: SegmentedCache<Key, Value, DefaultHasher>,
+pub(crate) struct GetWith<K> {
+    cache: SegmentedCache<K, Value, DefaultHasher>,
     config: Arc<Config>,
 }
 
-impl GetOrInsertOnce for GetWith {
+impl<K: CacheKey> GetOrInsertOnce for GetWith<K> {
     fn get_or_insert_once(&self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
         let is_inserted = Arc::new(AtomicBool::default());
 
-        for block in entry.range() {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
             {
+                let key = K::new(&self.config, entry, block);
                 let is_inserted2 = Arc::clone(&is_inserted);
                 match InitClosureType::select(block) {
-                    InitClosureType::GetOrInsert => self.get_with(block, req_id, is_inserted2),
-                    ty => self.try_get_with(ty, block, req_id, is_inserted2),
+                    InitClosureType::GetOrInsert => {
+                        self.get_with(entry, key, block, req_id, is_inserted2)
+                    }
+                    ty => self.try_get_with(ty, entry, key, block, req_id, is_inserted2),
                 }
             }
 
@@ -282,26 +282,34 @@ impl GetOrInsertOnce for GetWith {
             } else {
                 counters.read_hit();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
     }
 }
 
-impl GetWith {
-    fn get_with(&self, key: usize, req_id: usize, is_inserted: Arc<AtomicBool>) {
+impl<K: CacheKey> GetWith<K> {
+    fn get_with(
+        &self,
+        entry: &TraceEntry,
+        key: K,
+        block: usize,
+        req_id: usize,
+        is_inserted: Arc<AtomicBool>,
+    ) {
         self.cache.get_with(key, || {
             cache::sleep_thread_for_insertion(&self.config);
             is_inserted.store(true, Ordering::Release);
-            cache::make_value(&self.config, key, req_id)
+            cache::make_value(&self.config, entry, block, req_id)
         });
     }
 
     fn try_get_with(
         &self,
         ty: InitClosureType,
-        key: usize,
+        entry: &TraceEntry,
+        key: K,
+        block: usize,
         req_id: usize,
         is_inserted: Arc<AtomicBool>,
     ) {
@@ -311,7 +319,8 @@ impl GetWith {
                 .try_get_with(key, || {
                     cache::sleep_thread_for_insertion(&self.config);
                     is_inserted.store(true, Ordering::Release);
-                    Ok(cache::make_value(&self.config, key, req_id)) as Result<_, InitClosureError1>
+                    Ok(cache::make_value(&self.config, entry, block, req_id))
+                        as Result<_, InitClosureError1>
                 })
                 .is_ok(),
             InitClosureType::GetOrTyyInsertWithError2 => self
@@ -319,7 +328,8 @@ impl GetWith {
                 .try_get_with(key, || {
                     cache::sleep_thread_for_insertion(&self.config);
                     is_inserted.store(true, Ordering::Release);
-                    Ok(cache::make_value(&self.config, key, req_id)) as Result<_, InitClosureError2>
+                    Ok(cache::make_value(&self.config, entry, block, req_id))
+                        as Result<_, InitClosureError2>
                 })
                 .is_ok(),
             _ => unreachable!(),
@@ -335,29 +345,31 @@ mod entry_api {
     use super::*;
 
     #[derive(Clone)]
-    pub(crate) struct EntryOrInsertWith {
-        cache: SegmentedCache<Key, Value, DefaultHasher>,
+    pub(crate) struct EntryOrInsertWith<K> {
+        cache: SegmentedCache<K, Value, DefaultHasher>,
         config: Arc<Config>,
     }
 
-    impl EntryOrInsertWith {
+    impl<K: CacheKey> EntryOrInsertWith<K> {
         pub(crate) fn new(
-            cache: SegmentedCache<Key, Value, DefaultHasher>,
+            cache: SegmentedCache<K, Value, DefaultHasher>,
             config: Arc<Config>,
         ) -> Self {
             Self { cache, config }
         }
     }
 
-    impl GetOrInsertOnce for EntryOrInsertWith {
+    impl<K: CacheKey> GetOrInsertOnce for EntryOrInsertWith<K> {
         fn get_or_insert_once(&self, entry: &TraceEntry, report: &mut Report) {
             let mut counters = Counters::default();
-            let mut req_id = entry.line_number();
 
-            for block in entry.range() {
+            for (block, req_id) in entry.range().zip(entry.line_number()..) {
+                let key = K::new(&self.config, entry, block);
                 let is_inserted = match InitClosureType::select(block) {
-                    InitClosureType::GetOrInsert => self.entry_or_insert_with(block, req_id),
-                    ty => self.entry_or_try_insert_with(ty, block, req_id),
+                    InitClosureType::GetOrInsert => {
+                        self.entry_or_insert_with(entry, key, block, req_id)
+                    }
+                    ty => self.entry_or_try_insert_with(ty, entry, key, block, req_id),
                 };
 
                 if is_inserted {
@@ -366,32 +378,44 @@ mod entry_api {
                 } else {
                     counters.read_hit();
                 }
-                req_id += 1;
             }
 
             counters.add_to_report(report);
         }
     }
 
-    impl EntryOrInsertWith {
-        fn entry_or_insert_with(&self, key: usize, req_id: usize) -> bool {
+    impl<K: CacheKey> EntryOrInsertWith<K> {
+        fn entry_or_insert_with(
+            &self,
+            entry: &TraceEntry,
+            key: K,
+            block: usize,
+            req_id: usize,
+        ) -> bool {
             self.cache
                 .entry(key)
                 .or_insert_with(|| {
                     cache::sleep_thread_for_insertion(&self.config);
-                    cache::make_value(&self.config, key, req_id)
+                    cache::make_value(&self.config, entry, block, req_id)
                 })
                 .is_fresh()
         }
 
-        fn entry_or_try_insert_with(&self, ty: InitClosureType, key: usize, req_id: usize) -> bool {
+        fn entry_or_try_insert_with(
+            &self,
+            ty: InitClosureType,
+            entry: &TraceEntry,
+            key: K,
+            block: usize,
+            req_id: usize,
+        ) -> bool {
             match ty {
                 InitClosureType::GetOrTryInsertWithError1 => self
                     .cache
                     .entry(key)
                     .or_try_insert_with(|| {
                         cache::sleep_thread_for_insertion(&self.config);
-                        Ok(cache::make_value(&self.config, key, req_id))
+                        Ok(cache::make_value(&self.config, entry, block, req_id))
                             as Result<_, InitClosureError1>
                     })
                     .unwrap()
@@ -401,7 +425,7 @@ mod entry_api {
                     .entry(key)
                     .or_try_insert_with(|| {
                         cache::sleep_thread_for_insertion(&self.config);
-                        Ok(cache::make_value(&self.config, key, req_id))
+                        Ok(cache::make_value(&self.config, entry, block, req_id))
                             as Result<_, InitClosureError2>
                     })
                     .unwrap()
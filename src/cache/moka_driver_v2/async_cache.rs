@@ -1,12 +1,13 @@
 //! Driver for `moka::future::Cache` v0.12.0 or later.
 
 use super::{AsyncGetOrInsertOnce, InitClosureError1, InitClosureError2, InitClosureType};
-use crate::cache::{Key, Value};
+use crate::cache::{CacheKey, Value};
 use crate::moka::future::Cache;
 use crate::{
     async_rt_helper as rt,
     cache::{self, AsyncCacheDriver, Counters, DefaultHasher},
     config::Config,
+    op_mix::OpKind,
     parser::TraceEntry,
     report::Report,
     EvictionCounters,
@@ -18,14 +19,14 @@ use std::sync::{
     Arc,
 };
 
-pub struct MokaAsyncCache<I> {
+pub struct MokaAsyncCache<K, I> {
     config: Arc<Config>,
-    cache: Cache<Key, Value, DefaultHasher>,
+    cache: Cache<K, Value, DefaultHasher>,
     insert_once_impl: I,
     eviction_counters: Option<Arc<EvictionCounters>>,
 }
 
-impl<I: Clone> Clone for MokaAsyncCache<I> {
+impl<K, I: Clone> Clone for MokaAsyncCache<K, I> {
     fn clone(&self) -> Self {
         Self {
             config: Arc::clone(&self.config),
@@ -36,7 +37,7 @@ impl<I: Clone> Clone for MokaAsyncCache<I> {
     }
 }
 
-impl MokaAsyncCache<GetWith> {
+impl<K: CacheKey> MokaAsyncCache<K, GetWith<K>> {
     pub(crate) fn new(config: &Config, max_cap: u64, init_cap: usize) -> Self {
         let (cache, eviction_counters) = Self::create_cache(config, max_cap, init_cap);
         let config = Arc::new(config.clone());
@@ -56,7 +57,7 @@ impl MokaAsyncCache<GetWith> {
 
 use entry_api::EntryOrInsertWith;
 
-impl MokaAsyncCache<EntryOrInsertWith> {
+impl<K: CacheKey> MokaAsyncCache<K, EntryOrInsertWith<K>> {
     pub(crate) fn with_entry_api(config: &Config, max_cap: u64, init_cap: usize) -> Self {
         let (cache, eviction_counters) = Self::create_cache(config, max_cap, init_cap);
         let config = Arc::new(config.clone());
@@ -71,13 +72,13 @@ impl MokaAsyncCache<EntryOrInsertWith> {
     }
 }
 
-impl<I> MokaAsyncCache<I> {
+impl<K: CacheKey, I> MokaAsyncCache<K, I> {
     fn create_cache(
         config: &Config,
         max_cap: u64,
         init_cap: usize,
     ) -> (
-        Cache<Key, Value, DefaultHasher>,
+        Cache<K, Value, DefaultHasher>,
         Option<Arc<EvictionCounters>>,
     ) {
         let mut builder = Cache::builder()
@@ -86,26 +87,26 @@ impl<I> MokaAsyncCache<I> {
 
         if config.per_key_expiration {
             use crate::cache::moka_driver::expiry::MokabenchExpiry;
-            let expiry = MokabenchExpiry::new(config.ttl, config.tti);
+            let expiry = MokabenchExpiry::new(config.replay_ttl(), config.replay_tti());
             builder = builder.expire_after(expiry);
         }
 
-        if let Some(ttl) = config.ttl {
+        if let Some(ttl) = config.replay_ttl() {
             if !config.per_key_expiration {
                 builder = builder.time_to_live(ttl);
             }
         }
-        if let Some(tti) = config.tti {
+        if let Some(tti) = config.replay_tti() {
             if !config.per_key_expiration {
                 builder = builder.time_to_idle(tti)
             }
         }
 
-        if config.invalidate_entries_if {
+        if config.injects(OpKind::InvalidateEntriesIf) {
             builder = builder.support_invalidation_closures();
         }
         if config.size_aware {
-            builder = builder.weigher(|_k, (s, _v)| *s);
+            builder = builder.weigher(|_k, v: &Value| v.weight);
         }
 
         let eviction_counters;
@@ -127,35 +128,34 @@ impl<I> MokaAsyncCache<I> {
         (cache, eviction_counters)
     }
 
-    async fn get(&self, key: usize) -> bool {
-        self.cache.get(&key).await.is_some()
+    async fn get(&self, key: &K) -> bool {
+        self.cache.get(key).await.is_some()
     }
 
-    async fn insert(&self, key: usize, req_id: usize) {
-        let value = cache::make_value(&self.config, key, req_id);
+    async fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = cache::make_value(&self.config, entry, block, req_id);
         cache::sleep_task_for_insertion(&self.config).await;
         self.cache.insert(key, value).await;
     }
 }
 
 #[async_trait]
-impl<I> AsyncCacheDriver<TraceEntry> for MokaAsyncCache<I>
+impl<K: CacheKey, I> AsyncCacheDriver<TraceEntry> for MokaAsyncCache<K, I>
 where
     I: AsyncGetOrInsertOnce + Send + Sync,
 {
     async fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(block).await {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key).await {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id).await;
+                self.insert(entry, key, block, req_id).await;
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -169,12 +169,11 @@ where
 
     async fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id).await;
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id)
+                .await;
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -182,7 +181,9 @@ where
 
     async fn invalidate(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
-            self.cache.invalidate(&block).await;
+            self.cache
+                .invalidate(&K::new(&self.config, entry, block))
+                .await;
         }
     }
 
@@ -193,7 +194,7 @@ where
     fn invalidate_entries_if(&mut self, entry: &TraceEntry) {
         for block in entry.range() {
             self.cache
-                .invalidate_entries_if(move |_k, (_s, v)| v[0] == (block % 256) as u8)
+                .invalidate_entries_if(move |_k, v| v.data[0] == (block % 256) as u8)
                 .expect("invalidate_entries_if failed");
         }
     }
@@ -203,7 +204,7 @@ where
         for _kv in &self.cache {
             count += 1;
 
-            if count % 500 == 0 {
+            if count.is_multiple_of(500) {
                 rt::yield_now().await;
             }
         }
@@ -218,26 +219,29 @@ where
 // GetWith (implements GetOrInsertOnce)
 //
 #[derive(Clone)]
-pub(crate) struct GetWith {
-    cache: Cache<Key, Value, DefaultHasher>,
+pub(crate) struct GetWith<K> {
+    cache: Cache<K, Value, DefaultHasher>,
     config: Arc<Config>,
 }
 
 #[async_trait]
-impl AsyncGetOrInsertOnce for GetWith {
+impl<K: CacheKey> AsyncGetOrInsertOnce for GetWith<K> {
     async fn get_or_insert_once(&self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
         let is_inserted = Arc::new(AtomicBool::default());
 
-        for block in entry.range() {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
             {
+                let key = K::new(&self.config, entry, block);
                 let is_inserted2 = Arc::clone(&is_inserted);
                 match InitClosureType::select(block) {
                     InitClosureType::GetOrInsert => {
-                        self.get_with(block, req_id, is_inserted2).await
+                        self.get_with(entry, key, block, req_id, is_inserted2).await
+                    }
+                    ty => {
+                        self.try_get_with(ty, entry, key, block, req_id, is_inserted2)
+                            .await
                     }
-                    ty => self.try_get_with(ty, block, req_id, is_inserted2).await,
                 }
             }
 
@@ -248,20 +252,26 @@ impl AsyncGetOrInsertOnce for GetWith {
             } else {
                 counters.read_hit();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
     }
 }
 
-impl GetWith {
-    async fn get_with(&self, key: usize, req_id: usize, is_inserted: Arc<AtomicBool>) {
+impl<K: CacheKey> GetWith<K> {
+    async fn get_with(
+        &self,
+        entry: &TraceEntry,
+        key: K,
+        block: usize,
+        req_id: usize,
+        is_inserted: Arc<AtomicBool>,
+    ) {
         self.cache
             .get_with(key, async {
                 cache::sleep_task_for_insertion(&self.config).await;
                 is_inserted.store(true, Ordering::Release);
-                cache::make_value(&self.config, key, req_id)
+                cache::make_value(&self.config, entry, block, req_id)
             })
             .await;
     }
@@ -269,7 +279,9 @@ impl GetWith {
     async fn try_get_with(
         &self,
         ty: InitClosureType,
-        key: usize,
+        entry: &TraceEntry,
+        key: K,
+        block: usize,
         req_id: usize,
         is_inserted: Arc<AtomicBool>,
     ) {
@@ -279,7 +291,8 @@ impl GetWith {
                 .try_get_with(key, async {
                     cache::sleep_task_for_insertion(&self.config).await;
                     is_inserted.store(true, Ordering::Release);
-                    Ok(cache::make_value(&self.config, key, req_id)) as Result<_, InitClosureError1>
+                    Ok(cache::make_value(&self.config, entry, block, req_id))
+                        as Result<_, InitClosureError1>
                 })
                 .await
                 .is_ok(),
@@ -288,7 +301,8 @@ impl GetWith {
                 .try_get_with(key, async {
                     cache::sleep_task_for_insertion(&self.config).await;
                     is_inserted.store(true, Ordering::Release);
-                    Ok(cache::make_value(&self.config, key, req_id)) as Result<_, InitClosureError2>
+                    Ok(cache::make_value(&self.config, entry, block, req_id))
+                        as Result<_, InitClosureError2>
                 })
                 .await
                 .is_ok(),
@@ -304,27 +318,32 @@ mod entry_api {
     use super::*;
 
     #[derive(Clone)]
-    pub(crate) struct EntryOrInsertWith {
-        cache: Cache<Key, Value, DefaultHasher>,
+    pub(crate) struct EntryOrInsertWith<K> {
+        cache: Cache<K, Value, DefaultHasher>,
         config: Arc<Config>,
     }
 
-    impl EntryOrInsertWith {
-        pub(crate) fn new(cache: Cache<Key, Value, DefaultHasher>, config: Arc<Config>) -> Self {
+    impl<K: CacheKey> EntryOrInsertWith<K> {
+        pub(crate) fn new(cache: Cache<K, Value, DefaultHasher>, config: Arc<Config>) -> Self {
             Self { cache, config }
         }
     }
 
     #[async_trait]
-    impl AsyncGetOrInsertOnce for EntryOrInsertWith {
+    impl<K: CacheKey> AsyncGetOrInsertOnce for EntryOrInsertWith<K> {
         async fn get_or_insert_once(&self, entry: &TraceEntry, report: &mut Report) {
             let mut counters = Counters::default();
-            let mut req_id = entry.line_number();
 
-            for block in entry.range() {
+            for (block, req_id) in entry.range().zip(entry.line_number()..) {
+                let key = K::new(&self.config, entry, block);
                 let is_inserted = match InitClosureType::select(block) {
-                    InitClosureType::GetOrInsert => self.entry_or_insert_with(block, req_id).await,
-                    ty => self.entry_or_try_insert_with(ty, block, req_id).await,
+                    InitClosureType::GetOrInsert => {
+                        self.entry_or_insert_with(entry, key, block, req_id).await
+                    }
+                    ty => {
+                        self.entry_or_try_insert_with(ty, entry, key, block, req_id)
+                            .await
+                    }
                 };
 
                 if is_inserted {
@@ -333,20 +352,25 @@ mod entry_api {
                 } else {
                     counters.read_hit();
                 }
-                req_id += 1;
             }
 
             counters.add_to_report(report);
         }
     }
 
-    impl EntryOrInsertWith {
-        async fn entry_or_insert_with(&self, key: usize, req_id: usize) -> bool {
+    impl<K: CacheKey> EntryOrInsertWith<K> {
+        async fn entry_or_insert_with(
+            &self,
+            entry: &TraceEntry,
+            key: K,
+            block: usize,
+            req_id: usize,
+        ) -> bool {
             self.cache
                 .entry(key)
                 .or_insert_with(async {
                     cache::sleep_task_for_insertion(&self.config).await;
-                    cache::make_value(&self.config, key, req_id)
+                    cache::make_value(&self.config, entry, block, req_id)
                 })
                 .await
                 .is_fresh()
@@ -355,7 +379,9 @@ mod entry_api {
         async fn entry_or_try_insert_with(
             &self,
             ty: InitClosureType,
-            key: usize,
+            entry: &TraceEntry,
+            key: K,
+            block: usize,
             req_id: usize,
         ) -> bool {
             match ty {
@@ -364,7 +390,7 @@ mod entry_api {
                     .entry(key)
                     .or_try_insert_with(async {
                         cache::sleep_task_for_insertion(&self.config).await;
-                        Ok(cache::make_value(&self.config, key, req_id))
+                        Ok(cache::make_value(&self.config, entry, block, req_id))
                             as Result<_, InitClosureError1>
                     })
                     .await
@@ -375,7 +401,7 @@ mod entry_api {
                     .entry(key)
                     .or_try_insert_with(async {
                         cache::sleep_task_for_insertion(&self.config).await;
-                        Ok(cache::make_value(&self.config, key, req_id))
+                        Ok(cache::make_value(&self.config, entry, block, req_id))
                             as Result<_, InitClosureError2>
                     })
                     .await
@@ -1,32 +1,32 @@
-use super::{CacheDriver, Counters, DefaultHasher, Key, Value};
+use super::{CacheDriver, CacheKey, Counters, DefaultHasher, Value};
 use crate::{config::Config, parser::TraceEntry, report::Report};
 
 use ::quick_cache::OptionsBuilder;
 
 use std::sync::Arc;
 
-type QuickCacheImpl = ::quick_cache::sync::Cache<Key, Value, CustomWeighter, DefaultHasher>;
+type QuickCacheImpl<K> = ::quick_cache::sync::Cache<K, Value, CustomWeighter, DefaultHasher>;
 
 #[derive(Clone)]
-pub struct QuickCache {
+pub struct QuickCache<K> {
     config: Arc<Config>,
-    cache: Arc<QuickCacheImpl>,
+    cache: Arc<QuickCacheImpl<K>>,
 }
 
 #[derive(Clone)]
 struct CustomWeighter(bool);
 
-impl ::quick_cache::Weighter<usize, (u32, Arc<[u8]>)> for CustomWeighter {
-    fn weight(&self, _key: &usize, val: &(u32, Arc<[u8]>)) -> u32 {
+impl<K> ::quick_cache::Weighter<K, Value> for CustomWeighter {
+    fn weight(&self, _key: &K, val: &Value) -> u32 {
         if self.0 {
-            val.0
+            val.weight
         } else {
             1
         }
     }
 }
 
-impl QuickCache {
+impl<K: CacheKey> QuickCache<K> {
     pub fn new(config: &Config, estimated_items_capacity: usize, capacity: u64) -> Self {
         if let Some(_ttl) = config.ttl {
             todo!()
@@ -53,31 +53,30 @@ impl QuickCache {
         }
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = super::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = super::make_value(&self.config, entry, block, req_id);
         super::sleep_thread_for_insertion(&self.config);
         self.cache.insert(key, value);
     }
 }
 
-impl CacheDriver<TraceEntry> for QuickCache {
+impl<K: CacheKey> CacheDriver<TraceEntry> for QuickCache<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -89,14 +88,18 @@ impl CacheDriver<TraceEntry> for QuickCache {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
     }
+
+    fn invalidate(&mut self, entry: &TraceEntry) {
+        for block in entry.range() {
+            self.cache.remove(&K::new(&self.config, entry, block));
+        }
+    }
 }
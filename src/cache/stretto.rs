@@ -1,17 +1,17 @@
 use std::sync::Arc;
 
-use super::{CacheDriver, Counters, DefaultHasher, Key, Value};
+use super::{CacheDriver, CacheKey, Counters, DefaultHasher, Value};
 use crate::{config::Config, parser::TraceEntry, report::Report};
 
 #[derive(Clone)]
-pub struct StrettoCache {
+pub struct StrettoCache<K: CacheKey> {
     config: Arc<Config>,
     // https://rust-lang.github.io/rust-clippy/master/index.html#type_complexity
     #[allow(clippy::type_complexity)]
     cache: stretto::Cache<
-        Key,
+        K,
         Value,
-        stretto::DefaultKeyBuilder<Key>,
+        stretto::DefaultKeyBuilder<K>,
         stretto::DefaultCoster<Value>,
         stretto::DefaultUpdateValidator<Value>,
         stretto::DefaultCacheCallback<Value>,
@@ -19,7 +19,7 @@ pub struct StrettoCache {
     >,
 }
 
-impl StrettoCache {
+impl<K: CacheKey> StrettoCache<K> {
     pub fn new(config: &Config, capacity: usize) -> Self {
         if let Some(_ttl) = config.ttl {
             todo!()
@@ -40,31 +40,30 @@ impl StrettoCache {
         }
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = super::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = super::make_value(&self.config, entry, block, req_id);
         super::sleep_thread_for_insertion(&self.config);
         self.cache.insert(key, value, 1);
     }
 }
 
-impl CacheDriver<TraceEntry> for StrettoCache {
+impl<K: CacheKey> CacheDriver<TraceEntry> for StrettoCache<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -76,14 +75,18 @@ impl CacheDriver<TraceEntry> for StrettoCache {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
     }
+
+    fn invalidate(&mut self, entry: &TraceEntry) {
+        for block in entry.range() {
+            self.cache.remove(&K::new(&self.config, entry, block));
+        }
+    }
 }
@@ -1,15 +1,15 @@
 use std::sync::Arc;
 
-use super::{CacheDriver, Counters, Key, Value};
+use super::{CacheDriver, CacheKey, Counters, Value};
 use crate::{config::Config, parser::TraceEntry, report::Report};
 
 #[derive(Clone)]
-pub struct TinyUfoCache {
+pub struct TinyUfoCache<K> {
     config: Arc<Config>,
-    cache: Arc<tinyufo::TinyUfo<Key, Value>>,
+    cache: Arc<tinyufo::TinyUfo<K, Value>>,
 }
 
-impl TinyUfoCache {
+impl<K: CacheKey> TinyUfoCache<K> {
     pub fn new(config: &Config, capacity: usize) -> Self {
         if let Some(_ttl) = config.ttl {
             todo!()
@@ -28,31 +28,30 @@ impl TinyUfoCache {
         }
     }
 
-    fn get(&self, key: &usize) -> bool {
+    fn get(&self, key: &K) -> bool {
         self.cache.get(key).is_some()
     }
 
-    fn insert(&self, key: usize, req_id: usize) {
-        let value = super::make_value(&self.config, key, req_id);
+    fn insert(&self, entry: &TraceEntry, key: K, block: usize, req_id: usize) {
+        let value = super::make_value(&self.config, entry, block, req_id);
         super::sleep_thread_for_insertion(&self.config);
         self.cache.put(key, value, 1);
     }
 }
 
-impl CacheDriver<TraceEntry> for TinyUfoCache {
+impl<K: CacheKey> CacheDriver<TraceEntry> for TinyUfoCache<K> {
     fn get_or_insert(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            if self.get(&block) {
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            let key = K::new(&self.config, entry, block);
+            if self.get(&key) {
                 counters.read_hit();
             } else {
-                self.insert(block, req_id);
+                self.insert(entry, key, block, req_id);
                 counters.inserted();
                 counters.read_missed();
             }
-            req_id += 1;
         }
 
         counters.add_to_report(report);
@@ -64,12 +63,10 @@ impl CacheDriver<TraceEntry> for TinyUfoCache {
 
     fn update(&mut self, entry: &TraceEntry, report: &mut Report) {
         let mut counters = Counters::default();
-        let mut req_id = entry.line_number();
 
-        for block in entry.range() {
-            self.insert(block, req_id);
+        for (block, req_id) in entry.range().zip(entry.line_number()..) {
+            self.insert(entry, K::new(&self.config, entry, block), block, req_id);
             counters.inserted();
-            req_id += 1;
         }
 
         counters.add_to_report(report);
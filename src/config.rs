@@ -1,29 +1,63 @@
 use std::time::Duration;
 
-use crate::trace_file::TraceFile;
+use crate::{
+    op_mix::{OpKind, OpMix, OpRate, OpSchedule},
+    trace_file::TraceDescriptor,
+};
 
 #[derive(Clone, Debug)]
 pub struct Config {
-    pub trace_file: TraceFile,
+    pub trace_file: TraceDescriptor,
+    /// The traces of the tenants sharing the cache. Empty unless several traces
+    /// are interleaved, in which case `trace_file` describes them as a whole.
+    pub tenants: Vec<TraceDescriptor>,
+    pub tenant_weights: Vec<u32>,
+    pub interleave: Interleave,
     pub ttl: Option<Duration>,
     pub tti: Option<Duration>,
     pub num_clients: Option<Vec<u16>>,
     pub repeat: Option<u16>,
+    pub skip_requests: usize,
+    pub max_requests: Option<usize>,
+    pub from: Option<Duration>,
+    pub until: Option<Duration>,
+    pub sampling_rate: Option<f64>,
+    pub streaming: bool,
+    pub parse_once: bool,
+    pub num_producers: u16,
+    pub queue_size: usize,
+    pub time_scale: Option<f64>,
     pub insertion_delay: Option<Duration>,
     pub insert_once: bool,
     pub invalidate: bool,
     pub invalidate_all: bool,
     pub invalidate_entries_if: bool,
     pub iterate: bool,
+    /// The operations to inject into the requests. If `None`, they are given by
+    /// `insert_once`, `invalidate`, `invalidate_all`, `invalidate_entries_if`,
+    /// `iterate` and `size_aware` (see `op_mix`).
+    pub op_mix_spec: Option<OpMix>,
+    pub op_schedule: OpSchedule,
     pub eviction_listener: RemovalNotificationMode,
     pub size_aware: bool,
+    pub key_type: KeyType,
+    pub key_transform: KeyTransform,
+    pub key_len: Option<usize>,
     pub entry_api: bool,          // Since Moka v0.10
     pub per_key_expiration: bool, // Since Moka v0.11
 }
 
 impl Config {
+    /// The mean weight of the entries when the trace does not record value sizes.
+    /// The weights are then in `[4 .. 2^16)`, so their mean is about 2^15.
+    const DEFAULT_MEAN_WEIGHT: u64 = 2u64.pow(15);
+
+    /// The default number of command batches buffered in the bounded channel in
+    /// the streaming mode.
+    pub const DEFAULT_QUEUE_SIZE: usize = 1_024;
+
     pub fn new(
-        trace_file: TraceFile,
+        trace_file: TraceDescriptor,
         ttl_secs: Option<u64>,
         tti_secs: Option<u64>,
         num_clients: Option<Vec<u16>>,
@@ -32,23 +66,158 @@ impl Config {
     ) -> Self {
         Self {
             trace_file,
+            tenants: Vec::new(),
+            tenant_weights: Vec::new(),
+            interleave: Interleave::Weight,
             ttl: ttl_secs.map(Duration::from_secs),
             tti: tti_secs.map(Duration::from_secs),
             num_clients,
             repeat,
+            skip_requests: 0,
+            max_requests: None,
+            from: None,
+            until: None,
+            sampling_rate: None,
+            streaming: false,
+            parse_once: false,
+            num_producers: 1,
+            queue_size: Self::DEFAULT_QUEUE_SIZE,
+            time_scale: None,
             insertion_delay: insertion_delay_micros.map(Duration::from_micros),
             insert_once: false,
             invalidate: false,
             invalidate_all: false,
             invalidate_entries_if: false,
             iterate: false,
+            op_mix_spec: None,
+            op_schedule: OpSchedule::Periodic,
             eviction_listener: RemovalNotificationMode::None,
             size_aware: false,
+            key_type: KeyType::Integer,
+            key_transform: KeyTransform::Identity,
+            key_len: None,
             entry_api: false,
             per_key_expiration: false,
         }
     }
 
+    /// Interleaves the traces of the tenants into one stream of requests. The
+    /// `weights` are used with `Interleave::Weight`; if empty, all tenants have the
+    /// same weight.
+    pub fn set_tenants(
+        &mut self,
+        tenants: Vec<TraceDescriptor>,
+        weights: Vec<u32>,
+        interleave: Interleave,
+    ) {
+        self.tenant_weights = if weights.is_empty() {
+            vec![1; tenants.len()]
+        } else {
+            weights
+        };
+        self.tenants = tenants;
+        self.interleave = interleave;
+    }
+
+    pub fn is_multi_tenant(&self) -> bool {
+        !self.tenants.is_empty()
+    }
+
+    /// Returns the traces to run: the traces of the tenants, or `trace_file`.
+    pub fn traces(&self) -> impl Iterator<Item = &TraceDescriptor> {
+        if self.is_multi_tenant() {
+            self.tenants.iter()
+        } else {
+            std::slice::from_ref(&self.trace_file).iter()
+        }
+    }
+
+    /// Parses the traces to run in advance and keeps their entries in memory, so
+    /// that they are not read again for every capacity and number of clients.
+    pub fn parse_traces(&mut self) -> anyhow::Result<()> {
+        if self.is_multi_tenant() {
+            self.tenants
+                .iter_mut()
+                .try_for_each(TraceDescriptor::parse_entries)
+        } else {
+            self.trace_file.parse_entries()
+        }
+    }
+
+    /// Skips the first `v` requests of the trace (after `from`).
+    pub fn set_skip_requests(&mut self, v: usize) {
+        self.skip_requests = v;
+    }
+
+    /// Runs at most `v` requests of the trace (after skipping).
+    pub fn set_max_requests(&mut self, v: Option<usize>) {
+        self.max_requests = v;
+    }
+
+    /// Selects the requests with the timestamps in `from..until`, which are
+    /// relative to the timestamp of the first request of the trace.
+    pub fn set_time_window(&mut self, from: Option<Duration>, until: Option<Duration>) {
+        self.from = from;
+        self.until = until;
+    }
+
+    /// Samples the keys of the trace at the rate in `(0.0, 1.0]`, and scales down
+    /// the capacities by the same rate.
+    pub fn set_sampling_rate(&mut self, v: Option<f64>) {
+        self.sampling_rate = v;
+    }
+
+    /// Returns the capacity scaled down by the sampling rate.
+    pub fn sampled_capacity(&self, capacity: usize) -> usize {
+        match self.sampling_rate {
+            Some(rate) => ((capacity as f64 * rate).round() as usize).max(1),
+            None => capacity,
+        }
+    }
+
+    /// Returns `true` if only a part of the trace is selected.
+    pub fn is_sliced(&self) -> bool {
+        self.skip_requests > 0
+            || self.max_requests.is_some()
+            || self.from.is_some()
+            || self.until.is_some()
+    }
+
+    pub fn set_streaming(&mut self, v: bool) {
+        self.streaming = v;
+    }
+
+    pub fn set_parse_once(&mut self, v: bool) {
+        self.parse_once = v;
+    }
+
+    pub fn set_num_producers(&mut self, v: u16) {
+        self.num_producers = v;
+    }
+
+    pub fn set_queue_size(&mut self, v: usize) {
+        self.queue_size = v;
+    }
+
+    /// Replays the trace by the timestamps recorded in it, `v` times faster than
+    /// the original.
+    pub fn set_time_scale(&mut self, v: Option<f64>) {
+        self.time_scale = v;
+    }
+
+    /// Returns an error if any of the traces to run does not record timestamps,
+    /// which the option requires.
+    pub(crate) fn ensure_timestamps(&self, option: &str) -> anyhow::Result<()> {
+        if let Some(trace) = self.traces().find(|t| !t.has_timestamps()) {
+            anyhow::bail!(
+                r#"{} cannot be used with trace "{}" as it has no timestamps"#,
+                option,
+                trace.name
+            );
+        }
+        Ok(())
+    }
+
     pub fn set_insert_once(&mut self, v: bool) {
         self.insert_once = v;
     }
@@ -69,6 +238,51 @@ impl Config {
         self.iterate = v;
     }
 
+    /// Injects the operations of the mix into the requests instead of the ones
+    /// enabled by `set_insert_once`, `set_invalidate` etc.
+    pub fn set_op_mix(&mut self, v: Option<OpMix>) {
+        self.op_mix_spec = v;
+    }
+
+    pub fn set_op_schedule(&mut self, v: OpSchedule) {
+        self.op_schedule = v;
+    }
+
+    /// Returns the operations to inject into the requests: the mix set by
+    /// `set_op_mix`, or the fixed periods of the operations enabled by
+    /// `set_insert_once`, `set_invalidate` etc.
+    pub fn op_mix(&self) -> OpMix {
+        if let Some(mix) = &self.op_mix_spec {
+            return mix.clone();
+        }
+        let mut mix = OpMix::new();
+        let mut enable = |enabled: bool, kind, period| {
+            mix.set(kind, enabled.then_some(OpRate::Period(period)));
+        };
+        enable(self.invalidate_all, OpKind::InvalidateAll, 100_000);
+        enable(
+            self.invalidate_entries_if,
+            OpKind::InvalidateEntriesIf,
+            5_000,
+        );
+        enable(self.size_aware, OpKind::Update, 11);
+        enable(self.invalidate, OpKind::Invalidate, 8);
+        enable(self.insert_once, OpKind::InsertOnce, 3);
+        enable(self.iterate, OpKind::Iterate, 50_000);
+        mix
+    }
+
+    /// Returns `true` if the kind of operation is injected into the requests,
+    /// including the ones of any phase of a phased workload.
+    pub fn injects(&self, kind: OpKind) -> bool {
+        self.op_mix().contains(kind)
+            || self
+                .trace_file
+                .phases()
+                .iter()
+                .any(|phase| phase.op_mix.as_ref().is_some_and(|mix| mix.contains(kind)))
+    }
+
     pub fn set_eviction_listener(&mut self, v: RemovalNotificationMode) {
         self.eviction_listener = v;
     }
@@ -77,6 +291,18 @@ impl Config {
         self.size_aware = v;
     }
 
+    pub fn set_key_type(&mut self, v: KeyType) {
+        self.key_type = v;
+    }
+
+    pub fn set_key_transform(&mut self, v: KeyTransform) {
+        self.key_transform = v;
+    }
+
+    pub fn set_key_len(&mut self, v: Option<usize>) {
+        self.key_len = v;
+    }
+
     pub fn set_entry_api(&mut self, v: bool) {
         self.entry_api = v;
     }
@@ -85,6 +311,40 @@ impl Config {
         self.per_key_expiration = v;
     }
 
+    /// Returns the max capacity of a cache that can hold `capacity` entries. When
+    /// the cache is size aware, it is the total weight of `capacity` entries of the
+    /// mean value size of the trace.
+    pub fn max_capacity(&self, capacity: usize) -> u64 {
+        if !self.size_aware {
+            return capacity as u64;
+        }
+        let mean_weight = self
+            .trace_file
+            .mean_value_size
+            .map_or(Self::DEFAULT_MEAN_WEIGHT, u64::from);
+        capacity as u64 * mean_weight
+    }
+
+    /// Converts a duration on the timeline of the trace into the wall-clock
+    /// duration of the replay. It is scaled only when the trace is replayed by its
+    /// timestamps.
+    pub fn replay_duration(&self, duration: Duration) -> Duration {
+        match self.time_scale {
+            Some(scale) => duration.div_f64(scale),
+            None => duration,
+        }
+    }
+
+    /// The cache level time-to-live on the timeline of the replay.
+    pub fn replay_ttl(&self) -> Option<Duration> {
+        self.ttl.map(|ttl| self.replay_duration(ttl))
+    }
+
+    /// The cache level time-to-idle on the timeline of the replay.
+    pub fn replay_tti(&self) -> Option<Duration> {
+        self.tti.map(|tti| self.replay_duration(tti))
+    }
+
     pub fn is_eviction_listener_enabled(&self) -> bool {
         self.eviction_listener != RemovalNotificationMode::None
     }
@@ -96,3 +356,42 @@ pub enum RemovalNotificationMode {
     Immediate,
     Queued,
 }
+
+/// How the traces of the tenants are interleaved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interleave {
+    /// Takes the requests from the traces in proportion to the weights of the
+    /// tenants.
+    Weight,
+    /// Takes the request with the earliest timestamp from the traces.
+    Timestamp,
+}
+
+/// How the keys of the trace are transformed before they are sent to the caches.
+/// It changes the distribution of the keys but not the access pattern (except
+/// `Fold`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyTransform {
+    /// Uses the keys as they are.
+    Identity,
+    /// Maps the keys with a bijective hash function, so that dense keys become
+    /// sparse and evenly spread.
+    Scramble,
+    /// Renumbers the keys as 0, 1, 2, ... by their first appearance, so that
+    /// sparse keys become dense.
+    Dense,
+    /// Folds the keys into `0..n` by modulo. Different keys may collide, which
+    /// shrinks the key space.
+    Fold(usize),
+}
+
+/// The type of the keys stored in the caches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyType {
+    /// `usize`. Textual keys in the trace are replaced by their hashes.
+    Integer,
+    /// `String`. Numeric keys in the trace are converted to decimal strings.
+    String,
+    /// `Vec<u8>`. Same as `String` but stored as bytes.
+    Bytes,
+}
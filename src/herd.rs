@@ -0,0 +1,185 @@
+//! Runs a thundering herd workload. All clients read the keys of a round at once,
+//! and one caller per key is expected to run the init closure while the others
+//! wait for its value. The report counts the runs of the init closure against the
+//! callers, and the times the waiters took to return after the loader returned.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Barrier,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    async_rt_helper as rt,
+    cache::{AsyncCacheDriver, CacheDriver},
+    config::Config,
+    parser::TraceEntry,
+    report::{ContentionReport, ReportBuilder},
+    workload::ThunderingHerdWorkload,
+    Report,
+};
+
+/// A read of a key by a caller.
+#[derive(Clone, Copy)]
+struct Call {
+    returned_at: Instant,
+    /// `true` if the init closure of the caller ran.
+    loaded: bool,
+}
+
+/// Runs the workload with `num_clients` threads sharing the cache.
+pub(crate) fn run_threads(
+    config: &Config,
+    workload: &ThunderingHerdWorkload,
+    num_clients: u16,
+    cache_driver: &(impl CacheDriver<TraceEntry> + Clone + Send + 'static),
+    report_builder: ReportBuilder,
+) -> anyhow::Result<Report> {
+    let rounds = rounds(config, workload)?;
+    let barrier = Arc::new(Barrier::new(num_clients as usize));
+    let report_builder = Arc::new(report_builder);
+
+    let instant = Instant::now();
+    let handles = (0..num_clients)
+        .map(|_| {
+            let mut cache = cache_driver.clone();
+            let rounds = Arc::clone(&rounds);
+            let barrier = Arc::clone(&barrier);
+            let rb = Arc::clone(&report_builder);
+
+            std::thread::spawn(move || {
+                let mut report = rb.build();
+                let mut calls = Vec::new();
+                for round in rounds.iter() {
+                    barrier.wait();
+                    for entry in round {
+                        let insert_count = report.insert_count;
+                        cache.get_or_insert_once(entry, &mut report);
+                        calls.push(Call {
+                            returned_at: Instant::now(),
+                            loaded: report.insert_count > insert_count,
+                        });
+                    }
+                }
+                (report, calls)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = handles
+        .into_iter()
+        .map(|h| h.join().expect("Failed"))
+        .collect::<Vec<_>>();
+    Ok(merge_results(&report_builder, instant.elapsed(), results))
+}
+
+/// Runs the workload with `num_clients` tasks sharing the cache.
+pub(crate) async fn run_tasks(
+    config: &Config,
+    workload: &ThunderingHerdWorkload,
+    num_clients: u16,
+    cache_driver: &(impl AsyncCacheDriver<TraceEntry> + Clone + Send + 'static),
+    report_builder: ReportBuilder,
+) -> anyhow::Result<Report> {
+    let rounds = rounds(config, workload)?;
+    // The number of the clients that have started each round, summed up over the
+    // rounds.
+    let num_arrived = Arc::new(AtomicUsize::default());
+    let report_builder = Arc::new(report_builder);
+
+    let instant = Instant::now();
+    let handles = (0..num_clients)
+        .map(|_| {
+            let mut cache = cache_driver.clone();
+            let rounds = Arc::clone(&rounds);
+            let num_arrived = Arc::clone(&num_arrived);
+            let rb = Arc::clone(&report_builder);
+
+            rt::spawn(async move {
+                let mut report = rb.build();
+                let mut calls = Vec::new();
+                for (i, round) in rounds.iter().enumerate() {
+                    // Do not block the runtime thread while waiting for the other
+                    // clients.
+                    num_arrived.fetch_add(1, Ordering::AcqRel);
+                    while num_arrived.load(Ordering::Acquire) < (i + 1) * num_clients as usize {
+                        rt::yield_now().await;
+                    }
+                    for entry in round {
+                        let insert_count = report.insert_count;
+                        cache.get_or_insert_once(entry, &mut report).await;
+                        calls.push(Call {
+                            returned_at: Instant::now(),
+                            loaded: report.insert_count > insert_count,
+                        });
+                    }
+                }
+                (report, calls)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = futures_util::future::join_all(handles).await;
+    let elapsed = instant.elapsed();
+
+    #[cfg(feature = "rt-tokio")]
+    let results = results
+        .into_iter()
+        .map(|r| r.expect("Failed"))
+        .collect::<Vec<_>>();
+
+    Ok(merge_results(&report_builder, elapsed, results))
+}
+
+/// Returns the entries of the workload split into the rounds.
+fn rounds(
+    config: &Config,
+    workload: &ThunderingHerdWorkload,
+) -> anyhow::Result<Arc<[Vec<TraceEntry>]>> {
+    let entries = config
+        .trace_file
+        .entries()?
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(entries
+        .chunks(workload.keys_per_round)
+        .map(<[_]>::to_vec)
+        .collect())
+}
+
+/// Merges the reports of the clients into one, and counts the wake-ups of the
+/// waiters of each key from the calls of the clients, which are in the same order.
+fn merge_results(
+    report_builder: &ReportBuilder,
+    elapsed: Duration,
+    results: Vec<(Report, Vec<Call>)>,
+) -> Report {
+    let mut report = report_builder.build();
+    report.duration = Some(elapsed);
+
+    let mut contention = ContentionReport::default();
+    let num_calls = results.first().map_or(0, |(_, calls)| calls.len());
+    for i in 0..num_calls {
+        let calls = || results.iter().map(move |(_, calls)| calls[i]);
+        contention.load_count += 1;
+        // If more than one caller ran the init closure, the waiters could get the
+        // value of the first one to return.
+        let Some(loaded_at) = calls()
+            .filter(|call| call.loaded)
+            .map(|call| call.returned_at)
+            .min()
+        else {
+            continue;
+        };
+        for call in calls().filter(|call| !call.loaded) {
+            contention.add_waiter(call.returned_at.saturating_duration_since(loaded_at));
+        }
+    }
+
+    for (r, _) in &results {
+        report.merge(r);
+    }
+    report.contention = Some(contention);
+    report
+}
@@ -0,0 +1,149 @@
+//! Transforms the keys of a trace, to see how much the distribution of the keys
+//! (e.g. the small dense keys of the LIRS traces and the huge sparse block numbers
+//! of the ARC traces) affects the caches.
+
+use std::collections::HashMap;
+
+use crate::{config::KeyTransform, parser::TraceEntry};
+
+/// The mask of the bits of a key that are scrambled. The highest bit is kept as
+/// is, so that `key + 1` does not overflow for the keys below it.
+const SCRAMBLE_MASK: u64 = u64::MAX >> 1;
+
+/// Applies a `KeyTransform` to the entries of a trace. It keeps the dense keys
+/// assigned so far, so use the same transformer for all repeats of the trace.
+pub(crate) struct KeyTransformer {
+    transform: KeyTransform,
+    dense_keys: HashMap<usize, usize>,
+}
+
+impl KeyTransformer {
+    pub(crate) fn new(transform: KeyTransform) -> Self {
+        Self {
+            transform,
+            dense_keys: HashMap::new(),
+        }
+    }
+
+    /// Transforms the keys of the entries. An entry with a range of keys is split
+    /// into the entries of its keys, as the transformed keys are not contiguous.
+    pub(crate) fn apply<'a>(
+        &'a mut self,
+        entries: impl Iterator<Item = anyhow::Result<TraceEntry>> + 'a,
+    ) -> Box<dyn Iterator<Item = anyhow::Result<TraceEntry>> + 'a> {
+        if self.transform == KeyTransform::Identity {
+            return Box::new(entries);
+        }
+        Box::new(entries.flat_map(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => return vec![Err(e)],
+            };
+            entry
+                .range()
+                .map(|key| Ok(entry.with_transformed_key(self.key(key))))
+                .collect()
+        }))
+    }
+
+    fn key(&mut self, key: usize) -> usize {
+        match self.transform {
+            KeyTransform::Identity => key,
+            KeyTransform::Scramble => scramble(key as u64) as usize,
+            KeyTransform::Dense => {
+                let next = self.dense_keys.len();
+                *self.dense_keys.entry(key).or_insert(next)
+            }
+            KeyTransform::Fold(n) => key % n,
+        }
+    }
+}
+
+/// A bijective mix of the lower 63 bits of the key (the finalizer of SplitMix64
+/// modulo 2^63). The odd multiplications and the right xor-shifts are both
+/// invertible.
+fn scramble(key: u64) -> u64 {
+    let mut x = key & SCRAMBLE_MASK;
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9) & SCRAMBLE_MASK;
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb) & SCRAMBLE_MASK;
+    x ^= x >> 31;
+    (key & !SCRAMBLE_MASK) | x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The inverse of `scramble`.
+    fn unscramble(key: u64) -> u64 {
+        // Undoes `x ^= x >> shift` by applying it until all bits are restored.
+        let unshift = |x: u64, shift: u32| {
+            let mut y = x;
+            for _ in 0..(63 / shift) {
+                y = x ^ (y >> shift);
+            }
+            y
+        };
+        // The inverse of an odd number modulo 2^64, which is also the inverse
+        // modulo 2^63, by Newton's method.
+        let inverse = |c: u64| {
+            let mut inv = c;
+            for _ in 0..6 {
+                inv = inv.wrapping_mul(2u64.wrapping_sub(c.wrapping_mul(inv)));
+            }
+            inv
+        };
+
+        let mut x = key & SCRAMBLE_MASK;
+        x = unshift(x, 31);
+        x = x.wrapping_mul(inverse(0x94d0_49bb_1331_11eb)) & SCRAMBLE_MASK;
+        x = unshift(x, 27);
+        x = x.wrapping_mul(inverse(0xbf58_476d_1ce4_e5b9)) & SCRAMBLE_MASK;
+        x = unshift(x, 30);
+        (key & !SCRAMBLE_MASK) | x
+    }
+
+    #[test]
+    fn scramble_is_bijective() {
+        let random = (0..10_000u64).map(|i| xxhash_rust::xxh3::xxh3_64(&i.to_le_bytes()));
+        let keys = (0..1_000u64)
+            .chain([SCRAMBLE_MASK, SCRAMBLE_MASK + 1, u64::MAX])
+            .chain(random);
+        for key in keys {
+            let scrambled = scramble(key);
+            assert_eq!(unscramble(scrambled), key, "key: {key:#x}");
+            // The highest bit is kept.
+            assert_eq!(scrambled & !SCRAMBLE_MASK, key & !SCRAMBLE_MASK);
+        }
+    }
+
+    fn transform(transform: KeyTransform, ranges: &[std::ops::Range<usize>]) -> Vec<usize> {
+        let entries = ranges
+            .iter()
+            .enumerate()
+            .map(|(i, range)| Ok(TraceEntry::new(range.clone(), i)))
+            .collect::<Vec<_>>();
+        KeyTransformer::new(transform)
+            .apply(entries.into_iter())
+            .map(|entry| entry.unwrap().range().start)
+            .collect()
+    }
+
+    #[test]
+    fn transform_keys() {
+        let ranges = [1_000..1_003, 7..8, 1_001..1_002];
+        assert_eq!(
+            transform(KeyTransform::Identity, &ranges),
+            vec![1_000, 7, 1_001]
+        );
+        assert_eq!(transform(KeyTransform::Dense, &ranges), vec![0, 1, 2, 3, 1]);
+        assert_eq!(
+            transform(KeyTransform::Fold(3), &ranges),
+            vec![1, 2, 0, 1, 2]
+        );
+        let scrambled = [1_000, 1_001, 1_002, 7, 1_001].map(|key| scramble(key) as usize);
+        assert_eq!(transform(KeyTransform::Scramble, &ranges), scrambled);
+    }
+}
@@ -12,9 +12,8 @@ compile_error!(
                 You might need `--no-default-features`."
 );
 
-use std::io::prelude::*;
 use std::sync::Arc;
-use std::{fs::File, io::BufReader, time::Instant};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "moka-v012")]
 pub(crate) use moka012 as moka;
@@ -31,18 +30,29 @@ pub(crate) use moka09 as moka;
 #[cfg(feature = "moka-v08")]
 pub(crate) use moka08 as moka;
 
+mod analyzer;
 mod async_rt_helper;
 mod cache;
 pub mod config;
 mod eviction_counters;
+mod herd;
+mod key_transform;
 mod load_gen;
+pub mod op_mix;
 mod parser;
+pub mod recorder;
 mod report;
 mod trace_file;
+mod trace_reader;
+mod validator;
+pub mod workload;
 
+pub use analyzer::{analyze_trace, TraceAnalysis, WorkingSet, DEFAULT_WINDOW_SIZE};
 pub(crate) use eviction_counters::EvictionCounters;
-pub use report::Report;
-pub use trace_file::TraceFile;
+pub use parser::{binary::convert_trace, TraceFormat, TraceOperation, TraceParseError};
+pub use report::{ContentionReport, CsvColumns, Report, TenantReport};
+pub use trace_file::{TraceDescriptor, TraceRegistry};
+pub use validator::{validate_trace, TraceProblem, ValidationReport};
 
 use async_rt_helper as rt;
 use cache::{
@@ -51,8 +61,8 @@ use cache::{
     },
     AsyncCacheDriver, CacheDriver,
 };
-use config::Config;
-use itertools::Itertools;
+use config::{Config, KeyType};
+use crossbeam_channel::{Receiver, TryRecvError};
 use parser::TraceEntry;
 use report::ReportBuilder;
 
@@ -71,6 +81,32 @@ use crate::cache::tiny_ufo::TinyUfoCache;
 
 const BATCH_SIZE: usize = 200;
 
+/// Returns the number of threads to parse a trace or generate the commands with.
+pub(crate) fn available_parallelism() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Evaluates `$body` with `$key` bound to the key type selected by
+/// `config.key_type`.
+macro_rules! with_key_type {
+    ($config:expr, $key:ident => $body:block) => {
+        match $config.key_type {
+            KeyType::Integer => {
+                type $key = usize;
+                $body
+            }
+            KeyType::String => {
+                type $key = String;
+                $body
+            }
+            KeyType::Bytes => {
+                type $key = Vec<u8>;
+                $body
+            }
+        }
+    };
+}
+
 pub(crate) enum Command {
     GetOrInsert(TraceEntry),
     GetOrInsertOnce(TraceEntry),
@@ -79,6 +115,23 @@ pub(crate) enum Command {
     InvalidateAll,
     InvalidateEntriesIf(TraceEntry),
     Iterate,
+    /// Waits until the duration has elapsed since the start of the replay. Only
+    /// generated when the trace is replayed by its timestamps.
+    WaitUntil(Duration),
+}
+
+impl Command {
+    /// Returns the trace entry of the command, if any.
+    fn entry(&self) -> Option<&TraceEntry> {
+        match self {
+            Self::GetOrInsert(entry)
+            | Self::GetOrInsertOnce(entry)
+            | Self::Update(entry)
+            | Self::Invalidate(entry)
+            | Self::InvalidateEntriesIf(entry) => Some(entry),
+            Self::InvalidateAll | Self::Iterate | Self::WaitUntil(_) => None,
+        }
+    }
 }
 
 pub fn run_multi_threads_moka_sync(
@@ -86,21 +139,19 @@ pub fn run_multi_threads_moka_sync(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let max_cap = if config.size_aware {
-        capacity as u64 * 2u64.pow(15)
-    } else {
-        capacity as u64
-    };
+    let max_cap = config.max_capacity(capacity);
     let report_builder = ReportBuilder::new("Moka Sync Cache", max_cap, Some(num_clients));
 
-    #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
-    if config.entry_api {
-        let cache_driver = MokaSyncCache::with_entry_api(config, max_cap, capacity);
-        return run_multi_threads(config, num_clients, cache_driver, report_builder);
-    }
+    with_key_type!(config, K => {
+        #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
+        if config.entry_api {
+            let cache_driver = MokaSyncCache::<K, _>::with_entry_api(config, max_cap, capacity);
+            return run_multi_threads(config, num_clients, cache_driver, report_builder);
+        }
 
-    let cache_driver = MokaSyncCache::new(config, max_cap, capacity);
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+        let cache_driver = MokaSyncCache::<K, _>::new(config, max_cap, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 pub fn run_multi_threads_moka_segment(
@@ -109,23 +160,21 @@ pub fn run_multi_threads_moka_segment(
     num_clients: u16,
     num_segments: usize,
 ) -> anyhow::Result<Report> {
-    let max_cap = if config.size_aware {
-        capacity as u64 * 2u64.pow(15)
-    } else {
-        capacity as u64
-    };
+    let max_cap = config.max_capacity(capacity);
     let report_name = format!("Moka SegmentedCache({num_segments})");
     let report_builder = ReportBuilder::new(&report_name, max_cap, Some(num_clients));
 
-    #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
-    if config.entry_api {
-        let cache_driver =
-            MokaSegmentedCache::with_entry_api(config, max_cap, capacity, num_segments);
-        return run_multi_threads(config, num_clients, cache_driver, report_builder);
-    }
+    with_key_type!(config, K => {
+        #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
+        if config.entry_api {
+            let cache_driver =
+                MokaSegmentedCache::<K, _>::with_entry_api(config, max_cap, capacity, num_segments);
+            return run_multi_threads(config, num_clients, cache_driver, report_builder);
+        }
 
-    let cache_driver = MokaSegmentedCache::new(config, max_cap, capacity, num_segments);
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+        let cache_driver = MokaSegmentedCache::<K, _>::new(config, max_cap, capacity, num_segments);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 pub async fn run_multi_tasks_moka_async(
@@ -133,21 +182,19 @@ pub async fn run_multi_tasks_moka_async(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let max_cap = if config.size_aware {
-        capacity as u64 * 2u64.pow(15)
-    } else {
-        capacity as u64
-    };
+    let max_cap = config.max_capacity(capacity);
     let report_builder = ReportBuilder::new("Moka Async Cache", max_cap, Some(num_clients));
 
-    #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
-    if config.entry_api {
-        let cache_driver = MokaAsyncCache::with_entry_api(config, max_cap, capacity);
-        return run_multi_tasks(config, num_clients, cache_driver, report_builder).await;
-    }
+    with_key_type!(config, K => {
+        #[cfg(not(any(feature = "moka-v08", feature = "moka-v09")))]
+        if config.entry_api {
+            let cache_driver = MokaAsyncCache::<K, _>::with_entry_api(config, max_cap, capacity);
+            return run_multi_tasks(config, num_clients, cache_driver, report_builder).await;
+        }
 
-    let cache_driver = MokaAsyncCache::new(config, max_cap, capacity);
-    run_multi_tasks(config, num_clients, cache_driver, report_builder).await
+        let cache_driver = MokaAsyncCache::<K, _>::new(config, max_cap, capacity);
+        run_multi_tasks(config, num_clients, cache_driver, report_builder).await
+    })
 }
 
 #[cfg(any(feature = "mini-moka", feature = "moka-v08", feature = "moka-v09"))]
@@ -156,19 +203,17 @@ pub fn run_multi_threads_moka_dash(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let max_cap = if config.size_aware {
-        capacity as u64 * 2u64.pow(15)
-    } else {
-        capacity as u64
-    };
-    let cache_driver = MiniMokSyncCache::new(config, max_cap, capacity);
+    let max_cap = config.max_capacity(capacity);
     let report_name = if cfg!(feature = "mini-moka") {
         "Mini Moka Sync Cache"
     } else {
         "Moka Dash Cache"
     };
     let report_builder = ReportBuilder::new(report_name, max_cap, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = MiniMokSyncCache::<K>::new(config, max_cap, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(feature = "hashlink")]
@@ -177,10 +222,12 @@ pub fn run_multi_threads_hashlink(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let cache_driver = HashLink::new(config, capacity);
     let report_builder =
         ReportBuilder::new("HashLink (LRU w/ Mutex)", capacity as _, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = HashLink::<K>::new(config, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(feature = "quick_cache")]
@@ -189,15 +236,13 @@ pub fn run_multi_threads_quick_cache(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let max_cap = if config.size_aware {
-        capacity as u64 * 2u64.pow(15)
-    } else {
-        capacity as u64
-    };
-    let cache_driver = QuickCache::new(config, capacity, max_cap);
+    let max_cap = config.max_capacity(capacity);
     let report_builder =
         ReportBuilder::new("QuickCache Sync Cache", capacity as _, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = QuickCache::<K>::new(config, capacity, max_cap);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(feature = "light-cache")]
@@ -208,10 +253,12 @@ pub fn run_multi_threads_light_cache(
 ) -> anyhow::Result<Report> {
     use cache::light_cache::LightCache;
 
-    let cache_driver = LightCache::new(config, capacity);
     let report_builder =
         ReportBuilder::new("LightCache Sync Cache", capacity as _, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = LightCache::<K>::new(config, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(feature = "light-cache-lru")]
@@ -222,10 +269,12 @@ pub fn run_multi_threads_light_cache_lru(
 ) -> anyhow::Result<Report> {
     use cache::light_cache_lru::LightCacheLru;
 
-    let cache_driver = LightCacheLru::new(config, capacity);
     let report_builder =
         ReportBuilder::new("LightCache Sync Cache LRU", capacity as _, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = LightCacheLru::<K>::new(config, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(feature = "stretto")]
@@ -234,9 +283,11 @@ pub fn run_multi_threads_stretto(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let cache_driver = StrettoCache::new(config, capacity);
     let report_builder = ReportBuilder::new("Stretto", capacity as _, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = StrettoCache::<K>::new(config, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(feature = "tiny-ufo")]
@@ -245,76 +296,153 @@ pub fn run_multi_threads_tiny_ufo(
     capacity: usize,
     num_clients: u16,
 ) -> anyhow::Result<Report> {
-    let cache_driver = TinyUfoCache::new(config, capacity);
     let report_builder = ReportBuilder::new("TinyUFO", capacity as _, Some(num_clients));
-    run_multi_threads(config, num_clients, cache_driver, report_builder)
+    with_key_type!(config, K => {
+        let cache_driver = TinyUfoCache::<K>::new(config, capacity);
+        run_multi_threads(config, num_clients, cache_driver, report_builder)
+    })
 }
 
 #[cfg(any(feature = "mini-moka", feature = "moka-v08", feature = "moka-v09"))]
 pub fn run_single(config: &Config, capacity: usize) -> anyhow::Result<Report> {
-    let mut max_cap = capacity.try_into().unwrap();
-    if config.size_aware {
-        max_cap *= 2u64.pow(15);
-    }
-    let mut cache_driver = MiniMokaUnsyncCache::new(config, max_cap, capacity);
+    let max_cap = config.max_capacity(capacity);
     let name = if cfg!(feature = "mini-moka") {
         "Mini Moka Unsync Cache"
     } else {
         "Moka Unsync Cache"
     };
     let mut report = Report::new(name, max_cap, Some(1));
-    let mut counter = 0;
-
-    // pre-process all commands to reduce benchmark harness influence.
-    let mut all_commands = Vec::new();
-    for _ in 0..(config.repeat.unwrap_or(1)) {
-        let f = File::open(config.trace_file.path())?;
-        let reader = BufReader::new(f);
-
-        for chunk in reader.lines().enumerate().chunks(BATCH_SIZE).into_iter() {
-            let chunk = chunk.map(|(i, r)| r.map(|s| (i, s)));
-            let commands = load_gen::generate_commands(config, BATCH_SIZE, &mut counter, chunk)?;
-            all_commands.push(commands);
+
+    with_key_type!(config, K => {
+        let mut cache_driver = MiniMokaUnsyncCache::<K>::new(config, max_cap, capacity);
+
+        let phases = workload::phase_runs(config, 1);
+        if phases.is_empty() {
+            run_single_phase(config, &mut cache_driver, &mut report, None)?;
+        } else {
+            for phase in phases {
+                let phase_name = format!("{name} [{}]", phase.name);
+                let mut phase_report = Report::new(&phase_name, max_cap, Some(1));
+                let (phase_config, duration) = (&phase.config, phase.duration);
+                run_single_phase(phase_config, &mut cache_driver, &mut phase_report, duration)?;
+                add_phase_report(config, None, &mut report, phase_report);
+            }
         }
-    }
+    });
+    // The unsync cache runs with the preloaded commands except the phases with
+    // durations, so it rarely starves.
+    report.has_starvation = config.streaming;
+
+    Ok(report)
+}
+
+/// Runs the commands of the trace (or a phase of a phased workload) with a single
+/// client. The commands are preloaded unless the streaming mode is enabled, as it
+/// is for a phase with a `duration`, which runs until the duration has elapsed.
+#[cfg(any(feature = "mini-moka", feature = "moka-v08", feature = "moka-v09"))]
+fn run_single_phase(
+    config: &Config,
+    cache_driver: &mut impl CacheDriver<TraceEntry>,
+    report: &mut Report,
+    duration: Option<Duration>,
+) -> anyhow::Result<()> {
+    // pre-process all commands to reduce benchmark harness influence, unless the
+    // streaming mode is enabled (it always is for a phase with a duration).
+    let (all_commands, producers, mut sampled_reads) = if config.streaming {
+        let (receive, producers) = load_gen::spawn_producers(config, BATCH_SIZE);
+        (receive, Some(producers), Default::default())
+    } else {
+        let (receive, sampled_reads) = load_gen::preload(config, BATCH_SIZE)?;
+        (receive, None, sampled_reads)
+    };
+    report.sampling_rate = config.sampling_rate;
 
     let instant = Instant::now();
-    for commands in all_commands {
-        cache::process_commands(commands, &mut cache_driver, &mut report);
+    while let Some(commands) = recv_commands(&all_commands, &mut report.starvation) {
+        cache::process_commands(commands, cache_driver, report, instant);
+        if duration.is_some_and(|d| instant.elapsed() >= d) {
+            break;
+        }
     }
-    let elapsed = instant.elapsed();
-    report.duration = Some(elapsed);
+    report.duration = Some(instant.elapsed());
 
-    Ok(report)
+    drop(all_commands);
+    if let Some(producers) = producers {
+        sampled_reads = producers.join()?;
+    }
+    report.num_unsampled_reads = sampled_reads.unsampled_reads(report.read_count);
+    Ok(())
 }
 
-#[allow(clippy::needless_collect)] // on the `handles` variable.
 fn run_multi_threads(
     config: &Config,
     num_clients: u16,
     cache_driver: impl CacheDriver<TraceEntry> + Clone + Send + 'static,
     report_builder: ReportBuilder,
 ) -> anyhow::Result<Report> {
-    let report_builder = Arc::new(report_builder);
-    let (send, receive) = crossbeam_channel::unbounded::<Vec<Command>>();
-
-    // In order to have the minimum harness overhead and not have many consumers
-    // waiting for the single producer, we buffer all operations in a channel.
-    let mut counter = 0;
-    for _ in 0..(config.repeat.unwrap_or(1)) {
-        let f = File::open(config.trace_file.path())?;
-        let reader = BufReader::new(f);
-        for chunk in reader.lines().enumerate().chunks(BATCH_SIZE).into_iter() {
-            let chunk = chunk.map(|(i, r)| r.map(|s| (i, s)));
-            let commands = load_gen::generate_commands(config, BATCH_SIZE, &mut counter, chunk)?;
-            send.send(commands)?;
+    let eviction_counters = cache_driver.eviction_counters();
+    let phases = workload::phase_runs(config, num_clients);
+    let mut report = if let Some(workload) = config.trace_file.thundering_herd() {
+        herd::run_threads(config, workload, num_clients, &cache_driver, report_builder)?
+    } else if phases.is_empty() {
+        run_threads(config, num_clients, &cache_driver, report_builder, None)?
+    } else {
+        let mut report = report_builder.build();
+        for phase in phases {
+            let phase_report = run_threads(
+                &phase.config,
+                phase.num_clients,
+                &cache_driver,
+                report_builder.for_phase(&phase.name, phase.num_clients),
+                phase.duration,
+            )?;
+            add_phase_report(
+                config,
+                eviction_counters.as_deref(),
+                &mut report,
+                phase_report,
+            );
         }
+        report
+    };
+
+    if config.is_eviction_listener_enabled() {
+        report.add_eviction_counts(eviction_counters.as_ref().unwrap());
     }
 
-    // Drop the sender channel to notify the workers that we are finished.
-    std::mem::drop(send);
+    Ok(report)
+}
+
+/// Runs the commands of the trace (or a phase of a phased workload) with
+/// `num_clients` threads sharing the cache. If `duration` is given, the clients
+/// stop when it has elapsed.
+#[allow(clippy::needless_collect)] // on the `handles` variable.
+fn run_threads(
+    config: &Config,
+    num_clients: u16,
+    cache_driver: &(impl CacheDriver<TraceEntry> + Clone + Send + 'static),
+    report_builder: ReportBuilder,
+    duration: Option<Duration>,
+) -> anyhow::Result<Report> {
+    let report_builder = Arc::new(report_builder);
+
+    // In order to have the minimum harness overhead and not have many consumers
+    // waiting for the single producer, we buffer all operations in a channel,
+    // unless the streaming mode is enabled.
+    let preloaded = if config.streaming {
+        None
+    } else {
+        Some(load_gen::preload(config, BATCH_SIZE)?)
+    };
 
     let instant = Instant::now();
+    let (receive, producers, mut sampled_reads) = match preloaded {
+        Some((receive, sampled_reads)) => (receive, None, sampled_reads),
+        None => {
+            let (receive, producers) = load_gen::spawn_producers(config, BATCH_SIZE);
+            (receive, Some(producers), Default::default())
+        }
+    };
     let handles = (0..num_clients)
         .map(|_| {
             let mut cache = cache_driver.clone();
@@ -323,8 +451,11 @@ fn run_multi_threads(
 
             std::thread::spawn(move || {
                 let mut report = rb.build();
-                while let Ok(commands) = ch.recv() {
-                    cache::process_commands(commands, &mut cache, &mut report);
+                while let Some(commands) = recv_commands(&ch, &mut report.starvation) {
+                    cache::process_commands(commands, &mut cache, &mut report, instant);
+                    if duration.is_some_and(|d| instant.elapsed() >= d) {
+                        break;
+                    }
                 }
                 report
             })
@@ -342,40 +473,123 @@ fn run_multi_threads(
     let mut report = report_builder.build();
     report.duration = Some(elapsed);
     reports.iter().for_each(|r| report.merge(r));
+    report.has_starvation = config.streaming;
 
-    if config.is_eviction_listener_enabled() {
-        report.add_eviction_counts(cache_driver.eviction_counters().as_ref().unwrap());
+    // Stop the producers if the clients stopped before the end of the commands.
+    drop(receive);
+    if let Some(producers) = producers {
+        sampled_reads = producers.join()?;
     }
+    report.sampling_rate = config.sampling_rate;
+    report.num_unsampled_reads = sampled_reads.unsampled_reads(report.read_count);
 
     Ok(report)
 }
 
+/// Adds the report of a phase of a phased workload to the report of the whole run.
+/// The phase gets the evictions since the previous phases.
+fn add_phase_report(
+    config: &Config,
+    eviction_counters: Option<&EvictionCounters>,
+    report: &mut Report,
+    mut phase_report: Report,
+) {
+    // A phase with a duration is streamed, but it is reported in the same columns
+    // as the run.
+    phase_report.has_starvation = config.streaming;
+    phase_report.has_throughput = true;
+    if let (true, Some(counters)) = (config.is_eviction_listener_enabled(), eviction_counters) {
+        phase_report.add_eviction_counts(counters);
+        for previous in &report.phases {
+            phase_report.invalidation_count -= previous.invalidation_count;
+            phase_report.eviction_count -= previous.eviction_count;
+            phase_report.expiration_count -= previous.expiration_count;
+        }
+    }
+    report.add_phase(phase_report);
+}
+
+/// Receives the next batch of commands. The time spent waiting for the producers
+/// is added to `starvation`. Returns `None` when all commands have been received.
+fn recv_commands(ch: &Receiver<Vec<Command>>, starvation: &mut Duration) -> Option<Vec<Command>> {
+    if let Ok(commands) = ch.try_recv() {
+        return Some(commands);
+    }
+    let instant = Instant::now();
+    let commands = ch.recv().ok();
+    *starvation += instant.elapsed();
+    commands
+}
+
 async fn run_multi_tasks(
     config: &Config,
     num_clients: u16,
     cache_driver: impl AsyncCacheDriver<TraceEntry> + Clone + Send + 'static,
     report_builder: ReportBuilder,
 ) -> anyhow::Result<Report> {
-    let report_builder = Arc::new(report_builder);
-    let (send, receive) = crossbeam_channel::unbounded::<Vec<Command>>();
-
-    // In order to have the minimum harness overhead and not have many consumers
-    // waiting for the single producer, we buffer all operations in a channel.
-    let mut counter = 0;
-    for _ in 0..(config.repeat.unwrap_or(1)) {
-        let f = File::open(config.trace_file.path())?;
-        let reader = BufReader::new(f);
-        for chunk in reader.lines().enumerate().chunks(BATCH_SIZE).into_iter() {
-            let chunk = chunk.map(|(i, r)| r.map(|s| (i, s)));
-            let commands = load_gen::generate_commands(config, BATCH_SIZE, &mut counter, chunk)?;
-            send.send(commands)?;
+    let eviction_counters = cache_driver.eviction_counters();
+    let phases = workload::phase_runs(config, num_clients);
+    let mut report = if let Some(workload) = config.trace_file.thundering_herd() {
+        herd::run_tasks(config, workload, num_clients, &cache_driver, report_builder).await?
+    } else if phases.is_empty() {
+        run_tasks(config, num_clients, &cache_driver, report_builder, None).await?
+    } else {
+        let mut report = report_builder.build();
+        for phase in phases {
+            let phase_report = run_tasks(
+                &phase.config,
+                phase.num_clients,
+                &cache_driver,
+                report_builder.for_phase(&phase.name, phase.num_clients),
+                phase.duration,
+            )
+            .await?;
+            add_phase_report(
+                config,
+                eviction_counters.as_deref(),
+                &mut report,
+                phase_report,
+            );
         }
+        report
+    };
+
+    if config.is_eviction_listener_enabled() {
+        report.add_eviction_counts(eviction_counters.as_ref().unwrap());
     }
 
-    // Drop the sender channel to notify the workers that we are finished.
-    std::mem::drop(send);
+    Ok(report)
+}
+
+/// Runs the commands of the trace (or a phase of a phased workload) with
+/// `num_clients` tasks sharing the cache. If `duration` is given, the clients stop
+/// when it has elapsed.
+async fn run_tasks(
+    config: &Config,
+    num_clients: u16,
+    cache_driver: &(impl AsyncCacheDriver<TraceEntry> + Clone + Send + 'static),
+    report_builder: ReportBuilder,
+    duration: Option<Duration>,
+) -> anyhow::Result<Report> {
+    let report_builder = Arc::new(report_builder);
+
+    // In order to have the minimum harness overhead and not have many consumers
+    // waiting for the single producer, we buffer all operations in a channel,
+    // unless the streaming mode is enabled.
+    let preloaded = if config.streaming {
+        None
+    } else {
+        Some(load_gen::preload(config, BATCH_SIZE)?)
+    };
 
     let instant = Instant::now();
+    let (receive, producers, mut sampled_reads) = match preloaded {
+        Some((receive, sampled_reads)) => (receive, None, sampled_reads),
+        None => {
+            let (receive, producers) = load_gen::spawn_producers(config, BATCH_SIZE);
+            (receive, Some(producers), Default::default())
+        }
+    };
     let handles = (0..num_clients)
         .map(|_| {
             let mut cache = cache_driver.clone();
@@ -385,11 +599,30 @@ async fn run_multi_tasks(
 
             rt::spawn(async move {
                 let mut report = rb.build();
-                while let Ok(commands) = ch.recv() {
-                    cache::process_commands_async(commands, &mut cache, &mut report).await;
+                loop {
+                    let commands = match ch.try_recv() {
+                        Ok(commands) => commands,
+                        Err(TryRecvError::Disconnected) => break,
+                        Err(TryRecvError::Empty) => {
+                            // Wait for the producers on a blocking thread so that the
+                            // runtime thread can run the other clients.
+                            let instant = Instant::now();
+                            let ch = ch.clone();
+                            let commands = rt::spawn_blocking(move || ch.recv()).await;
+                            report.starvation += instant.elapsed();
+                            match commands {
+                                Ok(commands) => commands,
+                                Err(_) => break,
+                            }
+                        }
+                    };
+                    cache::process_commands_async(commands, &mut cache, &mut report, instant).await;
+                    if duration.is_some_and(|d| instant.elapsed() >= d) {
+                        break;
+                    }
                     count += 1;
-                    if count % 10_000 == 0 {
-                        tokio::task::yield_now().await;
+                    if count.is_multiple_of(10_000) {
+                        rt::yield_now().await;
                     }
                 }
                 report
@@ -412,10 +645,65 @@ async fn run_multi_tasks(
         #[cfg(feature = "rt-async-std")]
         report.merge(&r);
     }
+    report.has_starvation = config.streaming;
 
-    if config.is_eviction_listener_enabled() {
-        report.add_eviction_counts(cache_driver.eviction_counters().as_ref().unwrap());
+    // Stop the producers if the clients stopped before the end of the commands.
+    drop(receive);
+    if let Some(producers) = producers {
+        sampled_reads = producers.join()?;
     }
+    report.sampling_rate = config.sampling_rate;
+    report.num_unsampled_reads = sampled_reads.unsampled_reads(report.read_count);
 
     Ok(report)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workload::{ScanPollutionWorkload, Workload};
+
+    #[test]
+    fn count_starvation() {
+        let (send, receive) = crossbeam_channel::bounded(1);
+        let producer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            send.send(vec![Command::Iterate]).unwrap();
+        });
+
+        let mut starvation = Duration::default();
+        assert!(recv_commands(&receive, &mut starvation).is_some());
+        assert!(starvation >= Duration::from_millis(40));
+        producer.join().unwrap();
+
+        // No more commands once the producer has dropped the sender.
+        assert!(recv_commands(&receive, &mut starvation).is_none());
+    }
+
+    #[test]
+    fn report_hot_and_scan_separately() -> anyhow::Result<()> {
+        // 8 rounds of 2,000 hot reads followed by a scan of 500 new keys.
+        let workload = ScanPollutionWorkload::new(0.99, 1_000, 20_000, 500, 2_000, false, 1)?;
+        let trace_file = TraceDescriptor::synthetic(Workload::ScanPollution(workload));
+        let config = Config::new(trace_file, None, None, None, None, None);
+        assert_eq!(config.trace_file.request_classes(), ["hot", "scan"]);
+
+        let report = run_multi_threads_moka_sync(&config, 2_000, 1)?;
+        assert!(report.tenants.is_empty());
+        let [hot, scan] = report.classes[..] else {
+            panic!("Two classes expected, got {}", report.classes.len());
+        };
+        assert_eq!(hot.read_count, 16_000);
+        assert_eq!(scan.read_count, 4_000);
+        assert_eq!(hot.hit_count + scan.hit_count, report.hit_count);
+        // The hot set fits in the cache, while the keys of the scans are read once.
+        assert!(hot.hit_ratio() > 0.9, "{}", hot.hit_ratio());
+        assert_eq!(scan.hit_count, 0);
+
+        let records = report.class_csv_records(config.trace_file.request_classes());
+        assert_eq!(records.len(), 2);
+        assert!(records[0].starts_with("Moka Sync Cache [hot], "));
+        assert!(records[1].starts_with("Moka Sync Cache [scan], "));
+        Ok(())
+    }
+}
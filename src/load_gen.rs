@@ -1,44 +1,1108 @@
+use std::{
+    hash::BuildHasher, io::BufRead, iter::Peekable, sync::Arc, thread::JoinHandle, time::Duration,
+};
+
+use anyhow::Context;
+
+use crossbeam_channel::{Receiver, Sender};
+use itertools::Itertools;
+
 use crate::{
-    config::Config,
-    parser::{GenericTraceParser, TraceParser},
+    cache::DefaultHasher,
+    config::{Config, Interleave, KeyTransform},
+    key_transform::KeyTransformer,
+    op_mix::{OpKind, OpScheduler},
+    parser::{self, TraceEntries, TraceEntry, TraceOperation},
+    trace_file::ParsedEntries,
     Command,
 };
 
-pub(crate) fn generate_commands<I>(
+/// The number of the top bits of the key hashes to compare with the sampling
+/// threshold. The caches use the same hasher, and take their buckets from the low
+/// bits.
+const SAMPLING_BITS: u32 = 24;
+
+/// Generates all commands from the trace (times `repeat`) and buffers them in an
+/// unbounded channel. Also returns the counts of the reads of a sampled trace (see
+/// `produce`).
+///
+/// If the trace has been parsed in advance, the batches of commands are generated
+/// by worker threads in parallel, and buffered in the same order as `produce`
+/// would do.
+pub(crate) fn preload(
+    config: &Config,
+    max_chunk_size: usize,
+) -> anyhow::Result<(Receiver<Vec<Command>>, SampledReads)> {
+    let (send, receive) = crossbeam_channel::unbounded();
+    let sampled_reads = match &config.trace_file.parsed {
+        Some(ParsedEntries(entries)) if !needs_sequential_generation(config) => {
+            generate_in_parallel(config, max_chunk_size, entries, &send)?;
+            SampledReads::default()
+        }
+        _ => produce(config, max_chunk_size, &send)?,
+    };
+    Ok((receive, sampled_reads))
+}
+
+/// Returns `true` if the commands must be generated in the order of the entries
+/// by a single thread: when the trace is replayed by its timestamps, sliced (e.g.
+/// `--skip-requests`), interleaved with other traces, sampled or its keys are
+/// transformed.
+fn needs_sequential_generation(config: &Config) -> bool {
+    config.time_scale.is_some()
+        || config.is_sliced()
+        || config.is_multi_tenant()
+        || config.sampling_rate.is_some()
+        || config.key_transform != KeyTransform::Identity
+}
+
+/// Generates the batches of commands from the entries (times `repeat`) with
+/// worker threads, and sends them in order. Each batch is generated from the
+/// counter at its position, so the injected operations (e.g. `--invalidate`) are
+/// the same as `produce`.
+fn generate_in_parallel(
+    config: &Config,
+    max_chunk_size: usize,
+    entries: &[TraceEntry],
+    send: &Sender<Vec<Command>>,
+) -> anyhow::Result<()> {
+    let jobs = (0..config.repeat.unwrap_or(1) as usize)
+        .flat_map(|i| {
+            entries
+                .chunks(max_chunk_size)
+                .enumerate()
+                .map(move |(j, chunk)| (i * entries.len() + j * max_chunk_size, chunk))
+        })
+        .collect::<Vec<_>>();
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    let jobs_per_thread = jobs.len().div_ceil(crate::available_parallelism());
+    let scheduler = OpScheduler::new(&config.op_mix(), config.op_schedule);
+    let scheduler = &scheduler;
+
+    let batches = std::thread::scope(|s| {
+        let handles = jobs
+            .chunks(jobs_per_thread)
+            .map(|jobs| {
+                s.spawn(move || {
+                    jobs.iter()
+                        .map(|&(mut counter, chunk)| {
+                            let chunk = chunk.iter().cloned().map(Ok);
+                            generate_commands(scheduler, max_chunk_size, &mut counter, None, chunk)
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("A generator thread panicked"))
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?;
+
+    for commands in batches.into_iter().flatten() {
+        if send.send(commands).is_err() {
+            // The clients have stopped before the end of the commands.
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Spawns the producer threads that generate the commands from the trace (times
+/// `repeat`) and send them to a bounded channel, while the clients are consuming
+/// them.
+///
+/// With more than one producer, a reader thread reads the lines of the trace and
+/// the producers parse them in parallel. Then the batches are sent out of order,
+/// and the injected operations (e.g. `--invalidate`) are placed by the line
+/// positions instead of the entry counts. A binary trace is always read
+/// by a single producer as it does not need parsing, and so is a trace whose
+/// commands must be generated in order (see `needs_sequential_generation`).
+pub(crate) fn spawn_producers(
+    config: &Config,
+    max_chunk_size: usize,
+) -> (Receiver<Vec<Command>>, Producers) {
+    let (send, receive) = crossbeam_channel::bounded(config.queue_size);
+    let config = Arc::new(config.clone());
+    let mut handles = Vec::new();
+
+    if config.num_producers <= 1
+        || config.trace_file.format.line_parser().is_none()
+        || needs_sequential_generation(&config)
+    {
+        handles.push(std::thread::spawn(move || {
+            produce(&config, max_chunk_size, &send)
+        }));
+    } else {
+        let (line_send, line_receive) = crossbeam_channel::bounded(config.queue_size);
+        {
+            let config = Arc::clone(&config);
+            handles.push(std::thread::spawn(move || {
+                read_lines(&config, max_chunk_size, &line_send).map(|_| SampledReads::default())
+            }));
+        }
+        for _ in 0..config.num_producers {
+            let config = Arc::clone(&config);
+            let line_receive = line_receive.clone();
+            let send = send.clone();
+            handles.push(std::thread::spawn(move || {
+                parse_lines(&config, &line_receive, &send).map(|_| SampledReads::default())
+            }));
+        }
+    }
+
+    (receive, Producers { handles })
+}
+
+/// The handles of the producer threads.
+pub(crate) struct Producers {
+    handles: Vec<JoinHandle<anyhow::Result<SampledReads>>>,
+}
+
+impl Producers {
+    /// Waits for the producer threads to finish and returns the first error, or
+    /// the counts of the reads of a sampled trace (see `produce`).
+    pub(crate) fn join(self) -> anyhow::Result<SampledReads> {
+        let mut sampled_reads = SampledReads::default();
+        for handle in self.handles {
+            let reads = handle.join().expect("A producer thread panicked")?;
+            sampled_reads.unsampled += reads.unsampled;
+            sampled_reads.sent += reads.sent;
+        }
+        Ok(sampled_reads)
+    }
+}
+
+/// The counts of the reads of a sampled trace. Both are 0 unless the trace is
+/// sampled.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SampledReads {
+    /// The number of keys that would be read without sampling.
+    unsampled: u64,
+    /// The number of sampled keys read by the commands sent to the clients.
+    sent: u64,
+}
+
+impl SampledReads {
+    /// Returns the number of keys that would be read without sampling in the part
+    /// of the trace that the clients ran, reading `read_count` sampled keys. If
+    /// the clients stopped before the end of the commands (e.g. at the end of a
+    /// phase with a duration), the count is scaled down to their reads, as the
+    /// producer has sampled the trace ahead of them.
+    pub(crate) fn unsampled_reads(&self, read_count: u64) -> u64 {
+        if read_count >= self.sent {
+            self.unsampled
+        } else {
+            (self.unsampled as f64 * read_count as f64 / self.sent as f64).round() as u64
+        }
+    }
+}
+
+/// Generates the commands and sends them in batches. Returns the counts of the
+/// reads, which are counted only when the trace is sampled.
+fn produce(
     config: &Config,
     max_chunk_size: usize,
+    send: &Sender<Vec<Command>>,
+) -> anyhow::Result<SampledReads> {
+    let mut counter = 0;
+    let mut unsampled_counter = 0;
+    let mut sampled_reads = SampledReads::default();
+    if config.time_scale.is_some() {
+        config.ensure_timestamps("time-scale")?;
+    }
+    let mut timeline = config.time_scale.map(ReplayTimeline::new);
+    let mut transformer = KeyTransformer::new(config.key_transform);
+    let scheduler = OpScheduler::new(&config.op_mix(), config.op_schedule);
+    'repeat: for i in 0..(config.repeat.unwrap_or(1)) {
+        if let (true, Some(timeline)) = (i > 0, &mut timeline) {
+            timeline.next_repeat();
+        }
+        let entries = sample(
+            config,
+            &scheduler,
+            selected_entries(config)?,
+            &mut unsampled_counter,
+            &mut sampled_reads.unsampled,
+        );
+        let entries = transformer.apply(entries);
+        for chunk in entries.chunks(max_chunk_size).into_iter() {
+            let commands = generate_commands(
+                &scheduler,
+                max_chunk_size,
+                &mut counter,
+                timeline.as_mut(),
+                chunk,
+            )?;
+            let num_reads = if config.sampling_rate.is_some() {
+                num_reads(&commands)
+            } else {
+                0
+            };
+            if send.send(commands).is_err() {
+                // The clients have stopped before the end of the commands (e.g. at
+                // the end of a phase with a duration).
+                break 'repeat;
+            }
+            sampled_reads.sent += num_reads;
+        }
+    }
+    Ok(sampled_reads)
+}
+
+/// Returns the number of keys read by the commands.
+fn num_reads(commands: &[Command]) -> u64 {
+    commands
+        .iter()
+        .map(|command| match command {
+            Command::GetOrInsert(entry) | Command::GetOrInsertOnce(entry) => {
+                entry.range().len() as u64
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Keeps only the keys whose hashes fall under the sampling rate (spatial sampling
+/// of SHARDS), so that every access to a sampled key is kept. An entry with a
+/// range of keys is split into the entries of its sampled keys.
+///
+/// The keys that would be read without sampling are counted into
+/// `num_unsampled_reads`. They are scheduled by `unsampled_counter`, the position
+/// of the key before sampling, as if all keys were sampled; so the injected
+/// operations that do not read are not counted.
+fn sample<'a>(
+    config: &Config,
+    scheduler: &'a OpScheduler,
+    entries: TraceEntries,
+    unsampled_counter: &'a mut usize,
+    num_unsampled_reads: &'a mut u64,
+) -> Box<dyn Iterator<Item = anyhow::Result<TraceEntry>> + 'a> {
+    let Some(rate) = config.sampling_rate else {
+        return Box::new(entries);
+    };
+    let threshold = (rate * (1u64 << SAMPLING_BITS) as f64) as u64;
+    let is_sampled =
+        move |key: usize| DefaultHasher.hash_one(key) >> (u64::BITS - SAMPLING_BITS) < threshold;
+
+    Box::new(entries.flat_map(move |entry| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return vec![Err(e)],
+        };
+        for _ in entry.range() {
+            *unsampled_counter += 1;
+            if is_read(scheduler, *unsampled_counter, entry.operation()) {
+                *num_unsampled_reads += 1;
+            }
+        }
+        if entry.range().len() == 1 {
+            return if is_sampled(entry.range().start) {
+                vec![Ok(entry)]
+            } else {
+                vec![]
+            };
+        }
+        entry
+            .range()
+            .filter(|&key| is_sampled(key))
+            .map(|key| Ok(entry.with_key(key)))
+            .collect()
+    }))
+}
+
+/// Returns the entries of the trace (or the interleaved traces of the tenants)
+/// selected by `from`, `until`, `skip_requests` and `max_requests` of the config,
+/// in this order. `from` and `until` are relative to the timestamp of the first
+/// entry, as some traces record absolute times (e.g. MSR traces). The timestamps in
+/// a trace are assumed to be non-decreasing, so reading stops at the first entry
+/// at or after `until`.
+fn selected_entries(config: &Config) -> anyhow::Result<TraceEntries> {
+    let mut entries = if config.is_multi_tenant() {
+        Box::new(Interleaved::new(config)?)
+    } else {
+        config.trace_file.entries()?
+    };
+    if !config.is_sliced() {
+        return Ok(entries);
+    }
+
+    // Otherwise, the entries without timestamps would all be selected.
+    if config.from.is_some() {
+        config.ensure_timestamps("from")?;
+    }
+    if config.until.is_some() {
+        config.ensure_timestamps("until")?;
+    }
+    if config.from.is_some() || config.until.is_some() {
+        let mut peekable = entries.peekable();
+        let start = match peekable.peek() {
+            Some(Ok(entry)) => entry.timestamp().unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+        entries = Box::new(peekable);
+        if let Some(from) = config.from.map(|from| start + from) {
+            entries = Box::new(entries.skip_while(
+                move |entry| matches!(entry, Ok(e) if e.timestamp().is_some_and(|ts| ts < from)),
+            ));
+        }
+        if let Some(until) = config.until.map(|until| start + until) {
+            entries = Box::new(entries.take_while(
+                move |entry| !matches!(entry, Ok(e) if e.timestamp().is_some_and(|ts| ts >= until)),
+            ));
+        }
+    }
+    if config.skip_requests > 0 {
+        // Do not use `skip` as it would drop the errors in the skipped entries.
+        let mut to_skip = config.skip_requests;
+        entries = Box::new(entries.filter(move |entry| {
+            if entry.is_ok() && to_skip > 0 {
+                to_skip -= 1;
+                false
+            } else {
+                true
+            }
+        }));
+    }
+    if let Some(max) = config.max_requests {
+        entries = Box::new(entries.take(max));
+    }
+    Ok(entries)
+}
+
+/// Interleaves the entries of the traces of the tenants into one stream, moving
+/// their keys into the namespaces of the tenants. When a trace runs out, the rest
+/// are interleaved without it.
+struct Interleaved {
+    sources: Vec<Option<Peekable<TraceEntries>>>,
+    interleave: Interleave,
+    weights: Vec<u32>,
+    /// The current weights of the smooth weighted round-robin.
+    current_weights: Vec<i64>,
+}
+
+impl Interleaved {
+    fn new(config: &Config) -> anyhow::Result<Self> {
+        let sources = config
+            .tenants
+            .iter()
+            .enumerate()
+            .map(|(i, trace)| {
+                let tenant = u8::try_from(i).context("Too many tenants")?;
+                let entries: TraceEntries = Box::new(trace.entries()?.map(move |entry| {
+                    entry.map(|mut entry| {
+                        entry.set_tenant(tenant);
+                        entry
+                    })
+                }));
+                Ok(Some(entries.peekable()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            current_weights: vec![0; sources.len()],
+            sources,
+            interleave: config.interleave,
+            weights: config.tenant_weights.clone(),
+        })
+    }
+
+    /// Returns the index of the source to take the next entry from, or `None` if
+    /// all sources have run out.
+    fn pick(&mut self) -> Option<usize> {
+        match self.interleave {
+            Interleave::Weight => self.pick_by_weight(),
+            Interleave::Timestamp => self.pick_by_timestamp(),
+        }
+    }
+
+    /// Picks the source by the smooth weighted round-robin, which spreads the
+    /// entries of each tenant evenly.
+    fn pick_by_weight(&mut self) -> Option<usize> {
+        let mut total = 0;
+        let mut picked: Option<usize> = None;
+        for (i, source) in self.sources.iter().enumerate() {
+            if source.is_none() {
+                continue;
+            }
+            let weight = self.weights[i] as i64;
+            total += weight;
+            self.current_weights[i] += weight;
+            if picked.is_none_or(|p| self.current_weights[i] > self.current_weights[p]) {
+                picked = Some(i);
+            }
+        }
+        let picked = picked?;
+        self.current_weights[picked] -= total;
+        Some(picked)
+    }
+
+    /// Picks the source whose next entry has the earliest timestamp. An error is
+    /// picked immediately.
+    fn pick_by_timestamp(&mut self) -> Option<usize> {
+        let mut picked = None;
+        let mut earliest = None;
+        for (i, slot) in self.sources.iter_mut().enumerate() {
+            let Some(source) = slot else {
+                continue;
+            };
+            let timestamp = match source.peek() {
+                None => {
+                    *slot = None;
+                    continue;
+                }
+                Some(Err(_)) => return Some(i),
+                Some(Ok(entry)) => entry.timestamp().unwrap_or_default(),
+            };
+            if earliest.is_none_or(|e| timestamp < e) {
+                earliest = Some(timestamp);
+                picked = Some(i);
+            }
+        }
+        picked
+    }
+}
+
+impl Iterator for Interleaved {
+    type Item = anyhow::Result<TraceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let i = self.pick()?;
+            match self.sources[i].as_mut().and_then(Iterator::next) {
+                Some(entry) => return Some(entry),
+                None => self.sources[i] = None,
+            }
+        }
+    }
+}
+
+/// Converts the timestamps of the entries into the times since the start of the
+/// replay. The timeline of the trace starts at the timestamp of its first entry,
+/// and runs `time_scale` times faster in the replay.
+struct ReplayTimeline {
+    time_scale: f64,
+    first_timestamp: Option<Duration>,
+    /// The time of the start of the current repeat on the timeline of the trace.
+    repeat_offset: Duration,
+    /// The latest time on the timeline of the trace so far.
+    latest: Duration,
+}
+
+impl ReplayTimeline {
+    fn new(time_scale: f64) -> Self {
+        Self {
+            time_scale,
+            first_timestamp: None,
+            repeat_offset: Duration::ZERO,
+            latest: Duration::ZERO,
+        }
+    }
+
+    /// Returns the time since the start of the replay when the entry with the
+    /// timestamp is due.
+    fn due(&mut self, timestamp: Duration) -> Duration {
+        let first = *self.first_timestamp.get_or_insert(timestamp);
+        let time = self.repeat_offset + timestamp.saturating_sub(first);
+        self.latest = self.latest.max(time);
+        time.div_f64(self.time_scale)
+    }
+
+    /// Starts the next repeat of the trace one second after the latest entry.
+    fn next_repeat(&mut self) {
+        self.repeat_offset = self.latest + Duration::from_secs(1);
+    }
+}
+
+/// A batch of lines read from a text trace.
+struct LineBatch {
+    /// The number of lines read before this batch, including the previous repeats.
+    counter_base: usize,
+    lines: Vec<(usize, std::io::Result<String>)>,
+}
+
+fn read_lines(
+    config: &Config,
+    max_chunk_size: usize,
+    send: &Sender<LineBatch>,
+) -> anyhow::Result<()> {
+    let mut counter_base = 0;
+    'repeat: for _ in 0..(config.repeat.unwrap_or(1)) {
+        let reader = config.trace_file.open()?;
+        for chunk in reader
+            .lines()
+            .enumerate()
+            .chunks(max_chunk_size)
+            .into_iter()
+        {
+            let lines = chunk.collect::<Vec<_>>();
+            let len = lines.len();
+            let batch = LineBatch {
+                counter_base,
+                lines,
+            };
+            if send.send(batch).is_err() {
+                // The parsers have stopped as the clients did.
+                break 'repeat;
+            }
+            counter_base += len;
+        }
+    }
+    Ok(())
+}
+
+fn parse_lines(
+    config: &Config,
+    receive: &Receiver<LineBatch>,
+    send: &Sender<Vec<Command>>,
+) -> anyhow::Result<()> {
+    let mut parser = config
+        .trace_file
+        .format
+        .line_parser()
+        .expect("A text trace format");
+    let scheduler = OpScheduler::new(&config.op_mix(), config.op_schedule);
+    let source = config.trace_file.path().display().to_string();
+    let lenient = config.trace_file.lenient;
+    while let Ok(batch) = receive.recv() {
+        let mut ops = Vec::with_capacity(batch.lines.len());
+        for (i, (line_number, line)) in batch.lines.into_iter().enumerate() {
+            if let Some(entry) =
+                parser::parse_line(&mut *parser, line, line_number, &source, lenient)
+            {
+                push_commands(&scheduler, batch.counter_base + i + 1, entry?, &mut ops);
+            }
+        }
+        if send.send(ops).is_err() {
+            // The clients have stopped before the end of the commands.
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn generate_commands<I>(
+    scheduler: &OpScheduler,
+    max_chunk_size: usize,
     counter: &mut usize,
+    mut timeline: Option<&mut ReplayTimeline>,
     chunk: I,
 ) -> anyhow::Result<Vec<Command>>
 where
-    I: Iterator<Item = std::io::Result<(usize, String)>>,
+    I: Iterator<Item = anyhow::Result<TraceEntry>>,
 {
-    let mut parser = GenericTraceParser;
     let mut ops = Vec::with_capacity(max_chunk_size);
-    for line_result in chunk {
-        let (line_number, line) = line_result?;
-        let Some(entry) = parser.parse(&line, line_number)? else {
-            continue;
-        };
+    // Every batch starts with a wait, as the batches are processed by different
+    // clients.
+    let mut last_due = None;
+    for entry in chunk {
+        let entry = entry?;
+        if let (Some(timeline), Some(timestamp)) = (&mut timeline, entry.timestamp()) {
+            let due = timeline.due(timestamp);
+            if last_due != Some(due) {
+                ops.push(Command::WaitUntil(due));
+                last_due = Some(due);
+            }
+        }
         *counter += 1;
-        if config.invalidate_all && *counter % 100_000 == 0 {
+        push_commands(scheduler, *counter, entry, &mut ops);
+    }
+    Ok(ops)
+}
+
+/// Pushes the commands for the entry. `counter` is the position of the entry in
+/// the selected part of the trace (times `repeat`), starting from 1. It is used to
+/// schedule the injected operations.
+fn push_commands(
+    scheduler: &OpScheduler,
+    counter: usize,
+    entry: TraceEntry,
+    ops: &mut Vec<Command>,
+) {
+    // Writes and deletes recorded in the trace take precedence over the
+    // injected operations.
+    match (entry.operation(), scheduler.operation(counter)) {
+        (Some(TraceOperation::Write), _) => ops.push(Command::Update(entry)),
+        (Some(TraceOperation::Delete), _) => ops.push(Command::Invalidate(entry)),
+        (_, Some(OpKind::InvalidateAll)) => {
             ops.push(Command::InvalidateAll);
             ops.push(Command::GetOrInsert(entry));
-        } else if config.invalidate_entries_if && *counter % 5_000 == 0 {
-            ops.push(Command::InvalidateEntriesIf(entry));
-        } else if config.size_aware && *counter % 11 == 0 {
-            ops.push(Command::Update(entry));
-        } else if config.invalidate && *counter % 8 == 0 {
-            ops.push(Command::Invalidate(entry));
-        } else if config.insert_once && *counter % 3 == 0 {
-            ops.push(Command::GetOrInsertOnce(entry));
-        } else {
-            ops.push(Command::GetOrInsert(entry));
         }
+        (_, Some(OpKind::InvalidateEntriesIf)) => ops.push(Command::InvalidateEntriesIf(entry)),
+        (_, Some(OpKind::Update)) => ops.push(Command::Update(entry)),
+        (_, Some(OpKind::Invalidate)) => ops.push(Command::Invalidate(entry)),
+        (_, Some(OpKind::InsertOnce)) => ops.push(Command::GetOrInsertOnce(entry)),
+        (_, None | Some(OpKind::Iterate)) => ops.push(Command::GetOrInsert(entry)),
+    }
+
+    if scheduler.iterates(counter) {
+        ops.push(Command::Iterate);
+    }
+}
+
+/// Returns `true` if `push_commands` turns the request into a read.
+fn is_read(scheduler: &OpScheduler, counter: usize, operation: Option<TraceOperation>) -> bool {
+    match (operation, scheduler.operation(counter)) {
+        (Some(TraceOperation::Write | TraceOperation::Delete), _) => false,
+        (_, Some(OpKind::InvalidateEntriesIf | OpKind::Update | OpKind::Invalidate)) => false,
+        (_, None | Some(OpKind::InvalidateAll | OpKind::InsertOnce | OpKind::Iterate)) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        cache::CacheKey,
+        op_mix::OpSchedule,
+        parser::{TraceParser, TwitterTraceParser},
+        report::Report,
+        trace_file::{TraceDescriptor, TraceRegistry},
+        workload::{Workload, YcsbKind, YcsbWorkload},
+    };
+
+    /// Writes an ARC trace with `num_lines` one-block requests and returns its
+    /// config, repeated twice.
+    fn arc_config(dir: &tempfile::TempDir, num_lines: usize) -> anyhow::Result<Config> {
+        let path = dir.path().join("trace.lis");
+        let trace = (0..num_lines)
+            .map(|i| format!("{} 1 0 0\n", i % 300))
+            .collect::<String>();
+        std::fs::write(&path, trace)?;
+        let trace_file = TraceDescriptor::from_path(&path)?;
+        Ok(Config::new(trace_file, None, None, None, Some(2), None))
+    }
+
+    fn sorted_keys(receive: &Receiver<Vec<Command>>) -> Vec<usize> {
+        let mut keys = receive
+            .iter()
+            .flatten()
+            .map(|command| match command {
+                Command::GetOrInsert(entry) => entry.range().start,
+                _ => panic!("Unexpected command"),
+            })
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn stream_all_commands() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut config = arc_config(&dir, 1_000)?;
+        let expected = sorted_keys(&preload(&config, 64)?.0);
+        assert_eq!(expected.len(), 2_000);
+
+        config.set_streaming(true);
+        config.set_queue_size(2);
+        for num_producers in [1, 3] {
+            config.set_num_producers(num_producers);
+            let (receive, producers) = spawn_producers(&config, 64);
+            assert_eq!(sorted_keys(&receive), expected);
+            producers.join()?;
+        }
+        Ok(())
+    }
 
-        if config.iterate && *counter % 50_000 == 0 {
-            ops.push(Command::Iterate);
+    #[test]
+    fn stop_producers_early() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut config = arc_config(&dir, 1_000)?;
+        config.set_streaming(true);
+        config.set_queue_size(1);
+        for num_producers in [1, 3] {
+            config.set_num_producers(num_producers);
+            let (receive, producers) = spawn_producers(&config, 64);
+            assert!(receive.recv().is_ok());
+            // The clients stop before the end of the commands.
+            drop(receive);
+            producers.join()?;
         }
+        Ok(())
+    }
+
+    fn describe(batches: impl IntoIterator<Item = Vec<Command>>) -> Vec<Vec<String>> {
+        batches
+            .into_iter()
+            .map(|commands| {
+                commands
+                    .iter()
+                    .map(|command| {
+                        let kind = match command {
+                            Command::GetOrInsert(_) => "get",
+                            Command::GetOrInsertOnce(_) => "get_once",
+                            Command::Update(_) => "update",
+                            Command::Invalidate(_) => "invalidate",
+                            Command::InvalidateAll => "invalidate_all",
+                            Command::InvalidateEntriesIf(_) => "invalidate_if",
+                            Command::Iterate => "iterate",
+                            Command::WaitUntil(_) => "wait",
+                        };
+                        match command.entry() {
+                            Some(entry) => {
+                                format!("{kind} {}@{}", entry.range().start, entry.line_number())
+                            }
+                            None => kind.to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generate_in_parallel_as_produce() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut config = arc_config(&dir, 3_000)?;
+        config.set_insert_once(true);
+        config.set_invalidate(true);
+        config.set_invalidate_entries_if(true);
+
+        let (send, receive) = crossbeam_channel::unbounded();
+        produce(&config, 64, &send)?;
+        drop(send);
+        let expected = describe(receive);
+        assert_eq!(expected.iter().map(Vec::len).sum::<usize>(), 6_000);
+        for kind in ["get ", "get_once ", "invalidate ", "invalidate_if "] {
+            assert!(
+                expected.iter().flatten().any(|c| c.starts_with(kind)),
+                "{kind}"
+            );
+        }
+
+        config.trace_file.parse_entries()?;
+        let Some(ParsedEntries(entries)) = &config.trace_file.parsed else {
+            unreachable!()
+        };
+        let (send, receive) = crossbeam_channel::unbounded();
+        generate_in_parallel(&config, 64, entries, &send)?;
+        drop(send);
+        assert_eq!(describe(receive), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn replay_twitter_operations() -> anyhow::Result<()> {
+        let config = Config::new(
+            TraceRegistry::builtin().get("s3")?,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let entries = ["get", "gets", "set", "add", "replace", "delete"]
+            .iter()
+            .enumerate()
+            .map(|(i, op)| {
+                let line = format!("{i},key{i},4,100,1,{op},0");
+                Ok(TwitterTraceParser.parse(&line, i)?.expect("An entry"))
+            });
+
+        let scheduler = OpScheduler::new(&config.op_mix(), config.op_schedule);
+        let commands = generate_commands(&scheduler, 10, &mut 0, None, entries)?;
+        let kinds = commands
+            .iter()
+            .map(|command| match command {
+                Command::GetOrInsert(_) => "get_or_insert",
+                Command::Update(_) => "update",
+                Command::Invalidate(_) => "invalidate",
+                _ => "other",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                "get_or_insert",
+                "get_or_insert",
+                "update",
+                "update",
+                "update",
+                "invalidate"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn replay_timeline() {
+        let secs = Duration::from_secs;
+        let mut timeline = ReplayTimeline::new(2.0);
+        // The timeline starts at the first timestamp and runs twice as fast.
+        assert_eq!(timeline.due(secs(100)), secs(0));
+        assert_eq!(timeline.due(secs(104)), secs(2));
+        assert_eq!(timeline.due(secs(110)), secs(5));
+        assert_eq!(timeline.due(secs(99)), secs(0));
+
+        // The next repeat starts one second after the latest entry.
+        timeline.next_repeat();
+        assert_eq!(timeline.due(secs(100)), Duration::from_millis(5_500));
+        assert_eq!(timeline.due(secs(104)), Duration::from_millis(7_500));
+    }
+
+    #[test]
+    fn pace_by_timestamps() -> anyhow::Result<()> {
+        let config = Config::new(
+            TraceRegistry::builtin().get("s3")?,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let entries = [0, 0, 1, 3].iter().enumerate().map(|(i, timestamp)| {
+            let line = format!("{timestamp},key{i},4,100,1,get,0");
+            Ok(TwitterTraceParser.parse(&line, i)?.expect("An entry"))
+        });
+
+        let scheduler = OpScheduler::new(&config.op_mix(), config.op_schedule);
+        let mut timeline = ReplayTimeline::new(10.0);
+        let commands = generate_commands(&scheduler, 10, &mut 0, Some(&mut timeline), entries)?;
+        let kinds = commands
+            .iter()
+            .map(|command| match command {
+                Command::WaitUntil(due) => format!("wait {}ms", due.as_millis()),
+                Command::GetOrInsert(_) => "get".to_string(),
+                _ => "other".to_string(),
+            })
+            .collect::<Vec<_>>();
+        // One wait for the entries with the same timestamp.
+        assert_eq!(
+            kinds,
+            [
+                "wait 0ms",
+                "get",
+                "get",
+                "wait 100ms",
+                "get",
+                "wait 300ms",
+                "get"
+            ]
+        );
+        Ok(())
+    }
+
+    /// Writes a Twitter trace with a request per second for 10 seconds and returns
+    /// its config.
+    fn twitter_config(dir: &tempfile::TempDir) -> anyhow::Result<Config> {
+        let path = dir.path().join("cluster.csv");
+        let trace = (0..10)
+            .map(|i| format!("{i},key{i},4,100,1,get,0\n"))
+            .collect::<String>();
+        std::fs::write(&path, trace)?;
+        let trace_file = TraceDescriptor::from_path(&path)?;
+        Ok(Config::new(trace_file, None, None, None, None, None))
+    }
+
+    fn selected_timestamps(config: &Config) -> anyhow::Result<Vec<u64>> {
+        selected_entries(config)?
+            .map(|entry| Ok(entry?.timestamp().expect("A timestamp").as_secs()))
+            .collect()
+    }
+
+    #[test]
+    fn select_slices() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut config = twitter_config(&dir)?;
+        assert_eq!(selected_timestamps(&config)?, (0..10).collect::<Vec<_>>());
+
+        config.set_time_window(Some(Duration::from_secs(2)), Some(Duration::from_secs(8)));
+        assert_eq!(selected_timestamps(&config)?, [2, 3, 4, 5, 6, 7]);
+
+        // The requests are skipped and limited after the time window.
+        config.set_skip_requests(1);
+        config.set_max_requests(Some(3));
+        assert_eq!(selected_timestamps(&config)?, [3, 4, 5]);
+
+        config.set_time_window(None, None);
+        assert_eq!(selected_timestamps(&config)?, [1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn select_time_window_from_first_request() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let timestamps = (1_000..1_010).collect::<Vec<_>>();
+        let trace = twitter_trace(&dir, "absolute.csv", &timestamps)?;
+        let mut config = Config::new(trace, None, None, None, None, None);
+        config.set_time_window(Some(Duration::from_secs(2)), Some(Duration::from_secs(5)));
+        assert_eq!(selected_timestamps(&config)?, [1_002, 1_003, 1_004]);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_time_window_without_timestamps() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut config = arc_config(&dir, 10)?;
+        config.set_time_window(None, Some(Duration::from_secs(1)));
+        let err = selected_entries(&config).err().expect("An error");
+        assert!(err.to_string().contains("no timestamps"));
+        Ok(())
+    }
+
+    /// The operations injected periodically are placed by the positions in the
+    /// selected part of the trace.
+    #[test]
+    fn restart_counter_at_first_selected_request() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut config = arc_config(&dir, 20)?;
+        config.set_invalidate(true);
+        config.set_skip_requests(5);
+
+        let invalidated = preload(&config, 64)?
+            .0
+            .iter()
+            .flatten()
+            .filter_map(|command| match command {
+                Command::Invalidate(entry) => Some(entry.range().start),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // The 8th of the 15 requests from key 5, and then the 16th and 24th, which
+        // are the 1st and 9th of the second repeat.
+        assert_eq!(invalidated, [12, 5, 13]);
+        Ok(())
+    }
+
+    /// Writes a Twitter trace with the timestamps and returns its descriptor.
+    fn twitter_trace(
+        dir: &tempfile::TempDir,
+        name: &str,
+        timestamps: &[u64],
+    ) -> anyhow::Result<TraceDescriptor> {
+        let path = dir.path().join(name);
+        let trace = timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, ts)| format!("{ts},key{i},4,100,1,get,0\n"))
+            .collect::<String>();
+        std::fs::write(&path, trace)?;
+        TraceDescriptor::from_path(&path)
+    }
+
+    fn interleave(
+        tenants: Vec<TraceDescriptor>,
+        weights: Vec<u32>,
+        interleave: Interleave,
+    ) -> anyhow::Result<Vec<TraceEntry>> {
+        let mut config = Config::new(
+            TraceDescriptor::multi_tenant(&tenants),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        config.set_tenants(tenants, weights, interleave);
+        Interleaved::new(&config)?.collect()
+    }
+
+    #[test]
+    fn interleave_by_weight() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let a = twitter_trace(&dir, "a.csv", &[0; 6])?;
+        let b = twitter_trace(&dir, "b.csv", &[0; 6])?;
+        let entries = interleave(vec![a, b], vec![2, 1], Interleave::Weight)?;
+        let tenants = entries
+            .iter()
+            .map(|e| e.tenant().expect("A tenant"))
+            .collect::<Vec<_>>();
+        // Two requests of the first tenant for each of the second, spread evenly,
+        // and then the rest of the second.
+        assert_eq!(tenants, [0, 1, 0, 0, 1, 0, 0, 1, 0, 1, 1, 1]);
+
+        // The same key of the tenants is in their own namespaces.
+        assert_eq!(entries[0].key(), Some("0:key0"));
+        assert_eq!(entries[1].key(), Some("1:key0"));
+        let config = arc_config(&dir, 1)?;
+        let keys = entries[..2]
+            .iter()
+            .map(|e| <usize as CacheKey>::new(&config, e, e.range().start))
+            .collect::<Vec<_>>();
+        assert_eq!(entries[0].range(), entries[1].range());
+        assert_ne!(keys[0], keys[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn interleave_by_timestamp() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let a = twitter_trace(&dir, "a.csv", &[0, 2, 4])?;
+        let b = twitter_trace(&dir, "b.csv", &[1, 1, 3, 10])?;
+        let entries = interleave(vec![a, b], Vec::new(), Interleave::Timestamp)?;
+        let order = entries
+            .iter()
+            .map(|e| {
+                let tenant = e.tenant().expect("A tenant");
+                (tenant, e.timestamp().expect("A timestamp").as_secs())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [(0, 0), (1, 1), (1, 1), (0, 2), (1, 3), (0, 4), (1, 10)]
+        );
+        Ok(())
+    }
+
+    /// Runs the commands on an unbounded cache and returns the report.
+    fn run(commands: Receiver<Vec<Command>>) -> Report {
+        let mut cache = HashSet::new();
+        let mut report = Report::default();
+        for command in commands.into_iter().flatten() {
+            match command {
+                Command::GetOrInsert(entry) | Command::GetOrInsertOnce(entry) => {
+                    for key in entry.range() {
+                        report.read_count += 1;
+                        if !cache.insert(key) {
+                            report.hit_count += 1;
+                        }
+                    }
+                }
+                Command::Update(entry) => cache.extend(entry.range()),
+                Command::Invalidate(entry) | Command::InvalidateEntriesIf(entry) => {
+                    entry.range().for_each(|key| {
+                        cache.remove(&key);
+                    });
+                }
+                Command::InvalidateAll => cache.clear(),
+                Command::Iterate | Command::WaitUntil(_) => (),
+            }
+        }
+        report
+    }
+
+    #[test]
+    fn estimate_all_sampled_with_op_mix() -> anyhow::Result<()> {
+        // Workload E has scans of ranges of keys, and inserts.
+        let workload = YcsbWorkload::new(YcsbKind::E, 0.99, 1_000, 10_000, 1)?;
+        let mut config = Config::new(
+            TraceDescriptor::synthetic(Workload::Ycsb(workload)),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        config.set_sampling_rate(Some(1.0));
+        config.set_op_mix(Some(
+            "invalidate=5%,update=1/7,invalidate-all=1/5000,insert-once=2%".parse()?,
+        ));
+
+        for schedule in [OpSchedule::Periodic, OpSchedule::Random(7)] {
+            config.op_schedule = schedule;
+            let (commands, sampled_reads) = preload(&config, 100)?;
+            let mut report = run(commands);
+            report.sampling_rate = config.sampling_rate;
+            report.num_unsampled_reads = sampled_reads.unsampled_reads(report.read_count);
+
+            assert_eq!(report.num_unsampled_reads, report.read_count);
+            assert!(report.hit_count > 0 && report.hit_count < report.read_count);
+            assert_eq!(report.estimated_hit_ratio(), Some(report.hit_ratio()));
+        }
+        Ok(())
     }
-    Ok(ops)
 }
@@ -1,11 +1,20 @@
 use anyhow::Context;
 use mokabench::{
     self,
-    config::{Config, RemovalNotificationMode},
-    Report, TraceFile,
+    config::{Config, Interleave, KeyTransform, KeyType, RemovalNotificationMode},
+    op_mix::{OpKind, OpMix, OpSchedule},
+    workload::{
+        PhasedWorkload, ScanPollutionWorkload, ThunderingHerdWorkload, Workload, YcsbKind,
+        YcsbWorkload, ZipfWorkload,
+    },
+    CsvColumns, Report, TraceDescriptor, TraceRegistry,
 };
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
 #[cfg(feature = "rt-tokio")]
 #[tokio::main]
@@ -20,19 +29,27 @@ async fn main() -> anyhow::Result<()> {
 }
 
 async fn run(async_rt_name: &str) -> anyhow::Result<()> {
-    let (trace_files, mut config) = create_config()?;
+    let matches = create_app().get_matches();
+    match matches.subcommand() {
+        Some((SUBCOMMAND_CONVERT, matches)) => return convert(matches),
+        Some((SUBCOMMAND_ANALYZE, matches)) => return analyze(matches),
+        Some((SUBCOMMAND_VALIDATE, matches)) => return validate(matches),
+        _ => (),
+    }
+
+    let (trace_files, mut config) = create_config(&matches)?;
 
     println!("Async runtime: {async_rt_name}");
 
     for trace_file in trace_files {
         config.trace_file = trace_file;
+        if config.parse_once {
+            config.parse_traces()?;
+        }
         println!("{config:?}");
         println!();
 
-        println!(
-            "{}",
-            Report::cvs_header(config.is_eviction_listener_enabled())
-        );
+        println!("{}", Report::cvs_header(csv_columns(&config)));
 
         for capacity in config.trace_file.default_capacities() {
             run_with_capacity(&config, *capacity).await?
@@ -43,6 +60,7 @@ async fn run(async_rt_name: &str) -> anyhow::Result<()> {
 }
 
 async fn run_with_capacity(config: &Config, capacity: usize) -> anyhow::Result<()> {
+    let capacity = config.sampled_capacity(capacity);
     const DEFAULT_NUM_CLIENTS_ARRAY: &[u16] = &[16, 24, 32, 40, 48];
 
     let num_clients_slice: &[u16] = if let Some(n) = &config.num_clients {
@@ -55,102 +73,106 @@ async fn run_with_capacity(config: &Config, capacity: usize) -> anyhow::Result<(
     // as it doesn't use the producer/consumer thread pattern as the other caches.
 
     #[cfg(any(feature = "mini-moka", feature = "moka-v08", feature = "moka-v09"))]
-    if !config.insert_once && !config.is_eviction_listener_enabled() {
+    if !config.injects(OpKind::InsertOnce) && !config.is_eviction_listener_enabled() {
         let report = mokabench::run_single(config, capacity)?;
-        println!("{}", report.to_csv_record());
+        print_report(config, &report);
     }
 
     #[cfg(feature = "hashlink")]
-    if !config.insert_once
+    if !config.injects(OpKind::InsertOnce)
         && !config.size_aware
-        && !config.invalidate_entries_if
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
     {
         for num_clients in num_clients_slice {
             let report = mokabench::run_multi_threads_hashlink(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            print_report(config, &report);
         }
     }
 
     #[cfg(feature = "quick_cache")]
-    if !config.insert_once
+    if !config.injects(OpKind::InsertOnce)
         && !config.size_aware
-        && !config.invalidate_entries_if
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
     {
         for num_clients in num_clients_slice {
             let report = mokabench::run_multi_threads_quick_cache(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            print_report(config, &report);
         }
     }
 
     #[cfg(feature = "light-cache")]
-    if !config.insert_once
+    if !config.injects(OpKind::InsertOnce)
         && !config.size_aware
-        && !config.invalidate_entries_if
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
     {
         for num_clients in num_clients_slice {
             let report = mokabench::run_multi_threads_light_cache(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            print_report(config, &report);
         }
     }
 
     #[cfg(feature = "light-cache-lru")]
-    if !config.insert_once
+    if !config.injects(OpKind::InsertOnce)
         && !config.size_aware
-        && !config.invalidate_entries_if
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
     {
         for num_clients in num_clients_slice {
-            let report = mokabench::run_multi_threads_light_cache_lru(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            let report =
+                mokabench::run_multi_threads_light_cache_lru(config, capacity, *num_clients)?;
+            print_report(config, &report);
         }
     }
 
     #[cfg(feature = "stretto")]
-    if !config.insert_once
+    if !config.injects(OpKind::InsertOnce)
         && !config.size_aware
-        && !config.invalidate_entries_if
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
     {
         for num_clients in num_clients_slice {
             let report = mokabench::run_multi_threads_stretto(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            print_report(config, &report);
         }
     }
 
+    // TinyUFO does not support invalidating entries.
     #[cfg(feature = "tiny-ufo")]
-    if !config.insert_once
+    if !config.injects(OpKind::InsertOnce)
         && !config.size_aware
-        && !config.invalidate_entries_if
+        && !config.injects(OpKind::Invalidate)
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
+        && !config.trace_file.has_operations()
     {
         for num_clients in num_clients_slice {
             let report = mokabench::run_multi_threads_tiny_ufo(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            print_report(config, &report);
         }
     }
 
     #[cfg(any(feature = "mini-moka", feature = "moka-v08", feature = "moka-v09"))]
-    if !config.insert_once
-        && !config.invalidate_entries_if
+    if !config.injects(OpKind::InsertOnce)
+        && !config.injects(OpKind::InvalidateEntriesIf)
         && !config.is_eviction_listener_enabled()
     {
         for num_clients in num_clients_slice {
             let report = mokabench::run_multi_threads_moka_dash(config, capacity, *num_clients)?;
-            println!("{}", report.to_csv_record());
+            print_report(config, &report);
         }
     }
 
     for num_clients in num_clients_slice {
         let report = mokabench::run_multi_threads_moka_sync(config, capacity, *num_clients)?;
-        println!("{}", report.to_csv_record());
+        print_report(config, &report);
     }
 
     for num_clients in num_clients_slice {
         let report = mokabench::run_multi_tasks_moka_async(config, capacity, *num_clients).await?;
-        println!("{}", report.to_csv_record());
+        print_report(config, &report);
     }
 
     let num_segments = 8;
@@ -162,14 +184,122 @@ async fn run_with_capacity(config: &Config, capacity: usize) -> anyhow::Result<(
             *num_clients,
             num_segments,
         )?;
-        println!("{}", report.to_csv_record());
+        print_report(config, &report);
     }
 
     Ok(())
 }
 
+/// Returns the optional columns of the reports of the runs with the config.
+fn csv_columns(config: &Config) -> CsvColumns {
+    CsvColumns {
+        eviction_counts: config.is_eviction_listener_enabled(),
+        starvation: config.streaming,
+        sampling: config.sampling_rate.is_some(),
+        throughput: !config.trace_file.phases().is_empty(),
+        contention: config.trace_file.thundering_herd().is_some(),
+    }
+}
+
+fn print_report(config: &Config, report: &Report) {
+    debug_assert_eq!(
+        report.csv_columns(),
+        csv_columns(config),
+        "The columns of the report do not match the header"
+    );
+    println!("{}", report.to_csv_record());
+    for record in report.phase_csv_records() {
+        println!("{record}");
+    }
+    if config.is_multi_tenant() {
+        let names = config
+            .tenants
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        for record in report.tenant_csv_records(&names) {
+            println!("{record}");
+        }
+    }
+    for record in report.class_csv_records(config.trace_file.request_classes()) {
+        println!("{record}");
+    }
+}
+
+fn convert(matches: &ArgMatches) -> anyhow::Result<()> {
+    let input = Path::new(matches.value_of(ARG_INPUT).unwrap());
+    let output = Path::new(matches.value_of(ARG_OUTPUT).unwrap());
+    let format = matches
+        .value_of(OPTION_FORMAT)
+        .map(str::parse)
+        .transpose()?;
+
+    let instant = Instant::now();
+    let num_records = mokabench::convert_trace(input, format, output)?;
+    println!(
+        "Converted {} records from {} to {} in {:.3} secs",
+        num_records,
+        input.display(),
+        output.display(),
+        instant.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn validate(matches: &ArgMatches) -> anyhow::Result<()> {
+    let trace = Path::new(matches.value_of(ARG_TRACE).unwrap());
+    let format = matches
+        .value_of(OPTION_FORMAT)
+        .map(str::parse)
+        .transpose()?;
+
+    let report = mokabench::validate_trace(trace, format)?;
+    println!("{report}");
+    if !report.is_valid() {
+        anyhow::bail!(
+            "Found {} problems in {}",
+            report.num_problems,
+            trace.display()
+        );
+    }
+    Ok(())
+}
+
+fn analyze(matches: &ArgMatches) -> anyhow::Result<()> {
+    let trace = Path::new(matches.value_of(ARG_TRACE).unwrap());
+    let format = matches
+        .value_of(OPTION_FORMAT)
+        .map(str::parse)
+        .transpose()?;
+    let window_size = match matches.value_of(OPTION_WINDOW_SIZE) {
+        None => mokabench::DEFAULT_WINDOW_SIZE,
+        Some(v) => v
+            .parse()
+            .with_context(|| format!(r#"Cannot parse window-size "{v}" as a positive integer"#))?,
+    };
+
+    let instant = Instant::now();
+    let analysis = mokabench::analyze_trace(trace, format, window_size)?;
+    println!("Trace: {}", trace.display());
+    println!("{analysis}");
+    println!();
+    println!("Analyzed in {:.3} secs", instant.elapsed().as_secs_f64());
+    Ok(())
+}
+
+const SUBCOMMAND_CONVERT: &str = "convert";
+const SUBCOMMAND_ANALYZE: &str = "analyze";
+const SUBCOMMAND_VALIDATE: &str = "validate";
+const ARG_TRACE: &str = "TRACE";
+const OPTION_WINDOW_SIZE: &str = "window-size";
+const ARG_INPUT: &str = "INPUT";
+const ARG_OUTPUT: &str = "OUTPUT";
+const OPTION_FORMAT: &str = "format";
+
 const OPTION_TRACE_FILE: &str = "trace-file";
 const OPTION_TRACE_FILES: &str = "trace-files";
+const OPTION_TRACE_PATH: &str = "trace-path";
+const OPTION_TRACE_MANIFEST: &str = "trace-manifest";
 const OPTION_TTL: &str = "ttl";
 const OPTION_TTI: &str = "tti";
 const OPTION_NUM_CLIENTS: &str = "num-clients";
@@ -180,7 +310,37 @@ const OPTION_INVALIDATE_ALL: &str = "invalidate-all";
 const OPTION_INVALIDATE_IF: &str = "invalidate-entries-if";
 const OPTION_ITERATE: &str = "iterate";
 const OPTION_SIZE_AWARE: &str = "size-aware";
+const OPTION_OP_MIX: &str = "op-mix";
+const OPTION_OP_SCHEDULE: &str = "op-schedule";
+const OPTION_KEY_TYPE: &str = "key-type";
+const OPTION_KEY_LEN: &str = "key-len";
+const OPTION_KEY_TRANSFORM: &str = "key-transform";
 const OPTION_REPEAT: &str = "repeat";
+const OPTION_STREAMING: &str = "streaming";
+const OPTION_PARSE_ONCE: &str = "parse-once";
+const OPTION_NUM_PRODUCERS: &str = "num-producers";
+const OPTION_QUEUE_SIZE: &str = "queue-size";
+const OPTION_TIME_SCALE: &str = "time-scale";
+const OPTION_SKIP_REQUESTS: &str = "skip-requests";
+const OPTION_MAX_REQUESTS: &str = "max-requests";
+const OPTION_FROM: &str = "from";
+const OPTION_MULTI_TENANT: &str = "multi-tenant";
+const OPTION_SAMPLING_RATE: &str = "sampling-rate";
+const OPTION_TENANT_WEIGHTS: &str = "tenant-weights";
+const OPTION_INTERLEAVE: &str = "interleave";
+const OPTION_UNTIL: &str = "until";
+const OPTION_LENIENT: &str = "lenient";
+const OPTION_WORKLOAD: &str = "workload";
+const OPTION_WORKLOAD_FILE: &str = "workload-file";
+const OPTION_ZIPF_THETA: &str = "zipf-theta";
+const OPTION_NUM_KEYS: &str = "num-keys";
+const OPTION_NUM_REQUESTS: &str = "num-requests";
+const OPTION_SEED: &str = "seed";
+const OPTION_SCAN_LENGTH: &str = "scan-length";
+const OPTION_SCAN_INTERVAL: &str = "scan-interval";
+const OPTION_SCAN_LOOP: &str = "scan-loop";
+const OPTION_HERD_KEYS: &str = "herd-keys";
+const OPTION_HERD_ROUNDS: &str = "herd-rounds";
 
 // Since Moka v0.9.0
 const OPTION_EVICTION_LISTENER: &str = "eviction-listener";
@@ -191,8 +351,82 @@ const OPTION_ENTRY_API: &str = "entry-api";
 // Since Moka v0.11.0
 const OPTION_PER_KEY_EXPIRATION: &str = "per-key-expiration";
 
-fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
+fn create_app() -> Command<'static> {
     let mut app = Command::new("Moka Bench")
+        .subcommand(
+            Command::new(SUBCOMMAND_CONVERT)
+                .about("Converts a trace file into the compact binary format")
+                .arg(
+                    Arg::new(ARG_INPUT)
+                        .help("The trace file to convert, or \"-\" for the standard input")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new(ARG_OUTPUT)
+                        .help("The path of the binary trace file to write")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new(OPTION_FORMAT)
+                        .long(OPTION_FORMAT)
+                        .help(
+                            "The format of the input trace: \"arc\", \"lirs\", \"twitter\" \
+                            or \"msr\". Detected from its contents if omitted",
+                        )
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new(SUBCOMMAND_ANALYZE)
+                .about(
+                    "Analyzes the access pattern of a trace file and suggests the \
+                    capacities to benchmark with",
+                )
+                .arg(
+                    Arg::new(ARG_TRACE)
+                        .help("The trace file to analyze, or \"-\" for the standard input")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new(OPTION_FORMAT)
+                        .long(OPTION_FORMAT)
+                        .help(
+                            "The format of the trace: \"arc\", \"lirs\", \"twitter\", \
+                            \"msr\" or \"binary\". Detected from its contents if omitted",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(OPTION_WINDOW_SIZE)
+                        .long(OPTION_WINDOW_SIZE)
+                        .help(
+                            "The number of requests in a window to measure the working \
+                            set size in. default: 100000",
+                        )
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new(SUBCOMMAND_VALIDATE)
+                .about(
+                    "Reads a whole trace file and reports its malformed lines with \
+                    their line numbers",
+                )
+                .arg(
+                    Arg::new(ARG_TRACE)
+                        .help("The trace file to validate, or \"-\" for the standard input")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new(OPTION_FORMAT)
+                        .long(OPTION_FORMAT)
+                        .help(
+                            "The format of the trace: \"arc\", \"lirs\", \"twitter\", \
+                            \"msr\" or \"binary\". Detected from its contents if omitted",
+                        )
+                        .takes_value(true),
+                ),
+        )
         .arg(
             Arg::new(OPTION_TRACE_FILE)
                 .alias(OPTION_TRACE_FILES)
@@ -205,6 +439,143 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
                 .multiple_values(true)
                 .use_value_delimiter(true),
         )
+        .arg(
+            Arg::new(OPTION_TRACE_PATH)
+                .long(OPTION_TRACE_PATH)
+                .help(
+                    "The path to an arbitrary trace file, or \"-\" to read it from the \
+                    standard input. The trace format is detected from its contents",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with(OPTION_TRACE_FILE),
+        )
+        .arg(
+            Arg::new(OPTION_WORKLOAD)
+                .long(OPTION_WORKLOAD)
+                .help(
+                    "Run with a synthetic workload instead of a trace file: \"zipf\" \
+                    (reads of Zipf-distributed keys), \"ycsb-a\" to \"ycsb-f\" (the \
+                    YCSB core workloads), \"scan-pollution\" (reads of a Zipf-distributed \
+                    hot set with periodic scans), or \"thundering-herd\" (all clients \
+                    reading the same missing keys at once by get_with)",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&[OPTION_TRACE_FILE, OPTION_TRACE_PATH, OPTION_LENIENT]),
+        )
+        .arg(
+            Arg::new(OPTION_WORKLOAD_FILE)
+                .long(OPTION_WORKLOAD_FILE)
+                .help(
+                    "Run with a workload of phases described in a TOML file. Each phase \
+                    has its own key distribution, key range, op mix and number of \
+                    clients, and is reported in its own row",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    OPTION_TRACE_FILE,
+                    OPTION_TRACE_PATH,
+                    OPTION_WORKLOAD,
+                    OPTION_LENIENT,
+                ]),
+        )
+        .arg(
+            Arg::new(OPTION_ZIPF_THETA)
+                .long(OPTION_ZIPF_THETA)
+                .help(
+                    "The skew of the Zipf distribution of the synthetic workload in \
+                    [0, 1). Give several values \
+                    (e.g. 0.6,0.8,0.99) to run with each of them. default: 0.99",
+                )
+                .takes_value(true)
+                .multiple_values(true)
+                .use_value_delimiter(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_NUM_KEYS)
+                .long(OPTION_NUM_KEYS)
+                .help("The number of keys of the synthetic workload. default: 1000000")
+                .takes_value(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_NUM_REQUESTS)
+                .long(OPTION_NUM_REQUESTS)
+                .help("The number of requests of the synthetic workload. default: 10000000")
+                .takes_value(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_SEED)
+                .long(OPTION_SEED)
+                .help(
+                    "The seed of the pseudo-random numbers of the synthetic workload \
+                    (or the workload file) and the \"random\" op-schedule. The same seed generates the same \
+                    requests. default: 0",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_SCAN_LENGTH)
+                .long(OPTION_SCAN_LENGTH)
+                .help(
+                    "The number of keys read by a scan of the scan-pollution workload. \
+                    default: 100000",
+                )
+                .takes_value(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_SCAN_INTERVAL)
+                .long(OPTION_SCAN_INTERVAL)
+                .help(
+                    "The number of reads of the hot set between the scans of the \
+                    scan-pollution workload. default: 1000000",
+                )
+                .takes_value(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_SCAN_LOOP)
+                .long(OPTION_SCAN_LOOP)
+                .help(
+                    "Read the same keys in every scan of the scan-pollution workload \
+                    (a loop), instead of new keys each time",
+                )
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_HERD_KEYS)
+                .long(OPTION_HERD_KEYS)
+                .help(
+                    "The number of keys read by all clients in each round of the \
+                    thundering-herd workload. default: 8",
+                )
+                .takes_value(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(
+            Arg::new(OPTION_HERD_ROUNDS)
+                .long(OPTION_HERD_ROUNDS)
+                .help("The number of rounds of the thundering-herd workload. default: 100")
+                .takes_value(true)
+                .requires(OPTION_WORKLOAD),
+        )
+        .arg(Arg::new(OPTION_LENIENT).long(OPTION_LENIENT).help(
+            "Skip the malformed lines of the traces instead of aborting. They are \
+                reported once before running the benchmarks",
+        ))
+        .arg(
+            Arg::new(OPTION_TRACE_MANIFEST)
+                .long(OPTION_TRACE_MANIFEST)
+                .help(
+                    "A TOML manifest registering additional trace files, which can be \
+                    selected with --trace-file. See traces.toml for the format",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
         .arg(
             Arg::new(OPTION_TTL)
                 .long(OPTION_TTL)
@@ -231,6 +602,125 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
                 .long(OPTION_REPEAT)
                 .takes_value(true),
         )
+        .arg(Arg::new(OPTION_STREAMING).long(OPTION_STREAMING).help(
+            "Generate the commands while the clients are running, instead of \
+                    preloading all of them into memory. The time the clients spend \
+                    waiting for the commands is reported as \"Starved Secs\"",
+        ))
+        .arg(
+            Arg::new(OPTION_PARSE_ONCE)
+                .long(OPTION_PARSE_ONCE)
+                .help(
+                    "Parse the trace only once in parallel and keep its entries in memory \
+                    for all runs, instead of reading it again for every run. Needs \
+                    memory for all entries of the trace",
+                )
+                .conflicts_with(OPTION_STREAMING),
+        )
+        .arg(
+            Arg::new(OPTION_NUM_PRODUCERS)
+                .long(OPTION_NUM_PRODUCERS)
+                .help("The number of producer threads in the streaming mode. default: 1")
+                .takes_value(true)
+                .requires(OPTION_STREAMING),
+        )
+        .arg(
+            Arg::new(OPTION_TIME_SCALE)
+                .long(OPTION_TIME_SCALE)
+                .help(
+                    "Replay the trace by the timestamps recorded in it, this many times \
+                    faster than the original (e.g. 60 replays a minute of the trace in a \
+                    second). The TTL and TTI are also scaled. Requires a trace with \
+                    timestamps",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_SAMPLING_RATE)
+                .long(OPTION_SAMPLING_RATE)
+                .help(
+                    "Sample the keys of the trace at this rate (e.g. 0.01) by their \
+                    hashes, and scale down the capacities by the same rate. The \
+                    estimated hit ratio of the full-scale trace and capacity is also \
+                    reported",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_MULTI_TENANT)
+                .long(OPTION_MULTI_TENANT)
+                .help(
+                    "Run the traces together against a shared cache, as the requests \
+                    of different tenants, instead of one after another. The keys of \
+                    each trace are put into its own namespace, and the hit ratios are \
+                    reported per tenant as well",
+                ),
+        )
+        .arg(
+            Arg::new(OPTION_TENANT_WEIGHTS)
+                .long(OPTION_TENANT_WEIGHTS)
+                .help(
+                    "The comma separated weights of the tenants, in the order of the \
+                    traces, to interleave their requests by. default: 1 for all",
+                )
+                .takes_value(true)
+                .use_value_delimiter(true)
+                .requires(OPTION_MULTI_TENANT),
+        )
+        .arg(
+            Arg::new(OPTION_INTERLEAVE)
+                .long(OPTION_INTERLEAVE)
+                .help(
+                    "How to interleave the requests of the tenants: \"weight\" or \
+                    \"timestamp\". default: weight",
+                )
+                .takes_value(true)
+                .requires(OPTION_MULTI_TENANT),
+        )
+        .arg(
+            Arg::new(OPTION_SKIP_REQUESTS)
+                .long(OPTION_SKIP_REQUESTS)
+                .help(
+                    "Skip this many requests at the start of the trace (e.g. to skip \
+                    the cold start)",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_MAX_REQUESTS)
+                .long(OPTION_MAX_REQUESTS)
+                .help("Run at most this many requests of the trace (after skipping)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_FROM)
+                .long(OPTION_FROM)
+                .help(
+                    "Start from the first request at or after this time (in seconds) \
+                    since the first request of the trace. Requires a trace with \
+                    timestamps",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_UNTIL)
+                .long(OPTION_UNTIL)
+                .help(
+                    "Stop at the first request at or after this time (in seconds) since \
+                    the first request of the trace. Requires a trace with timestamps",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_QUEUE_SIZE)
+                .long(OPTION_QUEUE_SIZE)
+                .help(
+                    "The max number of command batches buffered in the streaming mode. \
+                    default: 1024",
+                )
+                .takes_value(true)
+                .requires(OPTION_STREAMING),
+        )
         .arg(
             Arg::new(OPTION_INSERTION_DELAY)
                 .short('d')
@@ -242,7 +732,63 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
         .arg(Arg::new(OPTION_INVALIDATE_ALL).long(OPTION_INVALIDATE_ALL))
         .arg(Arg::new(OPTION_INVALIDATE_IF).long(OPTION_INVALIDATE_IF))
         .arg(Arg::new(OPTION_ITERATE).long(OPTION_ITERATE))
-        .arg(Arg::new(OPTION_SIZE_AWARE).long(OPTION_SIZE_AWARE));
+        .arg(Arg::new(OPTION_SIZE_AWARE).long(OPTION_SIZE_AWARE))
+        .arg(
+            Arg::new(OPTION_OP_MIX)
+                .long(OPTION_OP_MIX)
+                .help(
+                    "The operations to inject into the requests, as KIND=RATE separated \
+                    by commas, where KIND is \"insert-once\", \"update\", \
+                    \"invalidate\", \"invalidate-all\", \"invalidate-entries-if\" or \
+                    \"iterate\", and RATE is a percentage (e.g. 10%) or a period (e.g. \
+                    1/8). e.g. update=10%,invalidate=1/8",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    OPTION_INSERT_ONCE,
+                    OPTION_INVALIDATE,
+                    OPTION_INVALIDATE_ALL,
+                    OPTION_INVALIDATE_IF,
+                    OPTION_ITERATE,
+                ]),
+        )
+        .arg(
+            Arg::new(OPTION_OP_SCHEDULE)
+                .long(OPTION_OP_SCHEDULE)
+                .help(
+                    "How to place the injected operations: \"periodic\" (default, at \
+                    every multiple of their periods) or \"random\" (drawn with --seed)",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_KEY_TYPE)
+                .long(OPTION_KEY_TYPE)
+                .help(
+                    "The type of the cache keys: \"integer\" (default), \"string\" or \
+                    \"bytes\". Numeric keys in the trace are converted to decimal strings",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_KEY_TRANSFORM)
+                .long(OPTION_KEY_TRANSFORM)
+                .help(
+                    "How to transform the keys of the trace: \"identity\" (default), \
+                    \"scramble\" (bijective hash), \"dense\" (renumber by first \
+                    appearance) or \"fold:N\" (modulo N)",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(OPTION_KEY_LEN)
+                .long(OPTION_KEY_LEN)
+                .help(
+                    "Zero-pad the numeric keys converted to strings to this length. \
+                    Implies --key-type string if it is not given",
+                )
+                .takes_value(true),
+        );
 
     if cfg!(not(feature = "moka-v08")) {
         app = app.arg(
@@ -262,16 +808,179 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
         feature = "moka-v09",
         feature = "moka-v08"
     ))) {
-        app = app.arg(Arg::new(OPTION_PER_KEY_EXPIRATION).long(OPTION_PER_KEY_EXPIRATION));
+        app = app.arg(
+            Arg::new(OPTION_PER_KEY_EXPIRATION)
+                .long(OPTION_PER_KEY_EXPIRATION)
+                .help(
+                    "Use the per-entry expiration. The TTLs recorded in the trace \
+                    (e.g. Twitter cache traces) are used only with this option",
+                ),
+        );
     }
 
-    let matches = app.get_matches();
+    app
+}
 
-    let trace_files = matches
-        .values_of(OPTION_TRACE_FILE)
-        .unwrap()
-        .map(TraceFile::try_from)
-        .collect::<Result<Vec<TraceFile>, _>>()?;
+/// Returns an error if any of the traces does not record timestamps, which the
+/// option requires.
+fn ensure_timestamps(trace_files: &[TraceDescriptor], option: &str) -> anyhow::Result<()> {
+    if let Some(trace) = trace_files.iter().find(|t| !t.has_timestamps()) {
+        anyhow::bail!(
+            r#"{} cannot be used with trace "{}" as it has no timestamps"#,
+            option,
+            trace.name
+        );
+    }
+    Ok(())
+}
+
+/// Parses the value of the option as a positive integer, or returns `None` if it
+/// is not given.
+fn parse_integer<T: std::str::FromStr>(
+    matches: &ArgMatches,
+    option: &str,
+) -> anyhow::Result<Option<T>> {
+    match matches.value_of(option) {
+        None => Ok(None),
+        Some(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!(r#"Cannot parse {option} "{v}" as a positive integer"#)),
+    }
+}
+
+/// Creates the descriptors of the synthetic workload. The workload with each of
+/// the swept parameters (e.g. `--zipf-theta`) is run as a separate trace.
+fn create_workloads(matches: &ArgMatches, workload: &str) -> anyhow::Result<Vec<TraceDescriptor>> {
+    let num_keys =
+        parse_integer(matches, OPTION_NUM_KEYS)?.unwrap_or(ZipfWorkload::DEFAULT_NUM_KEYS);
+    let num_requests =
+        parse_integer(matches, OPTION_NUM_REQUESTS)?.unwrap_or(ZipfWorkload::DEFAULT_NUM_REQUESTS);
+    let seed = parse_integer(matches, OPTION_SEED)?.unwrap_or_default();
+
+    let thetas = match matches.values_of(OPTION_ZIPF_THETA) {
+        None => vec![ZipfWorkload::DEFAULT_THETA],
+        Some(v) => v
+            .map(|v| {
+                v.parse()
+                    .with_context(|| format!(r#"Cannot parse zipf-theta "{v}" as a number"#))
+            })
+            .collect::<Result<Vec<f64>, _>>()?,
+    };
+
+    match workload {
+        "zipf" => thetas
+            .into_iter()
+            .map(|theta| {
+                let zipf = ZipfWorkload::new(theta, num_keys, num_requests, seed)?;
+                Ok(TraceDescriptor::synthetic(Workload::Zipf(zipf)))
+            })
+            .collect(),
+        "scan-pollution" => {
+            let scan_length = parse_integer(matches, OPTION_SCAN_LENGTH)?
+                .unwrap_or(ScanPollutionWorkload::DEFAULT_SCAN_LENGTH);
+            let scan_interval = parse_integer(matches, OPTION_SCAN_INTERVAL)?
+                .unwrap_or(ScanPollutionWorkload::DEFAULT_SCAN_INTERVAL);
+            let loop_scans = matches.is_present(OPTION_SCAN_LOOP);
+            thetas
+                .into_iter()
+                .map(|theta| {
+                    let workload = ScanPollutionWorkload::new(
+                        theta,
+                        num_keys,
+                        num_requests,
+                        scan_length,
+                        scan_interval,
+                        loop_scans,
+                        seed,
+                    )?;
+                    Ok(TraceDescriptor::synthetic(Workload::ScanPollution(
+                        workload,
+                    )))
+                })
+                .collect()
+        }
+        "thundering-herd" => {
+            let keys_per_round = parse_integer(matches, OPTION_HERD_KEYS)?
+                .unwrap_or(ThunderingHerdWorkload::DEFAULT_KEYS_PER_ROUND);
+            let num_rounds = parse_integer(matches, OPTION_HERD_ROUNDS)?
+                .unwrap_or(ThunderingHerdWorkload::DEFAULT_NUM_ROUNDS);
+            let workload = ThunderingHerdWorkload::new(keys_per_round, num_rounds)?;
+            Ok(vec![TraceDescriptor::synthetic(Workload::ThunderingHerd(
+                workload,
+            ))])
+        }
+        _ => {
+            let Some(kind) = workload.strip_prefix("ycsb-") else {
+                anyhow::bail!(
+                    r#"Unknown workload "{workload}". Available: "zipf", "ycsb-a" to "ycsb-f", "scan-pollution" and "thundering-herd""#
+                );
+            };
+            let kind = kind.parse::<YcsbKind>()?;
+            thetas
+                .into_iter()
+                .map(|theta| {
+                    let ycsb = YcsbWorkload::new(kind, theta, num_keys, num_requests, seed)?;
+                    Ok(TraceDescriptor::synthetic(Workload::Ycsb(ycsb)))
+                })
+                .collect()
+        }
+    }
+}
+
+fn create_config(matches: &ArgMatches) -> anyhow::Result<(Vec<TraceDescriptor>, Config)> {
+    let lenient = matches.is_present(OPTION_LENIENT);
+    // The reports of the malformed lines skipped with `--lenient`.
+    let mut reports = Vec::new();
+    let mut trace_files = if let Some(workload) = matches.value_of(OPTION_WORKLOAD) {
+        create_workloads(matches, workload)?
+    } else if let Some(path) = matches.value_of(OPTION_WORKLOAD_FILE) {
+        let mut workload = PhasedWorkload::load(path)?;
+        if let Some(seed) = parse_integer(matches, OPTION_SEED)? {
+            workload.set_seed(seed);
+        }
+        vec![TraceDescriptor::synthetic(Workload::Phased(workload))]
+    } else if let Some(paths) = matches.values_of(OPTION_TRACE_PATH) {
+        if lenient {
+            paths
+                .map(|path| {
+                    let (trace, report) = TraceDescriptor::from_path_lenient(path)?;
+                    reports.push(report);
+                    Ok(trace)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            paths
+                .map(TraceDescriptor::from_path)
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    } else {
+        let mut registry = TraceRegistry::builtin();
+        for manifest in matches
+            .values_of(OPTION_TRACE_MANIFEST)
+            .into_iter()
+            .flatten()
+        {
+            registry.load_manifest(manifest)?;
+        }
+        matches
+            .values_of(OPTION_TRACE_FILE)
+            .unwrap()
+            .map(|v| registry.get(v))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    if lenient {
+        // The traces given by `--trace-path` were validated while being loaded.
+        for trace in trace_files.iter_mut().filter(|t| !t.lenient) {
+            trace.lenient = true;
+            reports.push(trace.validate()?);
+        }
+        for report in reports.iter().filter(|r| !r.is_valid()) {
+            eprintln!("{report}");
+            eprintln!();
+        }
+    }
 
     let ttl_secs = match matches.value_of(OPTION_TTL) {
         None => None,
@@ -309,19 +1018,172 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
         ),
     };
 
+    let streaming = matches.is_present(OPTION_STREAMING);
+    let parse_once = matches.is_present(OPTION_PARSE_ONCE);
+
+    let num_producers = match matches.value_of(OPTION_NUM_PRODUCERS) {
+        None => 1,
+        Some(v) => v.parse().with_context(|| {
+            format!(r#"Cannot parse num-producers "{v}" as a positive integer"#)
+        })?,
+    };
+
+    let queue_size = match matches.value_of(OPTION_QUEUE_SIZE) {
+        None => Config::DEFAULT_QUEUE_SIZE,
+        Some(v) => v
+            .parse()
+            .with_context(|| format!(r#"Cannot parse queue-size "{v}" as a positive integer"#))?,
+    };
+
+    let time_scale = match matches.value_of(OPTION_TIME_SCALE) {
+        None => None,
+        Some(v) => {
+            let scale = v.parse::<f64>().with_context(|| {
+                format!(r#"Cannot parse time-scale "{v}" as a positive number"#)
+            })?;
+            if !(scale.is_finite() && scale > 0.0) {
+                anyhow::bail!(r#"time-scale "{v}" must be a positive number"#);
+            }
+            ensure_timestamps(&trace_files, OPTION_TIME_SCALE)?;
+            Some(scale)
+        }
+    };
+
+    let skip_requests = match matches.value_of(OPTION_SKIP_REQUESTS) {
+        None => 0,
+        Some(v) => v.parse().with_context(|| {
+            format!(r#"Cannot parse skip-requests "{v}" as a positive integer"#)
+        })?,
+    };
+
+    let max_requests = match matches.value_of(OPTION_MAX_REQUESTS) {
+        None => None,
+        Some(v) => Some(v.parse().with_context(|| {
+            format!(r#"Cannot parse max-requests "{v}" as a positive integer"#)
+        })?),
+    };
+
+    let from = match matches.value_of(OPTION_FROM) {
+        None => None,
+        Some(v) => {
+            let secs = v
+                .parse()
+                .with_context(|| format!(r#"Cannot parse from "{v}" as a positive integer"#))?;
+            ensure_timestamps(&trace_files, OPTION_FROM)?;
+            Some(Duration::from_secs(secs))
+        }
+    };
+
+    let until = match matches.value_of(OPTION_UNTIL) {
+        None => None,
+        Some(v) => {
+            let secs = v
+                .parse()
+                .with_context(|| format!(r#"Cannot parse until "{v}" as a positive integer"#))?;
+            ensure_timestamps(&trace_files, OPTION_UNTIL)?;
+            Some(Duration::from_secs(secs))
+        }
+    };
+
+    if let (Some(from), Some(until)) = (from, until) {
+        if from >= until {
+            anyhow::bail!("from must be less than until");
+        }
+    }
+
+    // The thundering herd only reads the keys by get_with (or the entry API), whose
+    // init closure is the slow loader. Its rounds are generated by the clients
+    // themselves, so they are neither streamed nor sampled.
+    let thundering_herd = matches.value_of(OPTION_WORKLOAD) == Some("thundering-herd");
+    if thundering_herd {
+        for option in [OPTION_OP_MIX, OPTION_STREAMING, OPTION_SAMPLING_RATE] {
+            if matches.is_present(option) {
+                anyhow::bail!(
+                    "{} cannot be used with workload \"thundering-herd\"",
+                    option
+                );
+            }
+        }
+    }
+
     let insertion_delay_micros = match matches.value_of(OPTION_INSERTION_DELAY) {
+        None if thundering_herd => Some(ThunderingHerdWorkload::DEFAULT_LOADER_DELAY_MICROS),
         None => None,
         Some(v) => Some(v.parse().with_context(|| {
             format!(r#"Cannot parse insertion-delay "{v}" as a positive integer"#,)
         })?),
     };
 
-    let insert_once = matches.is_present(OPTION_INSERT_ONCE);
+    let insert_once = matches.is_present(OPTION_INSERT_ONCE) || thundering_herd;
     let invalidate = matches.is_present(OPTION_INVALIDATE);
     let invalidate_all = matches.is_present(OPTION_INVALIDATE_ALL);
     let invalidate_entries_if = matches.is_present(OPTION_INVALIDATE_IF);
     let iterate = matches.is_present(OPTION_ITERATE);
     let size_aware = matches.is_present(OPTION_SIZE_AWARE);
+    let op_mix = match matches.value_of(OPTION_OP_MIX) {
+        None => None,
+        Some(v) => Some(
+            v.parse::<OpMix>()
+                .with_context(|| format!(r#"Cannot parse op-mix "{v}""#))?,
+        ),
+    };
+    let op_schedule = match matches.value_of(OPTION_OP_SCHEDULE) {
+        None | Some("periodic") => OpSchedule::Periodic,
+        Some("random") => {
+            OpSchedule::Random(parse_integer(matches, OPTION_SEED)?.unwrap_or_default())
+        }
+        Some(v) => {
+            anyhow::bail!(
+                r#"op-schedule must be "periodic" or "random", but got "{}""#,
+                v
+            );
+        }
+    };
+    if matches.is_present(OPTION_SEED)
+        && !matches.is_present(OPTION_WORKLOAD)
+        && !matches.is_present(OPTION_WORKLOAD_FILE)
+        && op_schedule == OpSchedule::Periodic
+    {
+        anyhow::bail!("seed requires workload, workload-file or op-schedule \"random\"");
+    }
+
+    let key_len = match matches.value_of(OPTION_KEY_LEN) {
+        None => None,
+        Some(v) => Some(
+            v.parse()
+                .with_context(|| format!(r#"Cannot parse key-len "{v}" as a positive integer"#))?,
+        ),
+    };
+
+    let key_type = match matches.value_of(OPTION_KEY_TYPE) {
+        None if key_len.is_some() => KeyType::String,
+        None | Some("integer") => KeyType::Integer,
+        Some("string") => KeyType::String,
+        Some("bytes") => KeyType::Bytes,
+        Some(v) => {
+            anyhow::bail!(
+                r#"key-type must be "integer", "string" or "bytes", but got "{}""#,
+                v
+            );
+        }
+    };
+
+    if key_type == KeyType::Integer && key_len.is_some() {
+        anyhow::bail!("key-len cannot be used with key-type \"integer\"");
+    }
+
+    let key_transform = match matches.value_of(OPTION_KEY_TRANSFORM) {
+        None | Some("identity") => KeyTransform::Identity,
+        Some("scramble") => KeyTransform::Scramble,
+        Some("dense") => KeyTransform::Dense,
+        Some(v) => match v.strip_prefix("fold:").map(str::parse) {
+            Some(Ok(n)) if n > 0 => KeyTransform::Fold(n),
+            _ => anyhow::bail!(
+                r#"key-transform must be "identity", "scramble", "dense" or "fold:N" with a positive integer N, but got "{}""#,
+                v
+            ),
+        },
+    };
 
     // Since Moka v0.10
     let entry_api = matches.is_present(OPTION_ENTRY_API);
@@ -366,7 +1228,13 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
         }
     }
 
-    if !entry_api && insert_once && cfg!(not(any(feature = "moka-v08", feature = "moka-v09"))) {
+    let injects_insert_once = op_mix
+        .as_ref()
+        .map_or(insert_once, |mix| mix.contains(OpKind::InsertOnce));
+    if !entry_api
+        && injects_insert_once
+        && cfg!(not(any(feature = "moka-v08", feature = "moka-v09")))
+    {
         eprintln!("\nWARNING: Testing Moka's entry API is disabled by default. Use --entry-api to enable it.\n");
     }
 
@@ -374,21 +1242,98 @@ fn create_config() -> anyhow::Result<(Vec<TraceFile>, Config)> {
         eprintln!("\nWARNING: TinyUFO crate does not support custom hasher. Its default hasher will be used for TinyUFO.\n");
     }
 
+    let sampling_rate = match matches.value_of(OPTION_SAMPLING_RATE) {
+        None => None,
+        Some(v) => {
+            let rate = v
+                .parse::<f64>()
+                .with_context(|| format!(r#"Cannot parse sampling-rate "{v}" as a number"#))?;
+            if !(rate > 0.0 && rate <= 1.0) {
+                anyhow::bail!(r#"sampling-rate "{v}" must be greater than 0 and at most 1"#);
+            }
+            Some(rate)
+        }
+    };
+
+    let multi_tenant = matches.is_present(OPTION_MULTI_TENANT);
+    let mut tenants = Vec::new();
+    let mut tenant_weights = Vec::new();
+    let mut interleave = Interleave::Weight;
+    if multi_tenant {
+        if trace_files.len() < 2 || trace_files.len() > 256 {
+            anyhow::bail!("multi-tenant requires 2 to 256 traces");
+        }
+        if let Some(weights) = matches.values_of(OPTION_TENANT_WEIGHTS) {
+            tenant_weights = weights
+                .map(|v| match v.parse::<u32>() {
+                    Ok(w) if w > 0 => Ok(w),
+                    _ => Err(anyhow::anyhow!(
+                        r#"Cannot parse tenant weight "{v}" as a positive integer"#
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if tenant_weights.len() != trace_files.len() {
+                anyhow::bail!(
+                    "tenant-weights has {} weights, but there are {} traces",
+                    tenant_weights.len(),
+                    trace_files.len()
+                );
+            }
+        }
+        interleave = match matches.value_of(OPTION_INTERLEAVE) {
+            None | Some("weight") => Interleave::Weight,
+            Some("timestamp") => {
+                ensure_timestamps(&trace_files, OPTION_INTERLEAVE)?;
+                Interleave::Timestamp
+            }
+            Some(v) => {
+                anyhow::bail!(r#"Unknown interleave "{v}". Available: "weight" and "timestamp""#)
+            }
+        };
+        if interleave == Interleave::Weight
+            && (time_scale.is_some() || from.is_some() || until.is_some())
+        {
+            anyhow::bail!(
+                "time-scale, from and until require interleave \"timestamp\" with \
+                multi-tenant"
+            );
+        }
+        tenants = std::mem::take(&mut trace_files);
+        trace_files = vec![TraceDescriptor::multi_tenant(&tenants)];
+    }
+
     let mut config = Config::new(
-        trace_files[0],
+        trace_files[0].clone(),
         ttl_secs,
         tti_secs,
         num_clients,
         repeat,
         insertion_delay_micros,
     );
+    config.set_streaming(streaming);
+    config.set_parse_once(parse_once);
+    config.set_num_producers(num_producers);
+    config.set_queue_size(queue_size);
+    config.set_time_scale(time_scale);
+    config.set_skip_requests(skip_requests);
+    config.set_max_requests(max_requests);
+    config.set_time_window(from, until);
+    config.set_sampling_rate(sampling_rate);
+    if multi_tenant {
+        config.set_tenants(tenants, tenant_weights, interleave);
+    }
     config.set_insert_once(insert_once);
     config.set_invalidate(invalidate);
     config.set_invalidate_all(invalidate_all);
     config.set_invalidate_entries_if(invalidate_entries_if);
     config.set_iterate(iterate);
+    config.set_op_mix(op_mix);
+    config.set_op_schedule(op_schedule);
     config.set_eviction_listener(eviction_listener);
     config.set_size_aware(size_aware);
+    config.set_key_type(key_type);
+    config.set_key_transform(key_transform);
+    config.set_key_len(key_len);
     config.set_entry_api(entry_api);
     config.set_per_key_expiration(per_key_expiration);
 
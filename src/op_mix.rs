@@ -0,0 +1,402 @@
+//! The mix of the operations injected into the requests of a trace, such as
+//! invalidations and updates, and the schedule that places them.
+//!
+//! A mix is written as a comma-separated list of `KIND=RATE`, where `RATE` is a
+//! percentage of the requests (`10%`) or a period (`1/8` for every 8th request):
+//!
+//! ```text
+//! update=10%,invalidate=1/8,iterate=1/50000
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use crate::workload::SplitMix64;
+
+/// A kind of operation that can be injected into the requests of a trace.
+///
+/// The kinds are in the order of precedence: when more than one kind is due for a
+/// request, the first one is injected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OpKind {
+    /// Invalidates all entries, and then reads the key of the request.
+    InvalidateAll,
+    /// Invalidates the entries matching a predicate derived from the request.
+    InvalidateEntriesIf,
+    /// Inserts the key of the request without reading it.
+    Update,
+    /// Invalidates the key of the request.
+    Invalidate,
+    /// Reads the key of the request and inserts it on a miss, running the value
+    /// initialization only once for concurrent misses.
+    InsertOnce,
+    /// Iterates over all entries. Unlike the other kinds, it is injected after
+    /// the request instead of replacing it.
+    Iterate,
+}
+
+impl OpKind {
+    const ALL: [Self; 6] = [
+        Self::InvalidateAll,
+        Self::InvalidateEntriesIf,
+        Self::Update,
+        Self::Invalidate,
+        Self::InsertOnce,
+        Self::Iterate,
+    ];
+
+    /// The name of the kind in an op mix, which is the same as its command line
+    /// option.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::InvalidateAll => "invalidate-all",
+            Self::InvalidateEntriesIf => "invalidate-entries-if",
+            Self::Update => "update",
+            Self::Invalidate => "invalidate",
+            Self::InsertOnce => "insert-once",
+            Self::Iterate => "iterate",
+        }
+    }
+}
+
+impl FromStr for OpKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::ALL.into_iter().find(|kind| kind.name() == s) {
+            Some(kind) => Ok(kind),
+            None => anyhow::bail!(
+                r#"Unknown operation "{}". Available: {}"#,
+                s,
+                Self::ALL
+                    .iter()
+                    .map(|kind| format!(r#""{}""#, kind.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// How often an operation is injected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpRate {
+    /// Every `n`-th request.
+    Period(usize),
+    /// The fraction of the requests in `(0.0, 1.0]`.
+    Ratio(f64),
+}
+
+impl OpRate {
+    /// The period of the rate, rounded to the nearest integer for a ratio.
+    fn period(&self) -> usize {
+        match self {
+            Self::Period(n) => *n,
+            Self::Ratio(r) => ((1.0 / r).round() as usize).max(1),
+        }
+    }
+
+    /// The fraction of the requests of the rate.
+    fn ratio(&self) -> f64 {
+        match self {
+            Self::Period(n) => 1.0 / *n as f64,
+            Self::Ratio(r) => *r,
+        }
+    }
+}
+
+impl FromStr for OpRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(percent) = s.strip_suffix('%') {
+            match percent.parse::<f64>() {
+                Ok(p) if p > 0.0 && p <= 100.0 => Ok(Self::Ratio(p / 100.0)),
+                _ => anyhow::bail!(
+                    r#"Percentage "{}" must be greater than 0 and at most 100"#,
+                    s
+                ),
+            }
+        } else if let Some(period) = s.strip_prefix("1/") {
+            match period.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Self::Period(n)),
+                _ => anyhow::bail!(r#"Period "{}" must be "1/N" with a positive integer N"#, s),
+            }
+        } else {
+            anyhow::bail!(
+                r#"Cannot parse rate "{}". It must be a percentage (e.g. "10%") or a period (e.g. "1/8")"#,
+                s
+            )
+        }
+    }
+}
+
+impl fmt::Display for OpRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Period(n) => write!(f, "1/{n}"),
+            Self::Ratio(r) => write!(f, "{}%", r * 100.0),
+        }
+    }
+}
+
+/// The rates of the operations injected into the requests of a trace. The other
+/// requests are reads (get, and insert on a miss).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpMix {
+    /// Sorted by the kinds.
+    rates: Vec<(OpKind, OpRate)>,
+}
+
+impl OpMix {
+    /// Creates a mix with no injected operations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the rate of the kind of operation, or stops injecting it if `None`.
+    pub fn set(&mut self, kind: OpKind, rate: Option<OpRate>) {
+        self.rates.retain(|(k, _)| *k != kind);
+        if let Some(rate) = rate {
+            self.rates.push((kind, rate));
+            self.rates.sort_by_key(|(k, _)| *k);
+        }
+    }
+
+    /// Returns the rate of the kind of operation, or `None` if it is not injected.
+    pub fn rate(&self, kind: OpKind) -> Option<OpRate> {
+        self.rates.iter().find(|(k, _)| *k == kind).map(|(_, r)| *r)
+    }
+
+    pub fn contains(&self, kind: OpKind) -> bool {
+        self.rate(kind).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+}
+
+impl FromStr for OpMix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = Self::new();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let Some((kind, rate)) = item.split_once('=') else {
+                anyhow::bail!(
+                    r#"Cannot parse "{}" in the op mix. It must be "KIND=RATE""#,
+                    item
+                );
+            };
+            let kind = kind.trim().parse::<OpKind>()?;
+            if mix.contains(kind) {
+                anyhow::bail!(r#"Operation "{}" is given more than once"#, kind.name());
+            }
+            mix.set(kind, Some(rate.trim().parse()?));
+        }
+
+        let total = mix
+            .rates
+            .iter()
+            .filter(|(kind, _)| *kind != OpKind::Iterate)
+            .map(|(_, rate)| rate.ratio())
+            .sum::<f64>();
+        if total > 1.0 + f64::EPSILON {
+            anyhow::bail!(
+                "The operations other than iterate add up to {:.2}% of the requests, \
+                which must be at most 100%",
+                total * 100.0
+            );
+        }
+        Ok(mix)
+    }
+}
+
+impl fmt::Display for OpMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (kind, rate)) in self.rates.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={}", kind.name(), rate)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the injected operations are placed among the requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpSchedule {
+    /// Injects an operation at every multiple of its period (a ratio is rounded to
+    /// a period). When the periods of several kinds coincide, only the first kind
+    /// is injected, so the kinds later in the precedence get slightly less than
+    /// their rates.
+    Periodic,
+    /// Draws the operation of each request from the seeded pseudo-random numbers,
+    /// so the kinds get their rates on average. The same seed places the same
+    /// operations.
+    Random(u64),
+}
+
+/// Decides the operations to inject into the requests by their positions in the
+/// trace. It has no state, so the requests can be scheduled in any order (e.g. by
+/// several threads) with the same result.
+pub(crate) struct OpScheduler {
+    rates: Vec<(OpKind, OpRate)>,
+    iterate: Option<OpRate>,
+    schedule: OpSchedule,
+}
+
+impl OpScheduler {
+    /// An arbitrary number to draw the iterations from a stream independent of the
+    /// other operations.
+    const ITERATE_SEED_MASK: u64 = 0x6c62_272e_07bb_0142;
+
+    pub(crate) fn new(mix: &OpMix, schedule: OpSchedule) -> Self {
+        Self {
+            rates: mix
+                .rates
+                .iter()
+                .copied()
+                .filter(|(kind, _)| *kind != OpKind::Iterate)
+                .collect(),
+            iterate: mix.rate(OpKind::Iterate),
+            schedule,
+        }
+    }
+
+    /// Returns the operation to replace the request with, if any. `counter` is the
+    /// position of the request, starting from 1.
+    pub(crate) fn operation(&self, counter: usize) -> Option<OpKind> {
+        match self.schedule {
+            OpSchedule::Periodic => self
+                .rates
+                .iter()
+                .find(|(_, rate)| counter.is_multiple_of(rate.period()))
+                .map(|(kind, _)| *kind),
+            OpSchedule::Random(seed) => {
+                let u = SplitMix64::skip_to(seed, counter as u64).next_f64();
+                let mut cumulative = 0.0;
+                self.rates.iter().find_map(|(kind, rate)| {
+                    cumulative += rate.ratio();
+                    (u < cumulative).then_some(*kind)
+                })
+            }
+        }
+    }
+
+    /// Returns `true` if an iteration is injected after the request.
+    pub(crate) fn iterates(&self, counter: usize) -> bool {
+        let Some(rate) = self.iterate else {
+            return false;
+        };
+        match self.schedule {
+            OpSchedule::Periodic => counter.is_multiple_of(rate.period()),
+            OpSchedule::Random(seed) => {
+                let seed = seed ^ Self::ITERATE_SEED_MASK;
+                SplitMix64::skip_to(seed, counter as u64).next_f64() < rate.ratio()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rates() {
+        assert_eq!("10%".parse::<OpRate>().unwrap(), OpRate::Ratio(0.1));
+        assert_eq!("100%".parse::<OpRate>().unwrap(), OpRate::Ratio(1.0));
+        assert_eq!("1/8".parse::<OpRate>().unwrap(), OpRate::Period(8));
+        for rate in ["0%", "100.5%", "-1%", "x%", "1/0", "1/x", "2/8", "0.1", ""] {
+            assert!(rate.parse::<OpRate>().is_err(), "rate: {rate:?}");
+        }
+
+        assert_eq!(OpRate::Ratio(0.1).period(), 10);
+        assert_eq!(OpRate::Ratio(0.3).period(), 3);
+        assert_eq!(OpRate::Ratio(1.0).period(), 1);
+        assert_eq!(OpRate::Period(4).ratio(), 0.25);
+    }
+
+    #[test]
+    fn parse_mix() {
+        let mix = " update=10%, invalidate=1/8 ,iterate=1/50000,"
+            .parse::<OpMix>()
+            .unwrap();
+        assert_eq!(mix.rate(OpKind::Update), Some(OpRate::Ratio(0.1)));
+        assert_eq!(mix.rate(OpKind::Invalidate), Some(OpRate::Period(8)));
+        assert_eq!(mix.rate(OpKind::Iterate), Some(OpRate::Period(50_000)));
+        assert!(!mix.contains(OpKind::InvalidateAll));
+        // Sorted by the precedence of the kinds.
+        assert_eq!(mix.to_string(), "update=10%,invalidate=1/8,iterate=1/50000");
+        assert_eq!(mix.to_string().parse::<OpMix>().unwrap(), mix);
+
+        assert!("".parse::<OpMix>().unwrap().is_empty());
+        for mix in ["update", "update=10%,update=1/8", "delete=10%", "update=10"] {
+            assert!(mix.parse::<OpMix>().is_err(), "mix: {mix:?}");
+        }
+    }
+
+    #[test]
+    fn reject_total_over_100_percent() {
+        assert!("update=60%,invalidate=50%".parse::<OpMix>().is_err());
+        assert!("update=1/2,invalidate=1/2,invalidate-all=1/100"
+            .parse::<OpMix>()
+            .is_err());
+        assert!("update=50%,invalidate=1/2".parse::<OpMix>().is_ok());
+        // Iterate is injected after the request, so it does not count.
+        assert!("update=100%,iterate=100%".parse::<OpMix>().is_ok());
+    }
+
+    fn count(scheduler: &OpScheduler, kind: OpKind, n: usize) -> usize {
+        (1..=n)
+            .filter(|&i| scheduler.operation(i) == Some(kind))
+            .count()
+    }
+
+    #[test]
+    fn periodic_schedule() {
+        let mix = "update=1/4,invalidate=1/6,iterate=1/10".parse().unwrap();
+        let scheduler = OpScheduler::new(&mix, OpSchedule::Periodic);
+        let ops = (1..=12).map(|i| scheduler.operation(i)).collect::<Vec<_>>();
+        let (u, i) = (Some(OpKind::Update), Some(OpKind::Invalidate));
+        // At 12, update takes precedence over invalidate.
+        assert_eq!(
+            ops,
+            [None, None, None, u, None, i, None, u, None, None, None, u]
+        );
+        assert!(scheduler.iterates(10) && scheduler.iterates(20));
+        assert!(!(1..10).any(|i| scheduler.iterates(i)));
+    }
+
+    #[test]
+    fn random_schedule() {
+        let mix = "update=10%,invalidate=1/20,iterate=1%".parse().unwrap();
+        let scheduler = OpScheduler::new(&mix, OpSchedule::Random(42));
+        let n = 100_000;
+        let updates = count(&scheduler, OpKind::Update, n);
+        let invalidates = count(&scheduler, OpKind::Invalidate, n);
+        let iterations = (1..=n).filter(|&i| scheduler.iterates(i)).count();
+        assert!((9_500..10_500).contains(&updates), "updates: {updates}");
+        assert!(
+            (4_600..5_400).contains(&invalidates),
+            "invalidates: {invalidates}"
+        );
+        assert!(
+            (850..1_150).contains(&iterations),
+            "iterations: {iterations}"
+        );
+
+        // Not periodic.
+        assert!((1..=n).any(|i| scheduler.operation(i) == Some(OpKind::Update) && i % 10 != 0));
+
+        // The same seed places the same operations, in any order of the requests.
+        let same = OpScheduler::new(&mix, OpSchedule::Random(42));
+        assert!((1..=1_000)
+            .rev()
+            .all(|i| same.operation(i) == scheduler.operation(i)));
+        let other = OpScheduler::new(&mix, OpSchedule::Random(43));
+        assert!((1..=1_000).any(|i| other.operation(i) != scheduler.operation(i)));
+    }
+}
@@ -1,21 +1,229 @@
+use std::{io::BufRead, path::Path, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::trace_reader::{self, TraceInput};
+
+pub(crate) mod binary;
+
+/// An iterator of the entries read from a trace file.
+pub(crate) type TraceEntries = Box<dyn Iterator<Item = anyhow::Result<TraceEntry>> + Send>;
+
+/// The minimum size in bytes of a segment of a text trace to parse in a thread.
+const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+
+/// The maximum number of characters of a malformed line to show in a diagnostic.
+const MAX_EXCERPT_CHARS: usize = 80;
+
+/// The problems found in a malformed line of a trace.
+#[derive(Debug, Error)]
+pub enum TraceParseError {
+    #[error("missing key")]
+    MissingKey,
+    #[error("invalid {field} \"{text}\"")]
+    InvalidNumber { field: &'static str, text: String },
+    #[error("the key range {start} + {len} overflows")]
+    RangeOverflow { start: usize, len: usize },
+    #[error("expected {expected} comma separated fields but found {found}")]
+    FieldCount { expected: usize, found: usize },
+    #[error("unknown operation \"{0}\"")]
+    UnknownOperation(String),
+    #[error("the offset {0} is out of the supported range")]
+    OffsetOutOfRange(u64),
+}
+
+impl TraceParseError {
+    /// Returns a short description of the kind of the problem, which is used to
+    /// summarize the problems of a trace.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::MissingKey => "missing key",
+            Self::InvalidNumber { .. } => "invalid number",
+            Self::RangeOverflow { .. } => "key range overflow",
+            Self::FieldCount { .. } => "wrong number of fields",
+            Self::UnknownOperation(_) => "unknown operation",
+            Self::OffsetOutOfRange(_) => "offset out of range",
+        }
+    }
+}
+
+/// Parses a line of a text trace. The error of a malformed line tells the `source`
+/// and line number of the line. If `lenient` is `true`, a malformed line is
+/// skipped instead.
+pub(crate) fn parse_line(
+    parser: &mut dyn TraceParser<TraceEntry>,
+    line: std::io::Result<String>,
+    i: usize,
+    source: &str,
+    lenient: bool,
+) -> Option<anyhow::Result<TraceEntry>> {
+    match line {
+        Ok(line) => match parser.parse(&line, i) {
+            Ok(entry) => entry.map(Ok),
+            Err(_) if lenient => None,
+            Err(e) => {
+                Some(Err(e).with_context(|| format!("{}:{}: {}", source, i + 1, excerpt(&line))))
+            }
+        },
+        // A line that is not valid UTF-8.
+        Err(e) if lenient && e.kind() == std::io::ErrorKind::InvalidData => None,
+        Err(e) => Some(Err(e).with_context(|| format!("{}:{}", source, i + 1))),
+    }
+}
+
+/// Parses a number in a field of a trace line.
+fn parse_number<T: std::str::FromStr>(
+    field: &'static str,
+    text: &str,
+) -> Result<T, TraceParseError> {
+    text.parse().map_err(|_| TraceParseError::InvalidNumber {
+        field,
+        text: text.to_string(),
+    })
+}
+
+/// Returns the beginning of a line to show in a diagnostic.
+pub(crate) fn excerpt(line: &str) -> String {
+    if line.chars().count() <= MAX_EXCERPT_CHARS {
+        line.to_string()
+    } else {
+        let mut excerpt = line.chars().take(MAX_EXCERPT_CHARS).collect::<String>();
+        excerpt.push_str("...");
+        excerpt
+    }
+}
+
 pub trait TraceParser<T> {
     fn parse(&mut self, line: &str, line_number: usize) -> anyhow::Result<Option<T>>;
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TraceEntry {
     range: std::ops::Range<usize>,
+    key: Option<Box<str>>,
     line_number: usize,
+    tenant: Option<u8>,
+    class: Option<u8>,
+    timestamp: Option<Duration>,
+    operation: Option<TraceOperation>,
+    value_size: Option<u32>,
+    ttl: Option<Duration>,
 }
 
 impl TraceEntry {
+    pub(crate) fn new(range: std::ops::Range<usize>, line_number: usize) -> Self {
+        Self {
+            range,
+            key: None,
+            line_number,
+            tenant: None,
+            class: None,
+            timestamp: None,
+            operation: None,
+            value_size: None,
+            ttl: None,
+        }
+    }
+
     pub fn range(&self) -> std::ops::Range<usize> {
         self.range.clone()
     }
 
+    /// The textual key recorded in the trace. `None` if the trace format has only
+    /// numeric keys. If present, `range` holds the hash of this key.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
     pub fn line_number(&self) -> usize {
         self.line_number
     }
+
+    /// Returns a copy of the entry for only one of its keys.
+    pub(crate) fn with_key(&self, key: usize) -> Self {
+        Self {
+            range: key..(key + 1),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the entry with the operation.
+    pub(crate) fn with_operation(mut self, operation: TraceOperation) -> Self {
+        self.operation = Some(operation);
+        self
+    }
+
+    /// Returns a copy of the entry for only one of its keys, replaced by `key`. The
+    /// textual key is dropped, so that `key` is used for all key types.
+    pub(crate) fn with_transformed_key(&self, key: usize) -> Self {
+        Self {
+            key: None,
+            ..self.with_key(key)
+        }
+    }
+
+    /// The index of the tenant whose trace has this entry. `None` unless several
+    /// traces are interleaved.
+    pub fn tenant(&self) -> Option<u8> {
+        self.tenant
+    }
+
+    /// The index of the class of the request (e.g. the hot reads or the scans of
+    /// a synthetic workload), which is counted separately. `None` unless the
+    /// workload classifies its requests.
+    pub fn class(&self) -> Option<u8> {
+        self.class
+    }
+
+    /// Returns the entry tagged with the index of its class of requests. Unlike
+    /// `set_tenant`, the keys stay in the same namespace.
+    pub(crate) fn with_class(mut self, class: u8) -> Self {
+        self.class = Some(class);
+        self
+    }
+
+    /// Assigns the entry to the tenant and moves its keys into the namespace of
+    /// the tenant, so that the keys of different tenants never collide. The
+    /// tenant index is prefixed to the textual key, and the cache keys are
+    /// created from the tenant and the numeric keys (see `CacheKey`).
+    pub(crate) fn set_tenant(&mut self, tenant: u8) {
+        if let Some(key) = &self.key {
+            self.key = Some(format!("{tenant}:{key}").into());
+        }
+        self.tenant = Some(tenant);
+    }
+
+    /// The time of the request recorded in the trace. `None` if the trace format
+    /// does not record timestamps.
+    pub fn timestamp(&self) -> Option<Duration> {
+        self.timestamp
+    }
+
+    /// The operation recorded in the trace. `None` if the trace format does not
+    /// record operations.
+    pub fn operation(&self) -> Option<TraceOperation> {
+        self.operation
+    }
+
+    /// The size of the value in bytes recorded in the trace.
+    pub fn value_size(&self) -> Option<u32> {
+        self.value_size
+    }
+
+    /// The time-to-live of the entry recorded in the trace.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+}
+
+/// The kinds of the operations recorded in a trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOperation {
+    Read,
+    Write,
+    Delete,
 }
 
 // Arc traces contains a 2+ numbers per line, the first two being start and len, meaning a range `start..start+len`
@@ -29,21 +237,589 @@ impl TraceParser<TraceEntry> for GenericTraceParser {
             return Ok(None);
         }
         let mut tokens = line.split(' ');
-        let start = if let Some(token) = tokens.next() {
-            token.parse::<usize>()?
-        } else {
-            anyhow::bail!("Expected at least one integer in the line: {}", line);
+        let start = match tokens.next() {
+            Some(token) if !token.is_empty() => parse_number::<usize>("key", token)?,
+            _ => return Err(TraceParseError::MissingKey.into()),
         };
         let len = if let Some(token) = tokens.next() {
-            token.parse::<usize>()?
+            parse_number("length", token)?
         } else {
             // single integer per line format
             1
         };
+        let end = start
+            .checked_add(len)
+            .ok_or(TraceParseError::RangeOverflow { start, len })?;
 
-        Ok(Some(TraceEntry {
-            range: start..(start + len),
-            line_number,
-        }))
+        Ok(Some(TraceEntry::new(start..end, line_number)))
+    }
+}
+
+// Twitter cache traces (https://github.com/twitter/cache-trace) are CSV files. Each
+// line has 7 fields: timestamp, anonymized key, key size, value size, client id,
+// operation and TTL. The key is a string, so its hash is used as the numeric key.
+// The traces written by `recorder::TraceRecorder` have the timestamps in seconds
+// with a fraction (e.g. `12.000250`), and no value size when it is unknown.
+pub struct TwitterTraceParser;
+
+impl TwitterTraceParser {
+    const NUM_FIELDS: usize = 7;
+
+    fn operation(op: &str) -> Option<TraceOperation> {
+        match op {
+            "get" | "gets" => Some(TraceOperation::Read),
+            "set" | "add" | "replace" | "cas" | "append" | "prepend" | "incr" | "decr" => {
+                Some(TraceOperation::Write)
+            }
+            "delete" => Some(TraceOperation::Delete),
+            _ => None,
+        }
+    }
+
+    /// Parses a timestamp in seconds, optionally with a fraction of up to 9 digits.
+    fn timestamp(text: &str) -> Result<Duration, TraceParseError> {
+        let Some((secs, fraction)) = text.split_once('.') else {
+            return Ok(Duration::from_secs(parse_number("timestamp", text)?));
+        };
+        if fraction.is_empty()
+            || fraction.len() > 9
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(TraceParseError::InvalidNumber {
+                field: "timestamp",
+                text: text.to_string(),
+            });
+        }
+        let nanos =
+            parse_number::<u32>("timestamp", fraction)? * 10u32.pow(9 - fraction.len() as u32);
+        Ok(Duration::new(parse_number("timestamp", secs)?, nanos))
+    }
+
+    fn is_twitter_line(line: &str) -> bool {
+        let fields = line.split(',').collect::<Vec<_>>();
+        fields.len() == Self::NUM_FIELDS && Self::operation(fields[5]).is_some()
+    }
+}
+
+impl TraceParser<TraceEntry> for TwitterTraceParser {
+    fn parse(&mut self, line: &str, line_number: usize) -> anyhow::Result<Option<TraceEntry>> {
+        let fields = line.split(',').collect::<Vec<_>>();
+        if fields.len() != Self::NUM_FIELDS {
+            return Err(TraceParseError::FieldCount {
+                expected: Self::NUM_FIELDS,
+                found: fields.len(),
+            }
+            .into());
+        }
+        let Some(operation) = Self::operation(fields[5]) else {
+            return Err(TraceParseError::UnknownOperation(fields[5].to_string()).into());
+        };
+        let timestamp = Self::timestamp(fields[0])?;
+        // Drop the highest bit so that `key + 1` never overflows.
+        let key = (xxhash_rust::xxh3::xxh3_64(fields[1].as_bytes()) >> 1) as usize;
+        let value_size = match fields[3] {
+            "" => None,
+            size => Some(parse_number("value size", size)?),
+        };
+        let ttl = match parse_number::<u64>("TTL", fields[6])? {
+            // TTL 0 means the entry never expires.
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+
+        let mut entry = TraceEntry::new(key..(key + 1), line_number);
+        entry.key = Some(fields[1].into());
+        entry.timestamp = Some(timestamp);
+        entry.operation = Some(operation);
+        entry.value_size = value_size;
+        entry.ttl = ttl;
+        Ok(Some(entry))
+    }
+}
+
+// MSR Cambridge block I/O traces (http://iotta.snia.org/traces/block-io/388) and
+// the other SNIA block I/O traces in the same layout are CSV files. Each line has 7
+// fields: timestamp (in 100 ns units, usually since 1601-01-01), hostname, disk
+// number, type (`Read` or `Write`), offset and size in bytes, and response time.
+// The requested bytes are turned into a range of block numbers, which are
+// namespaced by the hostname and disk number.
+pub struct MsrTraceParser;
+
+impl MsrTraceParser {
+    const NUM_FIELDS: usize = 7;
+    /// The size of a block, i.e. a key, in bytes.
+    const BLOCK_SIZE: u64 = 4096;
+    /// The number of bits for the block numbers (up to 256 TiB per disk). The
+    /// bits above them hold the hash of the hostname and disk number, and the
+    /// highest bit is left unused so that the end of a range never overflows.
+    const BLOCK_BITS: u32 = 36;
+    const DISK_BITS: u32 = 27;
+    /// The seconds from 1601-01-01 (the epoch of Windows file times) to the UNIX
+    /// epoch.
+    const UNIX_EPOCH_SECS: u64 = 11_644_473_600;
+
+    fn operation(op: &str) -> Option<TraceOperation> {
+        if op.eq_ignore_ascii_case("read") || op.eq_ignore_ascii_case("r") {
+            Some(TraceOperation::Read)
+        } else if op.eq_ignore_ascii_case("write") || op.eq_ignore_ascii_case("w") {
+            Some(TraceOperation::Write)
+        } else {
+            None
+        }
+    }
+
+    fn is_msr_line(line: &str) -> bool {
+        let fields = line.split(',').collect::<Vec<_>>();
+        fields.len() == Self::NUM_FIELDS && Self::operation(fields[3]).is_some()
+    }
+
+    /// Returns the namespace of the blocks of the disk, which is the hash of the
+    /// hostname and disk number. The hashes of different disks may collide with
+    /// a very small probability.
+    fn namespace(host: &str, disk: &str) -> usize {
+        let hash = xxhash_rust::xxh3::xxh3_64(format!("{host}:{disk}").as_bytes());
+        ((hash >> (u64::BITS - Self::DISK_BITS)) as usize) << Self::BLOCK_BITS
+    }
+}
+
+impl TraceParser<TraceEntry> for MsrTraceParser {
+    fn parse(&mut self, line: &str, line_number: usize) -> anyhow::Result<Option<TraceEntry>> {
+        let fields = line.split(',').collect::<Vec<_>>();
+        if fields.len() != Self::NUM_FIELDS {
+            return Err(TraceParseError::FieldCount {
+                expected: Self::NUM_FIELDS,
+                found: fields.len(),
+            }
+            .into());
+        }
+        let Some(operation) = Self::operation(fields[3]) else {
+            return Err(TraceParseError::UnknownOperation(fields[3].to_string()).into());
+        };
+        let ticks = parse_number::<u64>("timestamp", fields[0])?;
+        let mut timestamp = Duration::from_secs(ticks / 10_000_000)
+            + Duration::from_nanos(ticks % 10_000_000 * 100);
+        // Convert a Windows file time into a UNIX time, so that it fits in the
+        // binary format. Keep a time relative to the start of the trace as is.
+        if let Some(unix_time) = timestamp.checked_sub(Duration::from_secs(Self::UNIX_EPOCH_SECS)) {
+            timestamp = unix_time;
+        }
+        let offset = parse_number::<u64>("offset", fields[4])?;
+        let size = parse_number::<u64>("size", fields[5])?;
+
+        let first = offset / Self::BLOCK_SIZE;
+        // Count a request with size 0 as a request for one block.
+        let last = offset
+            .checked_add(size.max(1) - 1)
+            .ok_or(TraceParseError::RangeOverflow {
+                start: offset as usize,
+                len: size as usize,
+            })?
+            / Self::BLOCK_SIZE;
+        if last >= 1 << Self::BLOCK_BITS {
+            return Err(TraceParseError::OffsetOutOfRange(offset).into());
+        }
+        let start = Self::namespace(fields[1], fields[2]) | first as usize;
+        let len = (last - first + 1) as usize;
+
+        let mut entry = TraceEntry::new(start..(start + len), line_number);
+        entry.timestamp = Some(timestamp);
+        entry.operation = Some(operation);
+        Ok(Some(entry))
+    }
+}
+
+/// The formats of the trace files that mokabench can read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceFormat {
+    /// ARC traces. Each line has 2+ numbers separated by a space, the first two
+    /// being `start` and `len` of a range of keys.
+    Arc,
+    /// LIRS/LIRS2 traces. Each line has a single key or `*` (a NOOP).
+    Lirs,
+    /// Twitter cache traces. Each line is a CSV record with a timestamp, string
+    /// key, value size, operation and TTL.
+    Twitter,
+    /// MSR Cambridge and the other SNIA block I/O traces. Each line is a CSV
+    /// record with a timestamp, hostname, disk number, type, offset, size and
+    /// response time.
+    Msr,
+    /// The compact binary format of mokabench, converted from one of the other
+    /// formats by `mokabench convert`.
+    Binary,
+    /// A synthetic workload generated by mokabench (see `--workload`), which has
+    /// no file.
+    #[serde(skip)]
+    Synthetic,
+}
+
+impl TraceFormat {
+    /// The maximum number of non-empty lines to read when detecting the format.
+    const DETECTION_LINES: usize = 1_000;
+
+    /// Returns a parser for the lines of a trace in this format. `None` for the
+    /// binary format, which has no lines.
+    pub(crate) fn line_parser(&self) -> Option<Box<dyn TraceParser<TraceEntry> + Send>> {
+        match self {
+            Self::Arc | Self::Lirs => Some(Box::new(GenericTraceParser)),
+            Self::Twitter => Some(Box::new(TwitterTraceParser)),
+            Self::Msr => Some(Box::new(MsrTraceParser)),
+            Self::Binary | Self::Synthetic => None,
+        }
+    }
+
+    /// Returns an iterator of the entries read from a trace in this format. The
+    /// errors of malformed lines tell the `source` and line number of the line.
+    /// If `lenient` is `true`, malformed lines are skipped instead; use
+    /// `validate_trace` to report them.
+    pub(crate) fn read_entries(
+        &self,
+        reader: Box<dyn BufRead + Send>,
+        source: &Path,
+        lenient: bool,
+    ) -> anyhow::Result<TraceEntries> {
+        let Some(mut parser) = self.line_parser() else {
+            return Ok(Box::new(binary::BinaryTraceReader::new(reader)?));
+        };
+        let source = source.display().to_string();
+        let entries = reader
+            .lines()
+            .enumerate()
+            .filter_map(move |(i, line)| parse_line(&mut *parser, line, i, &source, lenient));
+        Ok(Box::new(entries))
+    }
+
+    /// Reads and parses the whole trace, and returns its entries in the order of
+    /// the lines. A plain text trace file is split into segments at line
+    /// boundaries, which are read by their offsets and parsed by worker threads in
+    /// parallel. The other traces (e.g. compressed ones) are parsed by the calling
+    /// thread.
+    pub(crate) fn parse_all(
+        &self,
+        input: &TraceInput,
+        source: &Path,
+        lenient: bool,
+    ) -> anyhow::Result<Vec<TraceEntry>> {
+        let (Some(path), Some(_)) = (input.plain_file(), self.line_parser()) else {
+            return self.read_entries(input.open()?, source, lenient)?.collect();
+        };
+        let segments =
+            trace_reader::split_lines(path, MIN_SEGMENT_SIZE, crate::available_parallelism())
+                .with_context(|| format!("Cannot read {}", source.display()))?;
+        let source = source.display().to_string();
+
+        std::thread::scope(|s| {
+            // The lines of the segments are counted first to number the lines of
+            // the segments after them.
+            let line_counts = segments
+                .iter()
+                .map(|range| {
+                    s.spawn(move || {
+                        trace_reader::count_lines(trace_reader::read_segment(path, range)?)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut handles = Vec::with_capacity(segments.len());
+            let mut first_line = 0;
+            for (range, line_count) in segments.iter().zip(line_counts) {
+                let source = &source;
+                handles.push(s.spawn(move || {
+                    let mut parser = self.line_parser().expect("A text trace format");
+                    trace_reader::read_segment(path, range)?
+                        .lines()
+                        .enumerate()
+                        .filter_map(|(i, line)| {
+                            parse_line(&mut *parser, line, first_line + i, source, lenient)
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()
+                }));
+                first_line += line_count
+                    .join()
+                    .expect("A line counter thread panicked")
+                    .with_context(|| format!("Cannot read {source}"))?;
+            }
+
+            let mut entries = Vec::new();
+            for handle in handles {
+                entries.append(&mut handle.join().expect("A parser thread panicked")?);
+            }
+            Ok(entries)
+        })
+    }
+
+    /// Returns `true` if the traces in this format record the operations (e.g.
+    /// reads, writes and deletes) in addition to the keys. `false` for the binary
+    /// format, as a binary trace records them only if its source did; see
+    /// `TraceDescriptor::has_operations`.
+    pub fn has_operations(&self) -> bool {
+        matches!(self, Self::Twitter | Self::Msr)
+    }
+
+    /// Returns `true` if the traces in this format record the time of each
+    /// request. `false` for the binary format, as a binary trace records them only
+    /// if its source did; see `TraceDescriptor::has_timestamps`.
+    pub fn has_timestamps(&self) -> bool {
+        matches!(self, Self::Twitter | Self::Msr)
+    }
+
+    /// Detects the format of a trace by reading its first bytes or lines.
+    pub fn detect(mut reader: impl BufRead) -> anyhow::Result<Self> {
+        if binary::has_magic(&mut reader)? {
+            return Ok(Self::Binary);
+        }
+
+        let mut num_columns = None;
+        let mut num_lines = 0;
+        let mut first_malformed = None;
+
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            num_lines += 1;
+            if num_lines > Self::DETECTION_LINES {
+                break;
+            }
+
+            if line == "*" {
+                return Ok(Self::Lirs);
+            }
+            if TwitterTraceParser::is_twitter_line(line) {
+                return Ok(Self::Twitter);
+            }
+            if MsrTraceParser::is_msr_line(line) {
+                return Ok(Self::Msr);
+            }
+            if line.split(' ').any(|token| token.parse::<usize>().is_err()) {
+                // Tolerate a few malformed lines, so that they can be reported by
+                // `validate_trace` or skipped in the lenient mode.
+                first_malformed.get_or_insert_with(|| line.to_string());
+                num_lines -= 1;
+                continue;
+            }
+
+            let columns = if line.contains(' ') { 2 } else { 1 };
+            match num_columns {
+                None => num_columns = Some(columns),
+                Some(n) if n == columns => (),
+                Some(_) => anyhow::bail!(
+                    "Unknown trace format. Lines have different number of columns: {}",
+                    line
+                ),
+            }
+        }
+
+        match num_columns {
+            Some(1) => Ok(Self::Lirs),
+            Some(_) => Ok(Self::Arc),
+            None => match first_malformed {
+                Some(line) => {
+                    anyhow::bail!("Unknown trace format. Not a list of integers: {}", line)
+                }
+                None => anyhow::bail!("Cannot detect the trace format of an empty trace"),
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arc" => Ok(Self::Arc),
+            "lirs" => Ok(Self::Lirs),
+            "twitter" => Ok(Self::Twitter),
+            "msr" => Ok(Self::Msr),
+            "binary" => Ok(Self::Binary),
+            _ => anyhow::bail!(
+                r#"The trace format must be "arc", "lirs", "twitter", "msr" or "binary", but got "{}""#,
+                s
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_twitter(line: &str) -> anyhow::Result<TraceEntry> {
+        Ok(TwitterTraceParser.parse(line, 0)?.expect("An entry"))
+    }
+
+    #[test]
+    fn parse_all_segments() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("trace.lis");
+        // Large enough to be split into segments.
+        let trace = (0..300_000)
+            .map(|i| format!("{} {} 0 0\n", i, i % 3 + 1))
+            .collect::<String>();
+        std::fs::write(&path, trace)?;
+
+        let input = TraceInput::File(path.clone());
+        let entries = TraceFormat::Arc.parse_all(&input, &path, false)?;
+        assert_eq!(entries.len(), 300_000);
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(entry.range(), i..(i + i % 3 + 1));
+            assert_eq!(entry.line_number(), i);
+        }
+
+        // A malformed line is reported by its line number in the whole file.
+        let mut trace = std::fs::read_to_string(&path)?;
+        trace.push_str("x 1 0 0\n");
+        std::fs::write(&path, trace)?;
+        let err = TraceFormat::Arc
+            .parse_all(&input, &path, false)
+            .unwrap_err();
+        assert!(err.to_string().ends_with(":300001: x 1 0 0"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn twitter_operations() -> anyhow::Result<()> {
+        let operations = [
+            ("get", TraceOperation::Read),
+            ("gets", TraceOperation::Read),
+            ("set", TraceOperation::Write),
+            ("add", TraceOperation::Write),
+            ("replace", TraceOperation::Write),
+            ("delete", TraceOperation::Delete),
+        ];
+        for (op, expected) in operations {
+            let entry = parse_twitter(&format!("0,key1,4,100,1,{op},0"))?;
+            assert_eq!(entry.operation(), Some(expected), "{op}");
+        }
+        assert!(TwitterTraceParser
+            .parse("0,key1,4,100,1,touch,0", 0)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn twitter_fields() -> anyhow::Result<()> {
+        let entry = parse_twitter("5,key1,4,512,1,set,60")?;
+        assert_eq!(entry.range().len(), 1);
+        assert_eq!(entry.value_size(), Some(512));
+        assert_eq!(entry.ttl(), Some(Duration::from_secs(60)));
+
+        // The same key has the same hash, and TTL 0 means no expiration.
+        let other = parse_twitter("6,key1,4,0,2,get,0")?;
+        assert_eq!(other.range(), entry.range());
+        assert_eq!(other.value_size(), Some(0));
+        assert_eq!(other.ttl(), None);
+
+        assert_ne!(parse_twitter("6,key2,4,0,2,get,0")?.range(), entry.range());
+        assert!(TwitterTraceParser.parse("5,key1,4,512,1,set", 0).is_err());
+        assert!(TwitterTraceParser.parse("5,key1,4,-1,1,set,60", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn twitter_fractional_timestamps() -> anyhow::Result<()> {
+        let timestamp = |text: &str| parse_twitter(&format!("{text},key1,4,,1,get,0"));
+        assert_eq!(timestamp("12")?.timestamp(), Some(Duration::from_secs(12)));
+        assert_eq!(
+            timestamp("12.000250")?.timestamp(),
+            Some(Duration::from_micros(12_000_250))
+        );
+        assert_eq!(
+            timestamp("0.5")?.timestamp(),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            timestamp("1.123456789")?.timestamp(),
+            Some(Duration::new(1, 123_456_789))
+        );
+        // An empty value size is unknown.
+        assert_eq!(timestamp("12")?.value_size(), None);
+
+        for invalid in ["12.", ".5", "1.1234567890", "1.-5", "1.5e3"] {
+            assert!(timestamp(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    fn parse_msr(line: &str) -> anyhow::Result<TraceEntry> {
+        Ok(MsrTraceParser.parse(line, 0)?.expect("An entry"))
+    }
+
+    #[test]
+    fn msr_blocks() -> anyhow::Result<()> {
+        let blocks = |offset: u64, size: u64| -> anyhow::Result<_> {
+            let range =
+                parse_msr(&format!("128166372003061629,hm,1,Read,{offset},{size},100"))?.range();
+            let base = range.start & !((1 << MsrTraceParser::BLOCK_BITS) - 1);
+            Ok((range.start - base)..(range.end - base))
+        };
+        assert_eq!(blocks(0, 4096)?, 0..1);
+        assert_eq!(blocks(4096, 8192)?, 1..3);
+        // A request not aligned to the blocks touches all blocks it overlaps.
+        assert_eq!(blocks(4095, 2)?, 0..2);
+        assert_eq!(blocks(12288 + 512, 512)?, 3..4);
+        // A request with size 0 is a request for one block.
+        assert_eq!(blocks(8192, 0)?, 2..3);
+
+        let max_offset = (1u64 << MsrTraceParser::BLOCK_BITS) * MsrTraceParser::BLOCK_SIZE;
+        assert!(blocks(max_offset - 4096, 4096).is_ok());
+        assert!(blocks(max_offset, 4096).is_err());
+        assert!(blocks(u64::MAX, 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn msr_disk_namespaces() -> anyhow::Result<()> {
+        let start = |host: &str, disk: &str| -> anyhow::Result<usize> {
+            Ok(parse_msr(&format!("0,{host},{disk},Write,8192,4096,100"))?
+                .range()
+                .start)
+        };
+        let block = start("hm", "0")?;
+        assert_eq!(block & ((1 << MsrTraceParser::BLOCK_BITS) - 1), 2);
+        assert_eq!(start("hm", "0")?, block);
+        // The same block of other disks or hosts are different keys.
+        assert_ne!(start("hm", "1")?, block);
+        assert_ne!(start("prn", "0")?, block);
+        // The highest bit is unused.
+        assert_eq!(block >> (usize::BITS - 1), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn msr_timestamps() -> anyhow::Result<()> {
+        // A Windows file time (100 ns units since 1601-01-01) is converted into a
+        // UNIX time: 2007-02-22 17:00:00.3061629 UTC.
+        let entry = parse_msr("128166372003061629,hm,0,Read,0,4096,100")?;
+        assert_eq!(
+            entry.timestamp(),
+            Some(Duration::new(1_172_163_600, 306_162_900))
+        );
+        assert_eq!(entry.operation(), Some(TraceOperation::Read));
+        // A time relative to the start of the trace is kept as is.
+        let entry = parse_msr("25000000,hm,0,w,0,4096,100")?;
+        assert_eq!(entry.timestamp(), Some(Duration::from_millis(2_500)));
+        assert_eq!(entry.operation(), Some(TraceOperation::Write));
+
+        assert!(MsrTraceParser.parse("x,hm,0,Read,0,4096,100", 0).is_err());
+        assert!(MsrTraceParser.parse("0,hm,0,Trim,0,4096,100", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn detect_formats() -> anyhow::Result<()> {
+        let detect = |text: &str| TraceFormat::detect(text.as_bytes());
+        assert_eq!(detect("1 8 0 1\n9 1 0 1\n")?, TraceFormat::Arc);
+        assert_eq!(detect("1\n*\n2\n")?, TraceFormat::Lirs);
+        assert_eq!(detect("0,key1,4,100,1,get,0\n")?, TraceFormat::Twitter);
+        assert!(detect("a b c\n").is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,459 @@
+//! The compact binary trace format of mokabench.
+//!
+//! A binary trace file starts with a fixed-size header followed by the records.
+//! All integers are little endian.
+//!
+//! Header (20 bytes):
+//!
+//! | Field         | Type      | Description                                  |
+//! |---------------|-----------|----------------------------------------------|
+//! | `magic`       | `[u8; 8]` | `MOKATRCE`                                   |
+//! | `version`     | `u16`     | The version of the format. Currently `1`.    |
+//! | `source`      | `u8`      | The format converted from. 0: ARC, 1: LIRS, 2: Twitter, 3: MSR |
+//! | `columns`     | `u8`      | The bit flags of the optional columns.       |
+//! | `num_records` | `u64`     | The number of records.                       |
+//!
+//! Record:
+//!
+//! | Column        | Type             | Description                                         |
+//! |---------------|------------------|-----------------------------------------------------|
+//! | `line_gap`    | LEB128 varint    | The number of lines skipped since the last record.  |
+//! | `key`         | `u64`            | The (first) numeric key.                            |
+//! | `len`         | `u32`            | Optional. The length of the range of keys.          |
+//! | `operation`   | `u8`             | Optional. 0: none, 1: read, 2: write, 3: delete.    |
+//! | `value_size`  | `u32`            | Optional. The value size in bytes.                  |
+//! | `ttl`         | `u32`            | Optional. The TTL in seconds. 0 means no TTL.       |
+//! | `text_key`    | `u16` + bytes    | Optional. The length and UTF-8 bytes of the textual key. |
+//! | `timestamp`   | `u64`            | Optional. The time of the request in microseconds.  |
+//!
+//! The keys are fixed-width so that the records can be decoded without parsing.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use super::{TraceEntry, TraceFormat, TraceOperation};
+use crate::trace_reader::TraceInput;
+
+const MAGIC: &[u8; 8] = b"MOKATRCE";
+const VERSION: u16 = 1;
+/// The offset of `num_records` in the header.
+const NUM_RECORDS_OFFSET: u64 = 12;
+
+const COLUMN_LEN: u8 = 1;
+const COLUMN_OPERATION: u8 = 1 << 1;
+const COLUMN_VALUE_SIZE: u8 = 1 << 2;
+const COLUMN_TTL: u8 = 1 << 3;
+const COLUMN_TEXT_KEY: u8 = 1 << 4;
+const COLUMN_TIMESTAMP: u8 = 1 << 5;
+
+/// The optional columns recorded in a binary trace, which are the ones its source
+/// trace had.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct BinaryColumns(u8);
+
+impl BinaryColumns {
+    pub(crate) fn has_operations(&self) -> bool {
+        self.0 & COLUMN_OPERATION != 0
+    }
+
+    pub(crate) fn has_timestamps(&self) -> bool {
+        self.0 & COLUMN_TIMESTAMP != 0
+    }
+}
+
+/// Reads the header of a binary trace and returns its columns.
+pub(crate) fn read_columns(reader: impl BufRead) -> anyhow::Result<BinaryColumns> {
+    Ok(BinaryTraceReader::new(reader)?.columns())
+}
+
+/// Returns `true` if the reader starts with the magic bytes of the binary format.
+pub(crate) fn has_magic(reader: &mut impl BufRead) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(MAGIC))
+}
+
+/// Converts the trace file at `input` into the binary format and writes it to
+/// `output`. If `format` is `None`, the format of the input is detected from its
+/// contents. Returns the number of records written.
+pub fn convert_trace(
+    input: &Path,
+    format: Option<TraceFormat>,
+    output: &Path,
+) -> anyhow::Result<u64> {
+    let trace = TraceInput::new(input)?;
+    let format = match format {
+        Some(format) => format,
+        None => TraceFormat::detect(trace.peek()?)
+            .with_context(|| format!("Cannot detect the format of {}", input.display()))?,
+    };
+    if format == TraceFormat::Binary {
+        anyhow::bail!("{} is already in the binary format", input.display());
+    }
+
+    let file =
+        File::create(output).with_context(|| format!("Cannot create {}", output.display()))?;
+    let mut writer = BinaryTraceWriter::new(BufWriter::new(file), format)?;
+    for entry in format.read_entries(trace.open()?, input, false)? {
+        writer.write(&entry?)?;
+    }
+    Ok(writer.finish()?)
+}
+
+fn source_id(source: TraceFormat) -> u8 {
+    match source {
+        TraceFormat::Arc => 0,
+        TraceFormat::Lirs => 1,
+        TraceFormat::Twitter => 2,
+        TraceFormat::Msr => 3,
+        TraceFormat::Binary | TraceFormat::Synthetic => {
+            unreachable!("A binary trace or synthetic workload cannot be the source")
+        }
+    }
+}
+
+fn source_format(id: u8) -> Option<TraceFormat> {
+    match id {
+        0 => Some(TraceFormat::Arc),
+        1 => Some(TraceFormat::Lirs),
+        2 => Some(TraceFormat::Twitter),
+        3 => Some(TraceFormat::Msr),
+        _ => None,
+    }
+}
+
+fn columns(source: TraceFormat) -> u8 {
+    match source {
+        TraceFormat::Arc => COLUMN_LEN,
+        TraceFormat::Lirs | TraceFormat::Binary | TraceFormat::Synthetic => 0,
+        TraceFormat::Twitter => {
+            COLUMN_OPERATION | COLUMN_VALUE_SIZE | COLUMN_TTL | COLUMN_TEXT_KEY | COLUMN_TIMESTAMP
+        }
+        TraceFormat::Msr => COLUMN_LEN | COLUMN_OPERATION | COLUMN_TIMESTAMP,
+    }
+}
+
+/// Writes trace entries in the binary format.
+pub(crate) struct BinaryTraceWriter<W: Write + Seek> {
+    writer: W,
+    columns: u8,
+    next_line: usize,
+    num_records: u64,
+}
+
+impl<W: Write + Seek> BinaryTraceWriter<W> {
+    /// Creates a writer for the entries parsed from a trace in `source` format,
+    /// and writes the header.
+    pub(crate) fn new(mut writer: W, source: TraceFormat) -> io::Result<Self> {
+        let columns = columns(source);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[source_id(source), columns])?;
+        // The number of records is filled in by `finish`.
+        writer.write_all(&0u64.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            columns,
+            next_line: 0,
+            num_records: 0,
+        })
+    }
+
+    pub(crate) fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        let w = &mut self.writer;
+        write_varint(w, (entry.line_number - self.next_line) as u64)?;
+        self.next_line = entry.line_number + 1;
+
+        w.write_all(&(entry.range.start as u64).to_le_bytes())?;
+        if self.columns & COLUMN_LEN != 0 {
+            let len = u32::try_from(entry.range.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The key range of {} keys is too long", entry.range.len()),
+                )
+            })?;
+            w.write_all(&len.to_le_bytes())?;
+        }
+        if self.columns & COLUMN_OPERATION != 0 {
+            let op = match entry.operation {
+                None => 0u8,
+                Some(TraceOperation::Read) => 1,
+                Some(TraceOperation::Write) => 2,
+                Some(TraceOperation::Delete) => 3,
+            };
+            w.write_all(&[op])?;
+        }
+        if self.columns & COLUMN_VALUE_SIZE != 0 {
+            w.write_all(&entry.value_size.unwrap_or_default().to_le_bytes())?;
+        }
+        if self.columns & COLUMN_TTL != 0 {
+            let secs = entry.ttl.map_or(0, |ttl| ttl.as_secs() as u32);
+            w.write_all(&secs.to_le_bytes())?;
+        }
+        if self.columns & COLUMN_TEXT_KEY != 0 {
+            let key = entry.key.as_deref().unwrap_or_default().as_bytes();
+            let len = u16::try_from(key.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "The key is too long"))?;
+            w.write_all(&len.to_le_bytes())?;
+            w.write_all(key)?;
+        }
+        if self.columns & COLUMN_TIMESTAMP != 0 {
+            let micros =
+                u64::try_from(entry.timestamp.unwrap_or_default().as_micros()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "The timestamp is too large")
+                })?;
+            w.write_all(&micros.to_le_bytes())?;
+        }
+
+        self.num_records += 1;
+        Ok(())
+    }
+
+    /// Writes the number of records to the header and flushes the writer.
+    pub(crate) fn finish(mut self) -> io::Result<u64> {
+        self.writer.seek(SeekFrom::Start(NUM_RECORDS_OFFSET))?;
+        self.writer.write_all(&self.num_records.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.num_records)
+    }
+}
+
+/// Reads trace entries in the binary format.
+pub(crate) struct BinaryTraceReader<R> {
+    reader: R,
+    columns: u8,
+    next_line: usize,
+    remaining: u64,
+}
+
+impl<R: BufRead> BinaryTraceReader<R> {
+    pub(crate) fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut header = [0u8; 20];
+        reader
+            .read_exact(&mut header)
+            .context("Cannot read the header of the binary trace")?;
+        if &header[0..8] != MAGIC {
+            anyhow::bail!("Not a binary trace file");
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version != VERSION {
+            anyhow::bail!(
+                "Unsupported binary trace version: {} (expected {}). Convert the trace again",
+                version,
+                VERSION
+            );
+        }
+        if source_format(header[10]).is_none() {
+            anyhow::bail!("Unknown source format in the binary trace: {}", header[10]);
+        }
+        let columns = header[11];
+        let remaining = u64::from_le_bytes(header[12..20].try_into().unwrap());
+
+        Ok(Self {
+            reader,
+            columns,
+            next_line: 0,
+            remaining,
+        })
+    }
+
+    pub(crate) fn columns(&self) -> BinaryColumns {
+        BinaryColumns(self.columns)
+    }
+
+    fn read_entry(&mut self) -> io::Result<TraceEntry> {
+        let r = &mut self.reader;
+        let line_number = self.next_line + read_varint(r)? as usize;
+        self.next_line = line_number + 1;
+
+        let start = read_u64(r)? as usize;
+        let len = if self.columns & COLUMN_LEN != 0 {
+            read_u32(r)? as usize
+        } else {
+            1
+        };
+        let end = start.checked_add(len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The key range {start} + {len} overflows"),
+            )
+        })?;
+        let mut entry = TraceEntry::new(start..end, line_number);
+
+        if self.columns & COLUMN_OPERATION != 0 {
+            entry.operation = match read_u8(r)? {
+                1 => Some(TraceOperation::Read),
+                2 => Some(TraceOperation::Write),
+                3 => Some(TraceOperation::Delete),
+                _ => None,
+            };
+        }
+        if self.columns & COLUMN_VALUE_SIZE != 0 {
+            entry.value_size = Some(read_u32(r)?);
+        }
+        if self.columns & COLUMN_TTL != 0 {
+            entry.ttl = match read_u32(r)? {
+                0 => None,
+                secs => Some(Duration::from_secs(secs as u64)),
+            };
+        }
+        if self.columns & COLUMN_TEXT_KEY != 0 {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            let mut key = vec![0u8; u16::from_le_bytes(buf) as usize];
+            r.read_exact(&mut key)?;
+            let key = String::from_utf8(key)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            entry.key = Some(key.into_boxed_str());
+        }
+        if self.columns & COLUMN_TIMESTAMP != 0 {
+            entry.timestamp = Some(Duration::from_micros(read_u64(r)?));
+        }
+
+        Ok(entry)
+    }
+}
+
+impl<R: BufRead> Iterator for BinaryTraceReader<R> {
+    type Item = anyhow::Result<TraceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(
+            self.read_entry()
+                .context("Cannot read a record of the binary trace"),
+        )
+    }
+}
+
+fn write_varint(w: &mut impl Write, mut n: u64) -> io::Result<()> {
+    while n >= 0x80 {
+        w.write_all(&[(n as u8) | 0x80])?;
+        n >>= 7;
+    }
+    w.write_all(&[n as u8])
+}
+
+fn read_varint(r: &mut impl Read) -> io::Result<u64> {
+    let mut n = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = read_u8(r)?;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The varint is too long",
+            ));
+        }
+    }
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn write_entries(source: TraceFormat, entries: &[TraceEntry]) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        let mut writer = BinaryTraceWriter::new(&mut buf, source).unwrap();
+        for entry in entries {
+            writer.write(entry).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), entries.len() as u64);
+        buf.into_inner()
+    }
+
+    fn round_trip(source: TraceFormat, entries: &[TraceEntry]) -> BinaryColumns {
+        let reader = BinaryTraceReader::new(Cursor::new(write_entries(source, entries))).unwrap();
+        let columns = reader.columns();
+        let read = reader.collect::<anyhow::Result<Vec<_>>>().unwrap();
+        assert_eq!(read.len(), entries.len());
+        for (expected, actual) in entries.iter().zip(&read) {
+            assert_eq!(expected.range, actual.range);
+            assert_eq!(expected.line_number, actual.line_number);
+            assert_eq!(expected.operation, actual.operation);
+            assert_eq!(expected.value_size, actual.value_size);
+            assert_eq!(expected.ttl, actual.ttl);
+            assert_eq!(expected.key, actual.key);
+            assert_eq!(expected.timestamp, actual.timestamp);
+        }
+        columns
+    }
+
+    #[test]
+    fn round_trip_twitter() {
+        let mut set = TraceEntry::new(42..43, 0);
+        set.operation = Some(TraceOperation::Write);
+        set.value_size = Some(512);
+        set.ttl = Some(Duration::from_secs(60));
+        set.key = Some("user:42".into());
+        set.timestamp = Some(Duration::from_micros(3_250_001));
+
+        // The line numbers skip the lines that had no entries.
+        let mut get = TraceEntry::new(42..43, 1_000);
+        get.operation = Some(TraceOperation::Read);
+        get.value_size = Some(512);
+        get.key = Some("user:42".into());
+        get.timestamp = Some(Duration::from_secs(4));
+
+        let columns = round_trip(TraceFormat::Twitter, &[set, get]);
+        assert!(columns.has_operations());
+        assert!(columns.has_timestamps());
+    }
+
+    #[test]
+    fn round_trip_arc() {
+        let entries = [TraceEntry::new(100..108, 0), TraceEntry::new(7..8, 1)];
+        let columns = round_trip(TraceFormat::Arc, &entries);
+        assert!(!columns.has_operations());
+        assert!(!columns.has_timestamps());
+    }
+
+    #[test]
+    fn reject_too_long_range() {
+        let mut writer = BinaryTraceWriter::new(Cursor::new(Vec::new()), TraceFormat::Arc).unwrap();
+        let entry = TraceEntry::new(0..(u32::MAX as usize + 1), 0);
+        let err = writer.write(&entry).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reject_truncated_record() {
+        let mut bytes = write_entries(TraceFormat::Lirs, &[TraceEntry::new(1..2, 0)]);
+        bytes.pop();
+        let mut reader = BinaryTraceReader::new(Cursor::new(bytes)).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Records the requests to a cache of an application as a trace that mokabench
+//! can replay.
+//!
+//! The trace is written in the Twitter cache trace format, one CSV line per
+//! request:
+//!
+//! ```text
+//! timestamp,key,key_size,value_size,client_id,operation,ttl
+//! ```
+//!
+//! The timestamps are in seconds with six decimal places, and the value size is
+//! left empty when it is not known. mokabench reads both, but the other tools for
+//! the Twitter traces may not.
+//!
+//! Run the benchmarks with it by `--trace-path`, or convert it into the binary
+//! format by `mokabench convert` first.
+//!
+//! ```no_run
+//! use mokabench::recorder::TraceRecorder;
+//!
+//! let mut recorder = TraceRecorder::create("my-service.csv")?;
+//! recorder.get("user:42", None)?;
+//! recorder.insert("user:42", Some(512), None)?;
+//! recorder.invalidate("user:42")?;
+//! recorder.finish()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! The methods take `&mut self`. To record the requests of several threads, share
+//! the recorder in a `Mutex`.
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::parser::TraceOperation;
+
+/// Writes the requests to a cache as a trace.
+pub struct TraceRecorder<W: Write> {
+    writer: W,
+    start: Instant,
+    last_timestamp: Duration,
+    hash_keys: bool,
+    num_records: u64,
+    key_buf: String,
+}
+
+impl TraceRecorder<BufWriter<File>> {
+    /// Creates a recorder writing the trace to the file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> TraceRecorder<W> {
+    /// Creates a recorder writing the trace to `writer`. The timestamps of the
+    /// requests are the time since this call, unless given by `record`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            start: Instant::now(),
+            last_timestamp: Duration::ZERO,
+            hash_keys: false,
+            num_records: 0,
+            key_buf: String::new(),
+        }
+    }
+
+    /// If `v` is `true`, writes the hashes of the keys instead of the keys, so that
+    /// the trace does not reveal them. Disabled by default.
+    ///
+    /// The keys with a comma or a line break are always hashed as they cannot be
+    /// written in the trace as-is.
+    pub fn set_hash_keys(&mut self, v: bool) {
+        self.hash_keys = v;
+    }
+
+    /// Records a read of the key. `value_size` is the size of the value in bytes
+    /// if the read was a hit, which is used for the value when the replay misses.
+    pub fn get(&mut self, key: impl Display, value_size: Option<u32>) -> io::Result<()> {
+        self.record(TraceOperation::Read, key, value_size, None, None)
+    }
+
+    /// Records an insert (or update) of the key with the size of the value in
+    /// bytes and the time-to-live.
+    pub fn insert(
+        &mut self,
+        key: impl Display,
+        value_size: Option<u32>,
+        ttl: Option<Duration>,
+    ) -> io::Result<()> {
+        self.record(TraceOperation::Write, key, value_size, ttl, None)
+    }
+
+    /// Records an invalidation of the key.
+    pub fn invalidate(&mut self, key: impl Display) -> io::Result<()> {
+        self.record(TraceOperation::Delete, key, None, None, None)
+    }
+
+    /// Records a request. `timestamp` is the time of the request since an
+    /// arbitrary epoch (e.g. the UNIX epoch), or `None` for the time since the
+    /// recorder was created. The timestamps are recorded in microseconds, and a
+    /// timestamp earlier than the previous one is recorded as the previous one, as
+    /// mokabench assumes them non-decreasing. `value_size` is recorded only if it
+    /// is given.
+    pub fn record(
+        &mut self,
+        operation: TraceOperation,
+        key: impl Display,
+        value_size: Option<u32>,
+        ttl: Option<Duration>,
+        timestamp: Option<Duration>,
+    ) -> io::Result<()> {
+        let timestamp = timestamp
+            .unwrap_or_else(|| self.start.elapsed())
+            .max(self.last_timestamp);
+        self.last_timestamp = timestamp;
+
+        self.key_buf.clear();
+        {
+            use std::fmt::Write;
+            write!(self.key_buf, "{key}").expect("Cannot format the key");
+        }
+        if self.hash_keys || self.key_buf.contains([',', '\n', '\r']) {
+            let hash = xxhash_rust::xxh3::xxh3_64(self.key_buf.as_bytes());
+            self.key_buf = format!("{hash:016x}");
+        }
+
+        let operation = match operation {
+            TraceOperation::Read => "get",
+            TraceOperation::Write => "set",
+            TraceOperation::Delete => "delete",
+        };
+        writeln!(
+            self.writer,
+            "{}.{:06},{},{},{},0,{},{}",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            self.key_buf,
+            self.key_buf.len(),
+            value_size.map_or_else(String::new, |size| size.to_string()),
+            operation,
+            // TTL 0 means the entry never expires.
+            ttl.map_or(0, |ttl| ttl.as_secs().max(1)),
+        )?;
+        self.num_records += 1;
+        Ok(())
+    }
+
+    /// Returns the number of requests recorded so far.
+    pub fn num_records(&self) -> u64 {
+        self.num_records
+    }
+
+    /// Flushes the trace and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{TraceEntry, TraceParser, TwitterTraceParser};
+
+    fn parse(trace: &[u8]) -> Vec<TraceEntry> {
+        let mut parser = TwitterTraceParser;
+        std::str::from_utf8(trace)
+            .unwrap()
+            .lines()
+            .enumerate()
+            .map(|(i, line)| parser.parse(line, i).unwrap().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() -> anyhow::Result<()> {
+        let mut recorder = TraceRecorder::new(Vec::new());
+        let ts = |micros| Some(Duration::from_micros(micros));
+        recorder.record(TraceOperation::Read, "user:42", None, None, ts(1_500_250))?;
+        recorder.record(
+            TraceOperation::Write,
+            "user:42",
+            Some(512),
+            Some(Duration::from_secs(60)),
+            ts(2_000_001),
+        )?;
+        // Earlier than the previous one.
+        recorder.record(TraceOperation::Read, "a,b", Some(0), None, ts(1_000_000))?;
+        recorder.record(TraceOperation::Delete, "user:42", None, None, ts(3_000_000))?;
+        assert_eq!(recorder.num_records(), 4);
+
+        let entries = parse(&recorder.finish()?);
+        assert_eq!(entries.len(), 4);
+
+        assert_eq!(entries[0].operation(), Some(TraceOperation::Read));
+        assert_eq!(entries[0].key(), Some("user:42"));
+        assert_eq!(entries[0].value_size(), None);
+        assert_eq!(entries[0].ttl(), None);
+        assert_eq!(entries[0].timestamp(), ts(1_500_250));
+
+        assert_eq!(entries[1].operation(), Some(TraceOperation::Write));
+        assert_eq!(entries[1].range(), entries[0].range());
+        assert_eq!(entries[1].value_size(), Some(512));
+        assert_eq!(entries[1].ttl(), Some(Duration::from_secs(60)));
+        assert_eq!(entries[1].timestamp(), ts(2_000_001));
+
+        // The key with a comma is hashed.
+        assert_eq!(entries[2].key().map(str::len), Some(16));
+        assert_eq!(entries[2].value_size(), Some(0));
+        assert_eq!(entries[2].timestamp(), ts(2_000_001));
+
+        assert_eq!(entries[3].operation(), Some(TraceOperation::Delete));
+        assert_eq!(entries[3].timestamp(), ts(3_000_000));
+        Ok(())
+    }
+}
@@ -20,6 +20,16 @@ impl ReportBuilder {
     pub fn build(&self) -> Report {
         Report::new(&self.name, self.capacity, self.num_workers)
     }
+
+    /// Returns a builder of the reports of a phase of a phased workload, which are
+    /// named after the phase.
+    pub fn for_phase(&self, phase: &str, num_workers: u16) -> Self {
+        Self::new(
+            &format!("{} [{}]", self.name, phase),
+            self.capacity,
+            Some(num_workers),
+        )
+    }
 }
 
 #[derive(Clone, Default)]
@@ -36,6 +46,113 @@ pub struct Report {
     pub eviction_count: u64,
     pub expiration_count: u64,
     pub duration: Option<Duration>,
+    pub has_starvation: bool,
+    // The total time the workers spent waiting for the producers in the streaming
+    // mode.
+    pub starvation: Duration,
+    // Reports the reads per second, which are compared between the phases of a
+    // phased workload.
+    pub has_throughput: bool,
+    // The counts of each tenant when several traces are interleaved.
+    pub tenants: Vec<TenantReport>,
+    // The counts of each class of requests of a synthetic workload (e.g. the hot
+    // reads and the scans).
+    pub classes: Vec<TenantReport>,
+    // The rate of the keys sampled from the trace.
+    pub sampling_rate: Option<f64>,
+    // The number of keys that would have been read without sampling.
+    pub num_unsampled_reads: u64,
+    // The reports of the phases when a phased workload is run.
+    pub phases: Vec<Report>,
+    // The init closure runs and the wake-ups of the waiters when a thundering herd
+    // workload is run.
+    pub contention: Option<ContentionReport>,
+}
+
+/// The counts of the requests of a tenant, or of a class of requests.
+#[derive(Clone, Copy, Default)]
+pub struct TenantReport {
+    pub insert_count: u64,
+    pub read_count: u64,
+    pub hit_count: u64,
+}
+
+/// The optional columns of the CSV records of the reports. The header and the
+/// records of a run must be made with the same columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CsvColumns {
+    /// The numbers of the entries invalidated, evicted and expired.
+    pub eviction_counts: bool,
+    /// The time the workers starved in the streaming mode.
+    pub starvation: bool,
+    /// The full-scale capacity and the estimated hit ratio of a sampled trace.
+    pub sampling: bool,
+    /// The reads per second of a phased workload.
+    pub throughput: bool,
+    /// The contention of a thundering herd workload.
+    pub contention: bool,
+}
+
+impl CsvColumns {
+    /// Returns the number of the columns after the hit ratio.
+    fn num_columns_after_hit_ratio(&self) -> usize {
+        let mut n = if self.eviction_counts { 4 } else { 1 };
+        if self.starvation {
+            n += 1;
+        }
+        if self.sampling {
+            n += 2;
+        }
+        if self.throughput {
+            n += 1;
+        }
+        if self.contention {
+            n += 3;
+        }
+        n
+    }
+}
+
+/// The counts of the callers of a thundering herd workload, who read the same
+/// missing keys at once. The callers are counted by `Report::read_count`, and the
+/// runs of the init closure by `Report::insert_count`.
+#[derive(Clone, Copy, Default)]
+pub struct ContentionReport {
+    /// The number of the loads needed, which is the number of keys read by all
+    /// callers of a round, summed up over the rounds.
+    pub load_count: u64,
+    /// The number of the callers who got the value loaded by another caller.
+    pub waiter_count: u64,
+    /// The sum of the times from the return of the loader to the returns of the
+    /// waiters.
+    pub total_wake_up: Duration,
+    pub max_wake_up: Duration,
+}
+
+impl ContentionReport {
+    pub(crate) fn add_waiter(&mut self, wake_up: Duration) {
+        self.waiter_count += 1;
+        self.total_wake_up += wake_up;
+        self.max_wake_up = self.max_wake_up.max(wake_up);
+    }
+
+    pub fn mean_wake_up(&self) -> Option<Duration> {
+        (self.waiter_count > 0).then(|| {
+            Duration::from_secs_f64(self.total_wake_up.as_secs_f64() / self.waiter_count as f64)
+        })
+    }
+}
+
+impl TenantReport {
+    pub fn hit_ratio(&self) -> f64 {
+        (self.hit_count as f64) / (self.read_count as f64)
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.insert_count += other.insert_count;
+        self.read_count += other.read_count;
+        self.hit_count += other.hit_count;
+    }
 }
 
 impl Report {
@@ -56,6 +173,18 @@ impl Report {
         self.insert_count += other.insert_count;
         self.read_count += other.read_count;
         self.hit_count += other.hit_count;
+        self.starvation += other.starvation;
+        for (counts, other) in [
+            (&mut self.tenants, &other.tenants),
+            (&mut self.classes, &other.classes),
+        ] {
+            if counts.len() < other.len() {
+                counts.resize(other.len(), TenantReport::default());
+            }
+            for (counts, other) in counts.iter_mut().zip(other) {
+                counts.merge(other);
+            }
+        }
         if self.has_eviction_counts {
             self.invalidation_count += other.invalidation_count;
             self.eviction_count += other.eviction_count;
@@ -63,6 +192,95 @@ impl Report {
         }
     }
 
+    /// Returns the number of reads per second.
+    pub fn reads_per_sec(&self) -> Option<f64> {
+        let secs = self.duration?.as_secs_f64();
+        (secs > 0.0).then(|| self.read_count as f64 / secs)
+    }
+
+    /// Adds the report of a phase of a phased workload, which ran after the
+    /// previous phases. The durations are summed up, and the number of workers
+    /// becomes unknown if the phases have different numbers of workers.
+    pub(crate) fn add_phase(&mut self, phase: Report) {
+        self.merge(&phase);
+        self.duration =
+            Some(self.duration.unwrap_or_default() + phase.duration.unwrap_or_default());
+        self.num_unsampled_reads += phase.num_unsampled_reads;
+        self.sampling_rate = phase.sampling_rate;
+        self.has_starvation = phase.has_starvation;
+        self.has_throughput = phase.has_throughput;
+        if phase.num_workers != self.num_workers {
+            self.num_workers = None;
+        }
+        self.phases.push(phase);
+    }
+
+    /// Returns the number of the runs of the init closure beyond the one needed for
+    /// each key read by a thundering herd, or `None` for the other workloads.
+    pub fn redundant_init_count(&self) -> Option<u64> {
+        let contention = self.contention.as_ref()?;
+        Some(self.insert_count.saturating_sub(contention.load_count))
+    }
+
+    /// Returns the estimated hit ratio of the cache running the whole trace with
+    /// the capacity scaled up by the sampling rate. The difference between the
+    /// expected and actual numbers of the sampled reads is counted as hits, as
+    /// SHARDS-adj does for the smallest reuse distance.
+    ///
+    /// The operations injected periodically (e.g. `--invalidate`) are placed by
+    /// the positions of the keys in the sampled trace, while the reads without
+    /// sampling are counted by their positions in the whole trace. So the
+    /// injected operations hit other keys than in a run of the whole trace, and
+    /// the estimate may be off by more than the sampling error when they are
+    /// frequent.
+    pub fn estimated_hit_ratio(&self) -> Option<f64> {
+        let rate = self.sampling_rate?;
+        let expected_reads = self.num_unsampled_reads as f64 * rate;
+        if expected_reads <= 0.0 {
+            return None;
+        }
+        let hits = self.hit_count as f64 + (expected_reads - self.read_count as f64);
+        Some((hits / expected_reads).clamp(0.0, 1.0))
+    }
+
+    /// Returns the counts of the whole report, to be passed to `add_tenant_counts`
+    /// or `add_class_counts` after processing a request of a tenant or a class.
+    pub(crate) fn counts(&self) -> TenantReport {
+        TenantReport {
+            insert_count: self.insert_count,
+            read_count: self.read_count,
+            hit_count: self.hit_count,
+        }
+    }
+
+    /// Adds the counts increased since `before` to the tenant.
+    pub(crate) fn add_tenant_counts(&mut self, tenant: u8, before: TenantReport) {
+        let after = self.counts();
+        Self::add_counts(&mut self.tenants, tenant, before, after);
+    }
+
+    /// Adds the counts increased since `before` to the class of requests.
+    pub(crate) fn add_class_counts(&mut self, class: u8, before: TenantReport) {
+        let after = self.counts();
+        Self::add_counts(&mut self.classes, class, before, after);
+    }
+
+    fn add_counts(
+        counts: &mut Vec<TenantReport>,
+        i: u8,
+        before: TenantReport,
+        after: TenantReport,
+    ) {
+        let i = i as usize;
+        if counts.len() <= i {
+            counts.resize(i + 1, TenantReport::default());
+        }
+        let counts = &mut counts[i];
+        counts.insert_count += after.insert_count - before.insert_count;
+        counts.read_count += after.read_count - before.read_count;
+        counts.hit_count += after.hit_count - before.hit_count;
+    }
+
     pub(crate) fn add_eviction_counts(&mut self, eviction_counters: &EvictionCounters) {
         self.has_eviction_counts = true;
         self.invalidation_count += eviction_counters.explicit();
@@ -72,15 +290,40 @@ impl Report {
 
     // Formatting (CSV)
 
-    pub fn cvs_header(has_eviction_counters: bool) -> String {
-        if has_eviction_counters {
-            "Cache, Max Capacity, Clients, Inserts, Reads, Hit Ratio, Invalidates, Evicted by Size, Expired, Duration Secs".into()
+    pub fn cvs_header(columns: CsvColumns) -> String {
+        let mut header = if columns.eviction_counts {
+            "Cache, Max Capacity, Clients, Inserts, Reads, Hit Ratio, Invalidates, Evicted by Size, Expired, Duration Secs".to_string()
         } else {
-            "Cache, Max Capacity, Clients, Inserts, Reads, Hit Ratio, Duration Secs".into()
+            "Cache, Max Capacity, Clients, Inserts, Reads, Hit Ratio, Duration Secs".to_string()
+        };
+        if columns.starvation {
+            header.push_str(", Starved Secs");
+        }
+        if columns.sampling {
+            header.push_str(", Full-Scale Capacity, Est. Full-Scale Hit Ratio");
+        }
+        if columns.throughput {
+            header.push_str(", Reads/Sec");
+        }
+        if columns.contention {
+            header.push_str(", Redundant Inits, Mean Wake-up Micros, Max Wake-up Micros");
+        }
+        header
+    }
+
+    /// Returns the optional columns of the CSV record of this report.
+    pub fn csv_columns(&self) -> CsvColumns {
+        CsvColumns {
+            eviction_counts: self.has_eviction_counts,
+            starvation: self.has_starvation,
+            sampling: self.sampling_rate.is_some(),
+            throughput: self.has_throughput,
+            contention: self.contention.is_some(),
         }
     }
 
     pub fn to_csv_record(&self) -> String {
+        let columns = self.csv_columns();
         let num_workers = if let Some(n) = self.num_workers {
             n.to_string()
         } else {
@@ -93,7 +336,7 @@ impl Report {
             "-".to_string()
         };
 
-        if self.has_eviction_counts {
+        let mut record = if columns.eviction_counts {
             format!(
                 "{}, {}, {}, {}, {}, {:.3}, {}, {}, {}, {}",
                 self.name,
@@ -118,6 +361,118 @@ impl Report {
                 self.hit_ratio() * 100.0,
                 duration
             )
+        };
+        if columns.starvation {
+            record.push_str(&format!(", {:.3}", self.starvation.as_secs_f64()));
+        }
+        if let (true, Some(rate)) = (columns.sampling, self.sampling_rate) {
+            let hit_ratio = if let Some(r) = self.estimated_hit_ratio() {
+                format!("{:.3}", r * 100.0)
+            } else {
+                "-".to_string()
+            };
+            record.push_str(&format!(
+                ", {}, {}",
+                (self.capacity as f64 / rate).round() as u64,
+                hit_ratio
+            ));
+        }
+        if columns.throughput {
+            match self.reads_per_sec() {
+                Some(r) => record.push_str(&format!(", {r:.0}")),
+                None => record.push_str(", -"),
+            }
+        }
+        if let (true, Some(contention)) = (columns.contention, &self.contention) {
+            let mean = if let Some(d) = contention.mean_wake_up() {
+                format!("{:.1}", d.as_secs_f64() * 1e6)
+            } else {
+                "-".to_string()
+            };
+            record.push_str(&format!(
+                ", {}, {}, {:.1}",
+                self.redundant_init_count().unwrap_or_default(),
+                mean,
+                contention.max_wake_up.as_secs_f64() * 1e6
+            ));
+        }
+        record
+    }
+
+    /// Returns a CSV record of each phase, with the same columns as
+    /// `to_csv_record`.
+    pub fn phase_csv_records(&self) -> Vec<String> {
+        self.phases.iter().map(Report::to_csv_record).collect()
+    }
+
+    /// Returns a CSV record of each tenant, with the same columns as
+    /// `to_csv_record`. The columns that are not counted per tenant are `-`.
+    pub fn tenant_csv_records(&self, tenant_names: &[&str]) -> Vec<String> {
+        self.group_csv_records(&self.tenants, tenant_names)
+    }
+
+    /// Returns a CSV record of each class of requests, in the same way as
+    /// `tenant_csv_records`.
+    pub fn class_csv_records(&self, class_names: &[&str]) -> Vec<String> {
+        self.group_csv_records(&self.classes, class_names)
+    }
+
+    fn group_csv_records(&self, groups: &[TenantReport], names: &[&str]) -> Vec<String> {
+        let num_workers = if let Some(n) = self.num_workers {
+            n.to_string()
+        } else {
+            "-".to_string()
+        };
+        let other_columns = ", -".repeat(self.csv_columns().num_columns_after_hit_ratio());
+
+        groups
+            .iter()
+            .enumerate()
+            .map(|(i, counts)| {
+                let name = names.get(i).copied().unwrap_or("-");
+                format!(
+                    "{} [{}], {}, {}, {}, {}, {:.3}{}",
+                    self.name,
+                    name,
+                    self.capacity,
+                    num_workers,
+                    counts.insert_count,
+                    counts.read_count,
+                    counts.hit_ratio() * 100.0,
+                    other_columns
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num_columns(csv: &str) -> usize {
+        csv.split(", ").count()
+    }
+
+    #[test]
+    fn header_matches_records() {
+        for bits in 0..32u8 {
+            let mut report = Report::new("cache", 100, Some(2));
+            report.read_count = 10;
+            report.hit_count = 5;
+            report.has_eviction_counts = bits & 1 != 0;
+            report.has_starvation = bits & 2 != 0;
+            report.sampling_rate = (bits & 4 != 0).then_some(0.1);
+            report.num_unsampled_reads = 100;
+            report.has_throughput = bits & 8 != 0;
+            report.contention = (bits & 16 != 0).then(ContentionReport::default);
+            report.tenants = vec![TenantReport::default(); 2];
+
+            let header = Report::cvs_header(report.csv_columns());
+            assert_eq!(num_columns(&report.to_csv_record()), num_columns(&header));
+            for record in report.tenant_csv_records(&["a", "b"]) {
+                assert_eq!(num_columns(&record), num_columns(&header));
+            }
         }
     }
 }
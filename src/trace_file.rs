@@ -1,194 +1,651 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    fmt,
+    io::BufRead,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-pub const DATASET_DIR: &str = "./cache-trace";
+use anyhow::Context;
+use serde::Deserialize;
 
-#[derive(Clone, Copy, Debug)]
-pub enum TraceFileGroup {
-    Arc,
-    Lirs,
+use crate::{
+    parser::{
+        binary::{self, BinaryColumns},
+        TraceEntries, TraceEntry, TraceFormat,
+    },
+    trace_reader::{TraceInput, STDIN_PATH},
+    validator::{self, ValidationReport},
+    workload::{Phase, ThunderingHerdWorkload, Workload},
+};
+
+/// The manifest of the built-in ARC and LIRS traces.
+const BUILTIN_MANIFEST: &str = include_str!("../traces.toml");
+
+/// The group of the traces that are not registered in a manifest.
+const CUSTOM_GROUP: &str = "custom";
+
+/// The group of the descriptors of interleaved traces.
+const MULTI_TENANT_GROUP: &str = "multi-tenant";
+
+/// The group of the synthetic workloads.
+const SYNTHETIC_GROUP: &str = "synthetic";
+
+/// Describes a trace to run the benchmarks with.
+#[derive(Clone, Debug)]
+pub struct TraceDescriptor {
+    pub name: String,
+    pub path: PathBuf,
+    pub format: TraceFormat,
+    pub group: String,
+    pub capacities: Vec<usize>,
+    /// The mean size in bytes of the values recorded in the trace. `None` if the
+    /// trace format does not record value sizes or it is unknown.
+    pub mean_value_size: Option<u32>,
+    /// The columns in the header of a binary trace. `None` for the other formats.
+    pub(crate) binary_columns: Option<BinaryColumns>,
+    /// The traces of the tenants of a multi-tenant descriptor. Empty for a single
+    /// trace.
+    pub(crate) tenants: Vec<TraceDescriptor>,
+    /// If `true`, the malformed lines of the trace are skipped instead of
+    /// aborting the benchmarks.
+    pub lenient: bool,
+    /// The input of the trace file. `None` for a multi-tenant descriptor, whose
+    /// tenants have their own inputs, and a synthetic workload.
+    pub(crate) input: Option<TraceInput>,
+    pub(crate) workload: Option<Workload>,
+    /// The entries parsed in advance by `parse_entries`.
+    pub(crate) parsed: Option<ParsedEntries>,
 }
 
-impl TraceFileGroup {
-    pub fn new(file: TraceFile) -> Self {
-        if matches!(
-            file,
-            TraceFile::Loop
-                | TraceFile::Multi1
-                | TraceFile::Multi2
-                | TraceFile::Multi3
-                | TraceFile::TwoPools
-                | TraceFile::Sprite
-                | TraceFile::ZigZag
-        ) {
-            Self::Lirs
+/// The entries of a trace kept in memory, which are shared by the runs.
+#[derive(Clone)]
+pub(crate) struct ParsedEntries(pub(crate) Arc<[TraceEntry]>);
+
+impl fmt::Debug for ParsedEntries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParsedEntries")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+impl TraceDescriptor {
+    /// The fractions of the number of unique keys in a trace, which are used as
+    /// the default capacities for an arbitrary trace file.
+    pub(crate) const CAPACITY_FRACTIONS: &'static [f64] = &[0.05, 0.1, 0.25, 0.5];
+
+    /// Returns the capacities at `CAPACITY_FRACTIONS` of the number of unique keys.
+    pub(crate) fn capacities_for(num_keys: usize) -> Vec<usize> {
+        let mut capacities = Self::CAPACITY_FRACTIONS
+            .iter()
+            .map(|f| ((num_keys as f64 * f) as usize).max(1))
+            .collect::<Vec<_>>();
+        capacities.dedup();
+        capacities
+    }
+
+    /// Creates a descriptor for an arbitrary trace file. The trace format is
+    /// detected from the first lines of the file, and the default capacities are
+    /// calculated from the number of unique keys in the whole file, which is
+    /// estimated from a sample of the keys for a large trace.
+    ///
+    /// If `path` is `-`, the trace is read from the standard input. It and a named
+    /// pipe can be read only once, so they are copied to a temporary file to be
+    /// replayed by the benchmarks.
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self::load(path.as_ref(), false)?.0)
+    }
+
+    /// Same as `from_path`, but the malformed lines of the trace are skipped
+    /// instead of returning an error. They are reported in the returned report,
+    /// which is made in the same pass over the trace as the default capacities.
+    pub fn from_path_lenient(path: impl AsRef<Path>) -> anyhow::Result<(Self, ValidationReport)> {
+        let (trace, report) = Self::load(path.as_ref(), true)?;
+        Ok((trace, report.expect("A lenient load validates the trace")))
+    }
+
+    fn load(path: &Path, lenient: bool) -> anyhow::Result<(Self, Option<ValidationReport>)> {
+        let path = path.to_path_buf();
+        let input = TraceInput::new(&path)?.spool()?;
+        let format = TraceFormat::detect(input.open()?)
+            .with_context(|| format!("Cannot detect the format of {}", path.display()))?;
+
+        let (stats, report) = if lenient {
+            let mut stats = TraceStats::default();
+            let report =
+                validator::validate_input(&input, &path, format, |entry| stats.add(entry))?;
+            (stats, Some(report))
         } else {
-            Self::Arc
+            let entries = format.read_entries(input.open()?, &path, false)?;
+            (TraceStats::collect(entries)?, None)
+        };
+        let binary_columns = if format == TraceFormat::Binary {
+            Some(binary::read_columns(input.open()?)?)
+        } else {
+            None
+        };
+
+        let trace = Self {
+            name: trace_name(&path),
+            path,
+            format,
+            group: CUSTOM_GROUP.to_string(),
+            capacities: Self::capacities_for(stats.keys.count()),
+            mean_value_size: stats.mean_value_size(),
+            binary_columns,
+            tenants: Vec::new(),
+            lenient,
+            input: Some(input),
+            workload: None,
+            parsed: None,
+        };
+        Ok((trace, report))
+    }
+
+    /// Creates a descriptor for a synthetic workload. The default capacities are
+    /// calculated from the number of keys in its key space.
+    pub fn synthetic(workload: Workload) -> Self {
+        let name = workload.name();
+        let capacities = match &workload {
+            // The keys of a round are never read again, so smaller capacities only
+            // repeat the same run.
+            Workload::ThunderingHerd(_) => vec![workload.num_keys()],
+            _ => Self::capacities_for(workload.num_keys()),
+        };
+        Self {
+            path: PathBuf::from(&name),
+            name,
+            format: TraceFormat::Synthetic,
+            group: SYNTHETIC_GROUP.to_string(),
+            capacities,
+            mean_value_size: None,
+            binary_columns: None,
+            tenants: Vec::new(),
+            lenient: false,
+            input: None,
+            workload: Some(workload),
+            parsed: None,
+        }
+    }
+
+    /// Creates a descriptor of the traces of the tenants sharing a cache. The
+    /// default capacities are the sums of those of the tenants, and the mean value
+    /// size is the mean of the recorded ones. It has no `path` of its own, and the
+    /// `format` is of the first tenant; the columns of the traces are answered by
+    /// the tenants (e.g. `has_timestamps`).
+    pub fn multi_tenant(tenants: &[TraceDescriptor]) -> Self {
+        let num_capacities = tenants
+            .iter()
+            .map(|t| t.capacities.len())
+            .min()
+            .unwrap_or_default();
+        let capacities = (0..num_capacities)
+            .map(|i| tenants.iter().map(|t| t.capacities[i]).sum())
+            .collect();
+        let value_sizes = tenants
+            .iter()
+            .filter_map(|t| t.mean_value_size)
+            .collect::<Vec<_>>();
+        let mean_value_size = if value_sizes.is_empty() {
+            None
+        } else {
+            let total = value_sizes.iter().map(|&s| s as u64).sum::<u64>();
+            Some((total / value_sizes.len() as u64) as u32)
+        };
+
+        Self {
+            name: tenants
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>()
+                .join("+"),
+            path: PathBuf::new(),
+            format: tenants[0].format,
+            group: MULTI_TENANT_GROUP.to_string(),
+            capacities,
+            mean_value_size,
+            binary_columns: None,
+            tenants: tenants.to_vec(),
+            lenient: tenants.iter().any(|t| t.lenient),
+            input: None,
+            workload: None,
+            parsed: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn default_capacities(&self) -> &[usize] {
+        &self.capacities
+    }
+
+    /// Returns `true` if the trace records operations other than reads (e.g.
+    /// writes and deletes). For the tenants, `true` if any of their traces does.
+    pub fn has_operations(&self) -> bool {
+        if !self.tenants.is_empty() {
+            return self.tenants.iter().any(Self::has_operations);
+        }
+        if let Some(workload) = &self.workload {
+            return workload.has_operations();
+        }
+        match self.binary_columns {
+            Some(columns) => columns.has_operations(),
+            None => self.format.has_operations(),
+        }
+    }
+
+    /// Returns `true` if the trace records the time of each request. For the
+    /// tenants, `true` if all of their traces do.
+    pub fn has_timestamps(&self) -> bool {
+        if !self.tenants.is_empty() {
+            return self.tenants.iter().all(Self::has_timestamps);
+        }
+        match self.binary_columns {
+            Some(columns) => columns.has_timestamps(),
+            None => self.format.has_timestamps(),
+        }
+    }
+
+    /// Returns the names of the classes of the requests that are counted
+    /// separately (e.g. "hot" and "scan"), or an empty slice if they are not
+    /// classified.
+    pub fn request_classes(&self) -> &'static [&'static str] {
+        self.workload
+            .as_ref()
+            .map_or(&[], Workload::request_classes)
+    }
+
+    /// Returns the phases of a phased workload, or an empty slice for the other
+    /// traces.
+    pub fn phases(&self) -> &[Phase] {
+        match &self.workload {
+            Some(Workload::Phased(workload)) => workload.phases(),
+            _ => &[],
+        }
+    }
+
+    /// Returns the thundering herd workload, if the trace is one.
+    pub fn thundering_herd(&self) -> Option<&ThunderingHerdWorkload> {
+        match &self.workload {
+            Some(Workload::ThunderingHerd(workload)) => Some(workload),
+            _ => None,
+        }
+    }
+
+    /// Opens the trace file and returns a reader of its expanded contents.
+    pub(crate) fn open(&self) -> anyhow::Result<Box<dyn BufRead + Send>> {
+        self.trace_input()?.open()
+    }
+
+    fn trace_input(&self) -> anyhow::Result<&TraceInput> {
+        self.input.as_ref().with_context(|| {
+            format!(
+                r#""{}" is a multi-tenant trace or a synthetic workload, not a trace file"#,
+                self.name
+            )
+        })
+    }
+
+    /// Returns an iterator of the entries of the trace: the ones parsed in advance
+    /// if any, or the ones read from the trace file or generated by the workload.
+    pub(crate) fn entries(&self) -> anyhow::Result<TraceEntries> {
+        if let Some(ParsedEntries(entries)) = &self.parsed {
+            let entries = Arc::clone(entries);
+            return Ok(Box::new(
+                (0..entries.len()).map(move |i| Ok(entries[i].clone())),
+            ));
+        }
+        if let Some(workload) = &self.workload {
+            return Ok(workload.entries());
+        }
+        self.format
+            .read_entries(self.open()?, &self.path, self.lenient)
+    }
+
+    /// Reads the whole trace file in parallel and keeps its entries in memory, so
+    /// that the following runs with the trace do not read and parse it again.
+    /// A synthetic workload is generated once and kept in the same way, except a
+    /// phased workload, which is generated phase by phase when it runs.
+    pub fn parse_entries(&mut self) -> anyhow::Result<()> {
+        if self.parsed.is_none() && self.phases().is_empty() {
+            let entries = match &self.workload {
+                Some(workload) => workload.entries().collect::<anyhow::Result<Vec<_>>>()?,
+                None => self
+                    .format
+                    .parse_all(self.trace_input()?, &self.path, self.lenient)?,
+            };
+            self.parsed = Some(ParsedEntries(entries.into()));
         }
+        Ok(())
     }
 
-    pub fn sub_dir(&self) -> &'static str {
-        match self {
-            Self::Arc => "arc",
-            Self::Lirs => "lirs",
+    /// Reads the whole trace file and reports its malformed lines.
+    pub fn validate(&self) -> anyhow::Result<ValidationReport> {
+        validator::validate_input(self.trace_input()?, &self.path, self.format, |_| ())
+    }
+}
+
+/// A set of traces that can be selected by name. It is populated from TOML
+/// manifests; see `traces.toml` for the format.
+#[derive(Clone, Debug, Default)]
+pub struct TraceRegistry {
+    traces: Vec<TraceDescriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    dataset_dir: Option<PathBuf>,
+    #[serde(default, rename = "trace")]
+    traces: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    name: String,
+    path: PathBuf,
+    format: TraceFormat,
+    group: Option<String>,
+    capacities: Vec<usize>,
+    mean_value_size: Option<u32>,
+}
+
+impl TraceRegistry {
+    /// Creates a registry with the built-in ARC and LIRS traces.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry
+            .add_manifest(BUILTIN_MANIFEST, Path::new(""))
+            .expect("The built-in trace manifest is invalid");
+        registry
+    }
+
+    /// Registers the traces in the manifest file at `path`. A trace with the same
+    /// name as an already registered one replaces it.
+    pub fn load_manifest(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let manifest = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read trace manifest {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        self.add_manifest(&manifest, base_dir)
+            .with_context(|| format!("Invalid trace manifest {}", path.display()))
+    }
+
+    fn add_manifest(&mut self, manifest: &str, base_dir: &Path) -> anyhow::Result<()> {
+        let manifest: Manifest = toml::from_str(manifest)?;
+        let dataset_dir = base_dir.join(manifest.dataset_dir.unwrap_or_default());
+
+        for entry in manifest.traces {
+            if entry.capacities.is_empty() {
+                anyhow::bail!(r#"Trace "{}" has no capacities"#, entry.name);
+            }
+            let path = dataset_dir.join(entry.path);
+            let trace = TraceDescriptor {
+                name: normalize_name(&entry.name),
+                input: Some(TraceInput::File(path.clone())),
+                path,
+                format: entry.format,
+                group: entry.group.unwrap_or_else(|| CUSTOM_GROUP.to_string()),
+                capacities: entry.capacities,
+                mean_value_size: entry.mean_value_size,
+                binary_columns: None,
+                tenants: Vec::new(),
+                lenient: false,
+                workload: None,
+                parsed: None,
+            };
+            match self.traces.iter_mut().find(|t| t.name == trace.name) {
+                Some(existing) => *existing = trace,
+                None => self.traces.push(trace),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the trace registered with the name (case-insensitive). The header
+    /// of a binary trace is read here, as the file may not exist until it is
+    /// selected.
+    pub fn get(&self, name: &str) -> anyhow::Result<TraceDescriptor> {
+        let name = normalize_name(name);
+        let mut trace = self
+            .traces
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    r#"Unknown trace file "{}". Available: {}"#,
+                    name,
+                    self.names().collect::<Vec<_>>().join(", ")
+                )
+            })?;
+        if trace.format == TraceFormat::Binary {
+            let columns = binary::read_columns(trace.open()?)
+                .with_context(|| format!("Cannot read {}", trace.path.display()))?;
+            trace.binary_columns = Some(columns);
         }
+        Ok(trace)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.traces.iter().map(|t| t.name.as_str())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum TraceFile {
-    // From ARC paper: "ARC: A Self-Tuning, Low Overhead Replacement Cache"
-    // Traces and paper in the author page: https://researcher.watson.ibm.com/researcher/view_person_subpage.php?id=4700
-    ConCat,
-    Ds1,
-    MergeP,
-    MergeS,
-    Oltp,
-    P1,
-    P2,
-    P3,
-    P4,
-    P5,
-    P6,
-    P7,
-    P8,
-    P9,
-    P10,
-    P11,
-    P12,
-    P13,
-    P14,
-    S1,
-    S2,
-    S3,
-    Spc1LikeRead,
-
-    // From LIRS paper: "LIRS: An Efficient Low Inter-reference Recency Set Replacement Policy to Improve Buffer Cache Performance"
-    // https://ranger.uta.edu/~sjiang/pubs/papers/jiang02_LIRS.pdf
-    Loop,
-    Multi1,
-    Multi2,
-    Multi3,
-    TwoPools,
-    Sprite,
-    // Lirs2
-    ZigZag,
+fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase()
 }
 
-impl TryFrom<&str> for TraceFile {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.trim().to_lowercase().as_str() {
-            "concat" => Ok(Self::ConCat),
-            "ds1" => Ok(Self::Ds1),
-            "merge-p" => Ok(Self::MergeP),
-            "merge-s" => Ok(Self::MergeS),
-            "oltp" => Ok(Self::Oltp),
-            "p1" => Ok(Self::P1),
-            "p2" => Ok(Self::P2),
-            "p3" => Ok(Self::P3),
-            "p4" => Ok(Self::P4),
-            "p5" => Ok(Self::P5),
-            "p6" => Ok(Self::P6),
-            "p7" => Ok(Self::P7),
-            "p8" => Ok(Self::P8),
-            "p9" => Ok(Self::P9),
-            "p10" => Ok(Self::P10),
-            "p11" => Ok(Self::P11),
-            "p12" => Ok(Self::P12),
-            "p13" => Ok(Self::P13),
-            "p14" => Ok(Self::P14),
-            "s1" => Ok(Self::S1),
-            "s2" => Ok(Self::S2),
-            "s3" => Ok(Self::S3),
-            "spc1likeread" => Ok(Self::Spc1LikeRead),
-
-            "loop" => Ok(Self::Loop),
-            "multi1" => Ok(Self::Multi1),
-            "multi2" => Ok(Self::Multi2),
-            "multi3" => Ok(Self::Multi3),
-            "2-pools" => Ok(Self::ZigZag),
-            "sprite" => Ok(Self::Sprite),
-            "zigzag" => Ok(Self::ZigZag),
-            _ => Err(anyhow::anyhow!(r#"Unknown trace file "{}""#, value)),
+/// Returns the file name of the trace without the extensions. e.g. `DS1` for
+/// `DS1.lis`, `DS1.lis.zst` and `DS1.lis.zst.00`, and `stdin` for `-`.
+fn trace_name(path: &Path) -> String {
+    if path.as_os_str() == STDIN_PATH {
+        return "stdin".to_string();
+    }
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+/// The statistics of a whole trace file used to derive the defaults of its
+/// descriptor.
+#[derive(Default)]
+struct TraceStats {
+    keys: KeyCounter,
+    num_sized_entries: u64,
+    total_value_size: u64,
+}
+
+impl TraceStats {
+    fn collect(entries: TraceEntries) -> anyhow::Result<Self> {
+        let mut stats = Self::default();
+        for entry in entries {
+            stats.add(&entry?);
+        }
+        Ok(stats)
+    }
+
+    fn add(&mut self, entry: &TraceEntry) {
+        entry.range().for_each(|key| self.keys.insert(key));
+        if let Some(size) = entry.value_size() {
+            self.num_sized_entries += 1;
+            self.total_value_size += size as u64;
+        }
+    }
+
+    fn mean_value_size(&self) -> Option<u32> {
+        if self.num_sized_entries == 0 {
+            return None;
         }
+        let mean = self.total_value_size / self.num_sized_entries;
+        Some((mean as u32).max(1))
     }
 }
 
-impl TraceFile {
-    pub fn path(&self) -> PathBuf {
-        let mut p = Path::new(DATASET_DIR).to_path_buf();
-        p.push(TraceFileGroup::new(*self).sub_dir());
-
-        match self {
-            Self::ConCat => p.push("ConCat.lis"),
-            Self::Ds1 => p.push("DS1.lis"),
-            Self::MergeP => p.push("MergeP.lis"),
-            Self::MergeS => p.push("MergeS.lis"),
-            Self::Oltp => p.push("OLTP.lis"),
-            Self::P1 => p.push("P1.lis"),
-            Self::P2 => p.push("P2.lis"),
-            Self::P3 => p.push("P3.lis"),
-            Self::P4 => p.push("P4.lis"),
-            Self::P5 => p.push("P5.lis"),
-            Self::P6 => p.push("P6.lis"),
-            Self::P7 => p.push("P7.lis"),
-            Self::P8 => p.push("P8.lis"),
-            Self::P9 => p.push("P9.lis"),
-            Self::P10 => p.push("P10.lis"),
-            Self::P11 => p.push("P11.lis"),
-            Self::P12 => p.push("P12.lis"),
-            Self::P13 => p.push("P13.lis"),
-            Self::P14 => p.push("P14.lis"),
-            Self::S1 => p.push("S1.lis"),
-            Self::S2 => p.push("S2.lis"),
-            Self::S3 => p.push("S3.lis"),
-            Self::Spc1LikeRead => p.push("spc1likeread.lis"),
-
-            Self::Loop => p.push("loop.trc"),
-            Self::Multi1 => p.push("multi1.trc"),
-            Self::Multi2 => p.push("multi2.trc"),
-            Self::Multi3 => p.push("multi3.trc"),
-            Self::TwoPools => p.push("2_pools.trc"),
-            Self::Sprite => p.push("sprite.trc"),
-            Self::ZigZag => p.push("zigzag.trc"),
-        }
-        p
+/// Counts the unique keys. Only the keys whose hashes have `shift` leading zero
+/// bits are kept, and `shift` is incremented whenever more than `MAX_KEYS` keys
+/// are kept, so the count is exact until then and estimated from a spatial
+/// sample of the keys after that.
+#[derive(Default)]
+struct KeyCounter {
+    hashes: HashSet<u64>,
+    shift: u32,
+}
+
+impl KeyCounter {
+    const MAX_KEYS: usize = 1 << 18;
+
+    fn insert(&mut self, key: usize) {
+        let hash = xxhash_rust::xxh3::xxh3_64(&key.to_le_bytes());
+        if hash.leading_zeros() < self.shift {
+            return;
+        }
+        self.hashes.insert(hash);
+        if self.hashes.len() > Self::MAX_KEYS {
+            self.shift += 1;
+            let shift = self.shift;
+            self.hashes.retain(|hash| hash.leading_zeros() >= shift);
+        }
     }
 
-    pub fn default_capacities(&self) -> &[usize] {
-        match self {
-            Self::ConCat => &[200_000, 400_000, 3_200_000],
-            Self::Ds1 => &[1_000_000, 4_000_000, 8_000_000],
-            Self::MergeP => &[400_000, 1_000_000, 3_200_000],
-            Self::MergeS => &[400_000, 1_000_000, 3_200_000],
-            Self::Oltp => &[256, 512, 1_000, 2_000],
-            Self::P1 => &[20_000, 160_000],
-            Self::P2 => &[20_000, 160_000],
-            Self::P3 => &[20_000, 160_000],
-            Self::P4 => &[20_000, 160_000],
-            Self::P5 => &[20_000, 160_000],
-            Self::P6 => &[20_000, 160_000],
-            Self::P7 => &[20_000, 160_000],
-            Self::P8 => &[20_000, 160_000],
-            Self::P9 => &[20_000, 160_000],
-            Self::P10 => &[20_000, 160_000],
-            Self::P11 => &[20_000, 160_000],
-            Self::P12 => &[20_000, 160_000],
-            Self::P13 => &[20_000, 160_000],
-            Self::P14 => &[80_000, 640_000],
-            Self::S1 => &[100_000, 800_000],
-            Self::S2 => &[100_000, 800_000],
-            Self::S3 => &[100_000, 400_000, 800_000],
-            Self::Spc1LikeRead => &[500_000, 2_000_000, 30_000_000],
-
-            Self::Loop => &[256, 512, 768, 1024],
-            Self::Multi1 => &[128, 256, 512, 768],
-            Self::Multi2 => &[128, 256, 512, 768],
-            Self::Multi3 => &[128, 256, 512, 768],
-            Self::TwoPools => &[128, 256, 512, 768],
-            Self::Sprite => &[128, 256, 512, 768],
-            Self::ZigZag => &[128, 256, 512, 768],
+    fn count(&self) -> usize {
+        self.hashes.len() << self.shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_keys_exactly() {
+        let mut keys = KeyCounter::default();
+        for key in (0..KeyCounter::MAX_KEYS).chain(0..100) {
+            keys.insert(key);
+        }
+        assert_eq!(keys.shift, 0);
+        assert_eq!(keys.count(), KeyCounter::MAX_KEYS);
+    }
+
+    #[test]
+    fn estimate_many_keys() {
+        let num_keys = KeyCounter::MAX_KEYS * 10;
+        let mut keys = KeyCounter::default();
+        for key in (0..num_keys).chain(0..num_keys / 2) {
+            keys.insert(key);
+        }
+        assert!(keys.shift > 0);
+        assert!(keys.hashes.len() <= KeyCounter::MAX_KEYS);
+        let error = (keys.count() as f64 / num_keys as f64 - 1.0).abs();
+        assert!(error < 0.02, "estimated {} keys", keys.count());
+    }
+
+    #[test]
+    fn load_lenient_trace() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("bad.lis");
+        let mut lines = (0..2000)
+            .map(|i| format!("{} 1 0 0", i % 100))
+            .collect::<Vec<_>>();
+        lines[1500] = "x 1 0 0".to_string();
+        std::fs::write(&path, lines.join("\n"))?;
+
+        assert!(TraceDescriptor::from_path(&path).is_err());
+        let (trace, report) = TraceDescriptor::from_path_lenient(&path)?;
+        assert!(trace.lenient);
+        assert_eq!(trace.capacities, [5, 10, 25, 50]);
+        assert_eq!(report.num_lines, 2000);
+        assert_eq!(report.num_entries, 1999);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].line_number, 1501);
+        Ok(())
+    }
+
+    #[test]
+    fn builtin_traces() -> anyhow::Result<()> {
+        let registry = TraceRegistry::builtin();
+        let s3 = registry.get(" S3 ")?;
+        assert_eq!(s3.path, Path::new("./cache-trace/arc/S3.lis"));
+        assert_eq!(s3.format, TraceFormat::Arc);
+        assert_eq!(s3.capacities, [100_000, 400_000, 800_000]);
+
+        // It used to be mapped to the ZigZag trace.
+        let two_pools = registry.get("2-pools")?;
+        assert_eq!(two_pools.path, Path::new("./cache-trace/lirs/2_pools.trc"));
+        assert_eq!(two_pools.format, TraceFormat::Lirs);
+        assert_eq!(two_pools.group, "lirs");
+
+        assert!(registry.get("s4").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn load_manifest() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = dir.path().join("traces.toml");
+        std::fs::write(
+            &manifest,
+            r#"
+dataset_dir = "data"
+
+[[trace]]
+name = "Mine"
+path = "mine.lis"
+format = "lirs"
+capacities = [10, 20]
+
+[[trace]]
+name = "s3"
+path = "/traces/S3.lis"
+format = "arc"
+group = "arc"
+capacities = [1000]
+"#,
+        )?;
+        let mut registry = TraceRegistry::builtin();
+        registry.load_manifest(&manifest)?;
+
+        // A relative path is resolved against the directory of the manifest.
+        let mine = registry.get("mine")?;
+        assert_eq!(mine.path, dir.path().join("data").join("mine.lis"));
+        assert_eq!(mine.format, TraceFormat::Lirs);
+        assert_eq!(mine.group, CUSTOM_GROUP);
+        assert_eq!(mine.capacities, [10, 20]);
+
+        // A trace with the same name replaces the built-in one.
+        let s3 = registry.get("s3")?;
+        assert_eq!(s3.path, Path::new("/traces/S3.lis"));
+        assert_eq!(s3.capacities, [1000]);
+        assert_eq!(registry.names().filter(|&n| n == "s3").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_manifests() {
+        let invalid = [
+            // An unknown field in the manifest.
+            "dataset = \"data\"",
+            // An unknown field in a trace.
+            "[[trace]]\nname = \"a\"\npath = \"a.lis\"\nformat = \"arc\"\ncapacities = [1]\nsize = 1",
+            // A missing field.
+            "[[trace]]\nname = \"a\"\nformat = \"arc\"\ncapacities = [1]",
+            // An unknown format.
+            "[[trace]]\nname = \"a\"\npath = \"a.lis\"\nformat = \"csv\"\ncapacities = [1]",
+            // No capacities.
+            "[[trace]]\nname = \"a\"\npath = \"a.lis\"\nformat = \"arc\"\ncapacities = []",
+        ];
+        for manifest in invalid {
+            let mut registry = TraceRegistry::default();
+            assert!(
+                registry.add_manifest(manifest, Path::new("")).is_err(),
+                "{manifest}"
+            );
         }
     }
 }
@@ -0,0 +1,470 @@
+//! Opens trace files for reading, transparently decompressing the ones compressed
+//! with Zstandard.
+
+use std::{
+    ffi::OsString,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use tempfile::{NamedTempFile, TempPath};
+
+const ZSTD_EXTENSION: &str = "zst";
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The path that means the standard input.
+pub(crate) const STDIN_PATH: &str = "-";
+
+/// The maximum number of bytes read ahead from a stream by `TraceInput::peek`.
+const PEEK_LEN: usize = 1 << 20;
+
+type Stream = Arc<Mutex<Option<Box<dyn BufRead + Send>>>>;
+
+/// The input of a trace.
+#[derive(Clone)]
+pub(crate) enum TraceInput {
+    /// A trace file, which can be opened as many times as needed.
+    File(PathBuf),
+    /// The standard input or a named pipe, which is streamed as it can be read
+    /// only once: the first `open` takes the reader and the later ones fail. Use
+    /// `spool` to read it many times.
+    Stream(Stream),
+    /// The expanded contents of a stream copied to a temporary file, which is
+    /// removed when the last clone of the input is dropped.
+    Spooled(Arc<TempPath>),
+}
+
+impl TraceInput {
+    /// Creates the input for `path`. If it is `-` (the standard input) or a
+    /// named pipe, the input is a stream and nothing but its first bytes (to
+    /// detect the Zstandard compression) are read here.
+    pub(crate) fn new(path: &Path) -> anyhow::Result<Self> {
+        if path.as_os_str() == STDIN_PATH {
+            return Self::stream(io::stdin(), path);
+        }
+        let is_pipe = std::fs::metadata(path).is_ok_and(|m| !m.is_file() && !m.is_dir());
+        if is_pipe {
+            let file = File::open(path)
+                .with_context(|| format!("Cannot open trace file {}", path.display()))?;
+            return Self::stream(file, path);
+        }
+        Ok(Self::File(path.to_path_buf()))
+    }
+
+    fn stream(mut reader: impl Read + Send + 'static, path: &Path) -> anyhow::Result<Self> {
+        let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+        reader
+            .by_ref()
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut magic)
+            .with_context(|| format!("Cannot read trace from {}", path.display()))?;
+        let is_compressed = magic == ZSTD_MAGIC;
+        let reader = Cursor::new(magic).chain(BufReader::new(reader));
+        let reader = if is_compressed {
+            decompress(reader)?
+        } else {
+            Box::new(reader)
+        };
+        Ok(Self::Stream(Arc::new(Mutex::new(Some(reader)))))
+    }
+
+    /// Copies a stream to a temporary file, so that the returned input can be
+    /// opened many times (e.g. for every run and `--repeat`). The other inputs
+    /// are returned as they are.
+    pub(crate) fn spool(self) -> anyhow::Result<Self> {
+        if !matches!(self, Self::Stream(_)) {
+            return Ok(self);
+        }
+        let mut reader = self.open()?;
+        let mut file = NamedTempFile::new().context("Cannot create a file to spool the trace")?;
+        io::copy(&mut reader, file.as_file_mut())
+            .with_context(|| format!("Cannot spool the trace to {}", file.path().display()))?;
+        Ok(Self::Spooled(Arc::new(file.into_temp_path())))
+    }
+
+    /// Returns a reader of the first lines of the input, e.g. to detect its format
+    /// or to read the header of a binary trace. The lines read from a stream are
+    /// put back, so that `open` still reads it from the start.
+    pub(crate) fn peek(&self) -> anyhow::Result<Box<dyn BufRead + Send>> {
+        let Self::Stream(stream) = self else {
+            return self.open();
+        };
+        let mut stream = stream.lock();
+        let mut reader = stream.take().context(STREAM_READ)?;
+        let mut prefix = Vec::new();
+        while prefix.len() < PEEK_LEN && reader.read_until(b'\n', &mut prefix)? > 0 {}
+        *stream = Some(Box::new(Cursor::new(prefix.clone()).chain(reader)));
+        Ok(Box::new(Cursor::new(prefix)))
+    }
+
+    /// Opens the input and returns a buffered reader of its expanded contents.
+    pub(crate) fn open(&self) -> anyhow::Result<Box<dyn BufRead + Send>> {
+        match self {
+            Self::File(path) => open(path),
+            Self::Stream(stream) => stream.lock().take().context(STREAM_READ),
+            Self::Spooled(path) => {
+                let file = File::open(&**path)
+                    .with_context(|| format!("Cannot open the spooled trace {}", path.display()))?;
+                Ok(Box::new(BufReader::new(file)))
+            }
+        }
+    }
+
+    /// Returns the path of the input if it is a plain (uncompressed) file, whose
+    /// segments can be read by their offsets with `read_segment`.
+    pub(crate) fn plain_file(&self) -> Option<&Path> {
+        match self {
+            Self::File(path)
+                if path.is_file() && path.extension().is_none_or(|ext| ext != ZSTD_EXTENSION) =>
+            {
+                Some(path)
+            }
+            Self::Spooled(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+const STREAM_READ: &str = "The trace from the standard input or a named pipe can be read only once";
+
+impl fmt::Debug for TraceInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Stream(_) => f.write_str("Stream"),
+            Self::Spooled(path) => f.debug_tuple("Spooled").field(&path.as_os_str()).finish(),
+        }
+    }
+}
+
+/// Opens the trace file at `path` and returns a buffered reader of its expanded
+/// contents.
+///
+/// The following files are tried in this order:
+///
+/// 1. `path` itself. If it has the `.zst` extension, it is stream-decompressed.
+/// 2. `path` + `.zst` (e.g. `DS1.lis.zst` for `DS1.lis`).
+/// 3. `path` + `.zst.NN` (e.g. `spc1likeread.lis.zst.00`, `...zst.01`). These
+///    are the parts of a single compressed file split by `split(1)`. They are
+///    concatenated in the order of `NN` before being decompressed.
+fn open(path: &Path) -> anyhow::Result<Box<dyn BufRead + Send>> {
+    if path.exists() {
+        let file = File::open(path)
+            .with_context(|| format!("Cannot open trace file {}", path.display()))?;
+        if path.extension().is_some_and(|ext| ext == ZSTD_EXTENSION) {
+            return decompress(file);
+        }
+        return Ok(Box::new(BufReader::new(file)));
+    }
+
+    let zst_path = append_extension(path, ZSTD_EXTENSION);
+    if zst_path.exists() {
+        let file = File::open(&zst_path)
+            .with_context(|| format!("Cannot open trace file {}", zst_path.display()))?;
+        return decompress(file);
+    }
+
+    let parts = find_parts(&zst_path)?;
+    if parts.is_empty() {
+        anyhow::bail!(
+            "Cannot find trace file {} (also tried {} and {}.NN)",
+            path.display(),
+            zst_path.display(),
+            zst_path.display()
+        );
+    }
+    decompress(MultiPartReader::new(parts))
+}
+
+/// Splits the plain file at `path` into segments at line boundaries, and returns
+/// their byte ranges. The file is split into at most `max_segments` segments of
+/// at least `min_len` bytes, except the last one.
+pub(crate) fn split_lines(
+    path: &Path,
+    min_len: u64,
+    max_segments: usize,
+) -> io::Result<Vec<Range<u64>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let len = reader.get_ref().metadata()?.len();
+    let n = (len / min_len).clamp(1, max_segments as u64);
+    let mut segments = Vec::with_capacity(n as usize);
+    let mut start = 0;
+    let mut line = Vec::new();
+    for k in 1..=n {
+        let end = if k == n {
+            len
+        } else {
+            let mid = (len * k / n).max(start);
+            reader.seek(SeekFrom::Start(mid))?;
+            line.clear();
+            mid + reader.read_until(b'\n', &mut line)? as u64
+        };
+        if end > start {
+            segments.push(start..end);
+            start = end;
+        }
+    }
+    Ok(segments)
+}
+
+/// Returns a buffered reader of the `range` of the plain file at `path`.
+pub(crate) fn read_segment(path: &Path, range: &Range<u64>) -> io::Result<impl BufRead> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    Ok(BufReader::new(file.take(range.end - range.start)))
+}
+
+/// Returns the number of newlines read from `reader`.
+pub(crate) fn count_lines(mut reader: impl BufRead) -> io::Result<usize> {
+    let mut count = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(count);
+        }
+        count += buf.iter().filter(|&&b| b == b'\n').count();
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+fn decompress(reader: impl Read + Send + 'static) -> anyhow::Result<Box<dyn BufRead + Send>> {
+    let decoder = zstd::stream::read::Decoder::new(reader)?;
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+fn append_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut s: OsString = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(ext);
+    s.into()
+}
+
+/// Returns the paths of `<zst_path>.NN` files, sorted by `NN`.
+fn find_parts(zst_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (Some(dir), Some(file_name)) = (zst_path.parent(), zst_path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}.", file_name.to_string_lossy());
+    let mut parts = Vec::new();
+    for dir_entry in std::fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name();
+        let name = name.to_string_lossy();
+        let Some(suffix) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        if let Ok(n) = suffix.parse::<u32>() {
+            parts.push((n, dir_entry.path()));
+        }
+    }
+    parts.sort_unstable_by_key(|(n, _)| *n);
+    Ok(parts.into_iter().map(|(_, p)| p).collect())
+}
+
+/// Reads the given files one after another as if they were a single file.
+struct MultiPartReader {
+    parts: std::vec::IntoIter<PathBuf>,
+    current: Option<File>,
+}
+
+impl MultiPartReader {
+    fn new(parts: Vec<PathBuf>) -> Self {
+        Self {
+            parts: parts.into_iter(),
+            current: None,
+        }
+    }
+}
+
+impl Read for MultiPartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.current {
+                let n = file.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+            }
+            match self.parts.next() {
+                Some(path) => self.current = Some(File::open(path)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const TEXT: &str = "1 2 0 1\n3 1 0 1\n4 8 0 1\n";
+
+    fn compress(text: &str) -> Vec<u8> {
+        zstd::stream::encode_all(text.as_bytes(), 0).unwrap()
+    }
+
+    fn read_to_string(path: &Path) -> anyhow::Result<String> {
+        let mut text = String::new();
+        open(path)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn open_plain_and_compressed_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let plain = dir.path().join("plain.lis");
+        std::fs::write(&plain, TEXT)?;
+        assert_eq!(read_to_string(&plain)?, TEXT);
+
+        // A `.zst` file given by its own path.
+        let compressed = dir.path().join("compressed.lis.zst");
+        std::fs::write(&compressed, compress(TEXT))?;
+        assert_eq!(read_to_string(&compressed)?, TEXT);
+        Ok(())
+    }
+
+    #[test]
+    fn fall_back_to_zst() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("DS1.lis.zst"), compress(TEXT))?;
+        assert_eq!(read_to_string(&dir.path().join("DS1.lis"))?, TEXT);
+        Ok(())
+    }
+
+    #[test]
+    fn fall_back_to_zst_parts() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let text = TEXT.repeat(100);
+        let bytes = compress(&text);
+        // The parts are ordered by their numbers, not by their names.
+        let chunks = bytes.chunks(bytes.len() / 11).collect::<Vec<_>>();
+        assert!(chunks.len() > 10);
+        for (i, chunk) in chunks.iter().enumerate() {
+            std::fs::write(dir.path().join(format!("S3.lis.zst.{i}")), chunk)?;
+        }
+        // Not a part.
+        std::fs::write(dir.path().join("S3.lis.zst.bak"), b"garbage")?;
+
+        assert_eq!(read_to_string(&dir.path().join("S3.lis"))?, text);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let err = open(&dir.path().join("missing.lis")).err().unwrap();
+        assert!(err.to_string().starts_with("Cannot find trace file"));
+        Ok(())
+    }
+
+    fn read_input(input: &TraceInput) -> anyhow::Result<String> {
+        let mut text = String::new();
+        input.open()?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn read_stream_once() -> anyhow::Result<()> {
+        let text = TEXT.repeat(100_000);
+        let input = TraceInput::stream(Cursor::new(text.clone()), Path::new(STDIN_PATH))?;
+
+        // Peeking reads complete lines ahead and puts them back.
+        let mut prefix = String::new();
+        input.peek()?.read_to_string(&mut prefix)?;
+        assert!(prefix.len() >= PEEK_LEN && prefix.len() < text.len());
+        assert!(prefix.ends_with('\n'));
+
+        assert_eq!(read_input(&input)?, text);
+        let err = input.open().err().unwrap();
+        assert_eq!(err.to_string(), STREAM_READ);
+        Ok(())
+    }
+
+    #[test]
+    fn spool_compressed_stream() -> anyhow::Result<()> {
+        let input = TraceInput::stream(Cursor::new(compress(TEXT)), Path::new(STDIN_PATH))?;
+        let input = input.spool()?;
+        assert!(matches!(input, TraceInput::Spooled(_)));
+        // The spooled input is expanded and can be opened many times.
+        assert_eq!(read_input(&input)?, TEXT);
+        assert_eq!(read_input(&input.clone())?, TEXT);
+
+        let TraceInput::Spooled(path) = &input else {
+            unreachable!()
+        };
+        let path = path.to_path_buf();
+        assert!(path.exists());
+        drop(input);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn split_plain_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("plain.lis");
+        let text = TEXT.repeat(100);
+        std::fs::write(&path, &text)?;
+
+        let segments = split_lines(&path, 64, 4)?;
+        assert_eq!(segments.len(), 4);
+        let mut num_lines = 0;
+        let mut joined = String::new();
+        for range in &segments {
+            let mut segment = String::new();
+            read_segment(&path, range)?.read_to_string(&mut segment)?;
+            // A segment ends at a line boundary.
+            assert!(segment.ends_with('\n'));
+            num_lines += count_lines(read_segment(&path, range)?)?;
+            joined.push_str(&segment);
+        }
+        assert_eq!(joined, text);
+        assert_eq!(num_lines, 300);
+
+        // A small file is a single segment.
+        let segments = split_lines(&path, 1 << 20, 4)?;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0], 0..text.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn multi_part_reader() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut parts = Vec::new();
+        for (i, content) in ["abc", "", "de", "f"].iter().enumerate() {
+            let path = dir.path().join(format!("part{i}"));
+            File::create(&path)?.write_all(content.as_bytes())?;
+            parts.push(path);
+        }
+
+        let mut reader = MultiPartReader::new(parts);
+        // A read does not cross the end of a part.
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf)?, 3);
+        assert_eq!(&buf[..3], b"abc");
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest)?;
+        assert_eq!(rest, "def");
+        assert_eq!(reader.read(&mut buf)?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Validates a trace file: reads all of its lines and reports the malformed ones
+//! with their line numbers and contents, instead of stopping at the first one.
+
+use std::{
+    fmt,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    parser::{binary::BinaryTraceReader, excerpt, TraceEntry, TraceFormat, TraceParseError},
+    trace_reader::TraceInput,
+};
+
+/// The maximum number of problems to keep in a report. The rest are only counted.
+const MAX_REPORTED_PROBLEMS: usize = 20;
+
+/// A malformed line of a trace file.
+#[derive(Debug)]
+pub struct TraceProblem {
+    /// The 1-based line number, or the 1-based record number in a binary trace.
+    pub line_number: usize,
+    pub kind: &'static str,
+    pub message: String,
+    /// The beginning of the line. Empty for a binary trace.
+    pub excerpt: String,
+}
+
+/// The result of validating a trace file.
+#[derive(Debug)]
+pub struct ValidationReport {
+    pub source: PathBuf,
+    pub format: TraceFormat,
+    /// The number of lines, or records in a binary trace, that were read.
+    pub num_lines: usize,
+    pub num_entries: usize,
+    pub num_problems: usize,
+    /// The number of problems by kind, in the order they were first found.
+    pub problem_counts: Vec<(&'static str, usize)>,
+    /// The first problems found.
+    pub problems: Vec<TraceProblem>,
+}
+
+/// Reads the whole trace file at `path` and reports its malformed lines. If
+/// `format` is `None`, the format is detected from the contents of the file.
+pub fn validate_trace(
+    path: &Path,
+    format: Option<TraceFormat>,
+) -> anyhow::Result<ValidationReport> {
+    let trace = TraceInput::new(path)?;
+    let format = match format {
+        Some(format) => format,
+        None => TraceFormat::detect(trace.peek()?)
+            .with_context(|| format!("Cannot detect the format of {}", path.display()))?,
+    };
+    validate_input(&trace, path, format, |_| ())
+}
+
+/// Reads the whole trace and reports its malformed lines. `on_entry` is called
+/// with every valid entry, so that the caller can collect something else in the
+/// same pass.
+pub(crate) fn validate_input(
+    trace: &TraceInput,
+    path: &Path,
+    format: TraceFormat,
+    mut on_entry: impl FnMut(&TraceEntry),
+) -> anyhow::Result<ValidationReport> {
+    let mut report = ValidationReport::new(path, format);
+    let reader = trace.open()?;
+    let Some(mut parser) = format.line_parser() else {
+        validate_binary(reader, &mut report, on_entry)?;
+        return Ok(report);
+    };
+
+    for (i, line) in reader.lines().enumerate() {
+        report.num_lines += 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                report.add_problem(i + 1, "invalid UTF-8", e.to_string(), String::new());
+                continue;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("{}:{}", path.display(), i + 1));
+            }
+        };
+        match parser.parse(&line, i) {
+            Ok(Some(entry)) => {
+                report.num_entries += 1;
+                on_entry(&entry);
+            }
+            Ok(None) => (),
+            Err(e) => {
+                let kind = e
+                    .downcast_ref::<TraceParseError>()
+                    .map_or("malformed line", TraceParseError::kind);
+                report.add_problem(i + 1, kind, e.to_string(), excerpt(&line));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Reads the records of a binary trace. A corrupted record ends the validation as
+/// the records after it cannot be located.
+fn validate_binary(
+    reader: Box<dyn BufRead + Send>,
+    report: &mut ValidationReport,
+    mut on_entry: impl FnMut(&TraceEntry),
+) -> anyhow::Result<()> {
+    for entry in BinaryTraceReader::new(reader)? {
+        report.num_lines += 1;
+        match entry {
+            Ok(entry) => {
+                report.num_entries += 1;
+                on_entry(&entry);
+            }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                report.add_problem(report.num_lines, "corrupted record", message, String::new());
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ValidationReport {
+    fn new(source: &Path, format: TraceFormat) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            format,
+            num_lines: 0,
+            num_entries: 0,
+            num_problems: 0,
+            problem_counts: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if no malformed lines were found.
+    pub fn is_valid(&self) -> bool {
+        self.num_problems == 0
+    }
+
+    fn add_problem(
+        &mut self,
+        line_number: usize,
+        kind: &'static str,
+        message: String,
+        excerpt: String,
+    ) {
+        self.num_problems += 1;
+        match self.problem_counts.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, count)) => *count += 1,
+            None => self.problem_counts.push((kind, 1)),
+        }
+        if self.problems.len() < MAX_REPORTED_PROBLEMS {
+            self.problems.push(TraceProblem {
+                line_number,
+                kind,
+                message,
+                excerpt,
+            });
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = self.source.display();
+        for problem in &self.problems {
+            writeln!(f, "{}:{}: {}", source, problem.line_number, problem.message)?;
+            if !problem.excerpt.is_empty() {
+                writeln!(f, "    {}", problem.excerpt)?;
+            }
+        }
+        if self.num_problems > self.problems.len() {
+            writeln!(
+                f,
+                "... and {} more problems",
+                self.num_problems - self.problems.len()
+            )?;
+        }
+        if !self.problems.is_empty() {
+            writeln!(f)?;
+        }
+
+        let unit = if self.format == TraceFormat::Binary {
+            "Records"
+        } else {
+            "Lines"
+        };
+        writeln!(f, "Trace: {}", source)?;
+        writeln!(f, "Format: {:?}", self.format)?;
+        writeln!(f, "{}: {}", unit, self.num_lines)?;
+        writeln!(f, "Entries: {}", self.num_entries)?;
+        write!(
+            f,
+            "Malformed {}: {}",
+            unit.to_lowercase(),
+            self.num_problems
+        )?;
+        for (kind, count) in &self.problem_counts {
+            write!(f, "\n    {}: {}", kind, count)?;
+        }
+        Ok(())
+    }
+}
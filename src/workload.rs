@@ -0,0 +1,734 @@
+//! Synthetic workloads, which are generated by mokabench instead of read from a
+//! trace file. They run through the same harness as the trace files.
+
+use std::fmt;
+
+use crate::parser::{TraceEntries, TraceEntry, TraceOperation};
+
+mod phased;
+
+pub(crate) use phased::phase_runs;
+pub use phased::{Phase, PhasedWorkload};
+
+/// A synthetic workload.
+#[derive(Clone, Debug)]
+pub enum Workload {
+    /// Reads of Zipf-distributed keys.
+    Zipf(ZipfWorkload),
+    /// One of the YCSB core workloads.
+    Ycsb(YcsbWorkload),
+    /// Reads of a Zipf-distributed hot set overlaid with periodic scans.
+    ScanPollution(ScanPollutionWorkload),
+    /// A sequence of phases described in a file.
+    Phased(PhasedWorkload),
+    /// Rounds of all clients reading the same few missing keys at once.
+    ThunderingHerd(ThunderingHerdWorkload),
+}
+
+impl Workload {
+    /// The name of the workload, which is shown as the name of the trace.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Zipf(w) => format!("zipf-{}", w.theta),
+            Self::Ycsb(w) => format!("ycsb-{}-{}", w.kind.name(), w.theta),
+            Self::ScanPollution(w) => {
+                let kind = if w.loop_scans { "loop" } else { "scan" };
+                format!("{}-pollution-{}", kind, w.theta)
+            }
+            Self::Phased(w) => w.name().to_string(),
+            Self::ThunderingHerd(w) => format!("thundering-herd-{}", w.keys_per_round),
+        }
+    }
+
+    /// The number of keys in the key space of the workload.
+    pub fn num_keys(&self) -> usize {
+        match self {
+            Self::Zipf(w) => w.num_keys,
+            Self::Ycsb(w) => w.num_keys,
+            Self::ScanPollution(w) => w.num_keys,
+            Self::Phased(w) => w.num_keys(),
+            Self::ThunderingHerd(w) => w.keys_per_round * w.num_rounds,
+        }
+    }
+
+    /// The names of the classes of the requests that are counted separately, in
+    /// the order of their indices. Empty if the requests are not classified.
+    pub fn request_classes(&self) -> &'static [&'static str] {
+        match self {
+            Self::ScanPollution(_) => &["hot", "scan"],
+            _ => &[],
+        }
+    }
+
+    /// Returns `true` if the workload has operations other than reads.
+    pub fn has_operations(&self) -> bool {
+        match self {
+            Self::Zipf(_) | Self::ScanPollution(_) | Self::Phased(_) | Self::ThunderingHerd(_) => {
+                false
+            }
+            Self::Ycsb(w) => w.kind != YcsbKind::C,
+        }
+    }
+
+    /// Returns an iterator of the entries of the workload. The same entries are
+    /// generated every time from the seed.
+    pub(crate) fn entries(&self) -> TraceEntries {
+        match self {
+            Self::Zipf(w) => {
+                let w = w.clone();
+                let mut rng = SplitMix64::new(w.seed);
+                Box::new((0..w.num_requests).map(move |i| {
+                    let key = w.zipf.sample(&mut rng);
+                    Ok(TraceEntry::new(key..(key + 1), i))
+                }))
+            }
+            Self::Ycsb(w) => Box::new(YcsbGenerator::new(w).map(Ok)),
+            Self::ScanPollution(w) => Box::new(ScanPollutionGenerator::new(w).map(Ok)),
+            Self::Phased(w) => w.entries(),
+            Self::ThunderingHerd(w) => {
+                let num_keys = w.keys_per_round * w.num_rounds;
+                Box::new((0..num_keys).map(|key| Ok(TraceEntry::new(key..(key + 1), key))))
+            }
+        }
+    }
+}
+
+/// Reads of keys in `0..num_keys` drawn from a Zipf distribution. Key 0 is the
+/// hottest. Use `--key-transform scramble` to spread the hot keys over the key
+/// space.
+#[derive(Clone, Debug)]
+pub struct ZipfWorkload {
+    theta: f64,
+    num_keys: usize,
+    num_requests: usize,
+    seed: u64,
+    zipf: Zipf,
+}
+
+impl ZipfWorkload {
+    /// The default skew, which is the one of YCSB.
+    pub const DEFAULT_THETA: f64 = 0.99;
+    pub const DEFAULT_NUM_KEYS: usize = 1_000_000;
+    pub const DEFAULT_NUM_REQUESTS: usize = 10_000_000;
+
+    /// Creates a workload of `num_requests` reads of keys in `0..num_keys` with
+    /// the skew `theta` in `[0, 1)`. 0 is uniform, and the closer to 1 the more
+    /// skewed. The keys are drawn from a pseudo-random sequence of `seed`.
+    pub fn new(
+        theta: f64,
+        num_keys: usize,
+        num_requests: usize,
+        seed: u64,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            theta,
+            num_keys,
+            num_requests,
+            seed,
+            zipf: Zipf::new(theta, num_keys)?,
+        })
+    }
+}
+
+/// The YCSB core workloads. See
+/// <https://github.com/brianfrankcooper/YCSB/wiki/Core-Workloads>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YcsbKind {
+    /// Update heavy: 50% reads and 50% updates of Zipf-distributed keys.
+    A,
+    /// Read mostly: 95% reads and 5% updates of Zipf-distributed keys.
+    B,
+    /// Read only: 100% reads of Zipf-distributed keys.
+    C,
+    /// Read latest: 95% reads and 5% inserts of new keys. The reads are skewed to
+    /// the most recently inserted keys.
+    D,
+    /// Short ranges: 95% scans and 5% inserts of new keys. A scan reads a range of
+    /// up to 100 keys from a Zipf-distributed key.
+    E,
+    /// Read-modify-write: 50% reads, and 50% reads followed by updates of the same
+    /// Zipf-distributed keys.
+    F,
+}
+
+impl YcsbKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+            Self::C => "c",
+            Self::D => "d",
+            Self::E => "e",
+            Self::F => "f",
+        }
+    }
+}
+
+impl std::str::FromStr for YcsbKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a" => Ok(Self::A),
+            "b" => Ok(Self::B),
+            "c" => Ok(Self::C),
+            "d" => Ok(Self::D),
+            "e" => Ok(Self::E),
+            "f" => Ok(Self::F),
+            _ => anyhow::bail!(r#"Unknown YCSB workload "{}". Available: "a" to "f""#, s),
+        }
+    }
+}
+
+/// A YCSB core workload. The reads are read-through (get, and insert on a miss),
+/// and the updates and the inserts of new keys are inserts. A scan of workload E
+/// reads every key in its range.
+#[derive(Clone, Debug)]
+pub struct YcsbWorkload {
+    kind: YcsbKind,
+    theta: f64,
+    num_keys: usize,
+    num_requests: usize,
+    seed: u64,
+    zipf: Zipf,
+}
+
+impl YcsbWorkload {
+    /// The maximum length of a scan of workload E.
+    const MAX_SCAN_LEN: usize = 100;
+
+    /// Creates a workload of `num_requests` operations on keys in `0..num_keys`
+    /// (plus the inserted ones). `theta` is the skew of the Zipf distribution;
+    /// YCSB uses 0.99.
+    pub fn new(
+        kind: YcsbKind,
+        theta: f64,
+        num_keys: usize,
+        num_requests: usize,
+        seed: u64,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            kind,
+            theta,
+            num_keys,
+            num_requests,
+            seed,
+            zipf: Zipf::new(theta, num_keys)?,
+        })
+    }
+
+    /// Returns the fractions of the reads (or scans) and the other operation of
+    /// the workload.
+    fn read_fraction(&self) -> f64 {
+        match self.kind {
+            YcsbKind::A | YcsbKind::F => 0.5,
+            YcsbKind::B | YcsbKind::D | YcsbKind::E => 0.95,
+            YcsbKind::C => 1.0,
+        }
+    }
+}
+
+/// Generates the entries of a YCSB workload.
+struct YcsbGenerator {
+    workload: YcsbWorkload,
+    rng: SplitMix64,
+    /// The number of keys including the inserted ones. The next key to insert.
+    num_keys: usize,
+    num_requests: usize,
+    line_number: usize,
+    /// The update of a read-modify-write, which follows its read.
+    pending: Option<TraceEntry>,
+}
+
+impl YcsbGenerator {
+    fn new(workload: &YcsbWorkload) -> Self {
+        Self {
+            rng: SplitMix64::new(workload.seed),
+            num_keys: workload.num_keys,
+            num_requests: 0,
+            line_number: 0,
+            pending: None,
+            workload: workload.clone(),
+        }
+    }
+
+    fn entry(&mut self, range: std::ops::Range<usize>, op: TraceOperation) -> TraceEntry {
+        self.line_number += 1;
+        TraceEntry::new(range, self.line_number - 1).with_operation(op)
+    }
+
+    /// Inserts a new key, and grows the Zipf distribution over it.
+    fn insert(&mut self) -> TraceEntry {
+        let key = self.num_keys;
+        self.num_keys += 1;
+        self.workload.zipf.grow(self.num_keys);
+        self.entry(key..(key + 1), TraceOperation::Write)
+    }
+}
+
+impl Iterator for YcsbGenerator {
+    type Item = TraceEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.pending.take() {
+            return Some(entry);
+        }
+        if self.num_requests >= self.workload.num_requests {
+            return None;
+        }
+        self.num_requests += 1;
+
+        let is_read = self.rng.next_f64() < self.workload.read_fraction();
+        let entry = match self.workload.kind {
+            YcsbKind::A | YcsbKind::B | YcsbKind::C => {
+                let key = self.workload.zipf.sample(&mut self.rng);
+                let op = if is_read {
+                    TraceOperation::Read
+                } else {
+                    TraceOperation::Write
+                };
+                self.entry(key..(key + 1), op)
+            }
+            YcsbKind::D if is_read => {
+                // The latest distribution: the most recently inserted key is the
+                // hottest.
+                let key = self.num_keys - 1 - self.workload.zipf.sample(&mut self.rng);
+                self.entry(key..(key + 1), TraceOperation::Read)
+            }
+            YcsbKind::E if is_read => {
+                let start = self.workload.zipf.sample(&mut self.rng);
+                let len = 1 + self.rng.next_u64() as usize % YcsbWorkload::MAX_SCAN_LEN;
+                let end = (start + len).min(self.num_keys);
+                self.entry(start..end, TraceOperation::Read)
+            }
+            YcsbKind::D | YcsbKind::E => self.insert(),
+            YcsbKind::F => {
+                let key = self.workload.zipf.sample(&mut self.rng);
+                let read = self.entry(key..(key + 1), TraceOperation::Read);
+                if !is_read {
+                    // The update of the read-modify-write follows the read.
+                    self.pending = Some(self.entry(key..(key + 1), TraceOperation::Write));
+                }
+                read
+            }
+        };
+        Some(entry)
+    }
+}
+
+/// Reads of a hot set of keys in `0..num_keys` drawn from a Zipf distribution,
+/// with a sequential scan of `scan_length` keys after every `scan_interval` hot
+/// reads. The scans read the keys after the hot set, each once (one-shot scans),
+/// or the same keys every time (loops) if `loop_scans` is `true`. The hit ratios
+/// of the hot reads and the scans are reported separately, which shows how well
+/// the caches keep the hot set from being flushed by the scans.
+///
+/// The scans are not concurrent with the hot reads by themselves, but the clients
+/// run the batches of both at the same time.
+#[derive(Clone, Debug)]
+pub struct ScanPollutionWorkload {
+    theta: f64,
+    num_keys: usize,
+    num_requests: usize,
+    scan_length: usize,
+    scan_interval: usize,
+    loop_scans: bool,
+    seed: u64,
+    zipf: Zipf,
+}
+
+impl ScanPollutionWorkload {
+    pub const DEFAULT_SCAN_LENGTH: usize = 100_000;
+    pub const DEFAULT_SCAN_INTERVAL: usize = 1_000_000;
+
+    /// The index of the class of the hot reads in `Workload::request_classes`.
+    const HOT_CLASS: u8 = 0;
+    /// The index of the class of the scans in `Workload::request_classes`.
+    const SCAN_CLASS: u8 = 1;
+
+    /// Creates a workload of `num_requests` reads in total, of the hot set of
+    /// `num_keys` keys with the skew `theta` and of the scans.
+    pub fn new(
+        theta: f64,
+        num_keys: usize,
+        num_requests: usize,
+        scan_length: usize,
+        scan_interval: usize,
+        loop_scans: bool,
+        seed: u64,
+    ) -> anyhow::Result<Self> {
+        if scan_length == 0 || scan_interval == 0 {
+            anyhow::bail!("The scan length and interval must be positive");
+        }
+        Ok(Self {
+            theta,
+            num_keys,
+            num_requests,
+            scan_length,
+            scan_interval,
+            loop_scans,
+            seed,
+            zipf: Zipf::new(theta, num_keys)?,
+        })
+    }
+}
+
+/// Generates the entries of a scan pollution workload.
+struct ScanPollutionGenerator {
+    workload: ScanPollutionWorkload,
+    rng: SplitMix64,
+    num_requests: usize,
+    /// The number of hot reads since the last scan.
+    num_hot_reads: usize,
+    num_scans: usize,
+    /// The keys left to read in the current scan.
+    scan: std::ops::Range<usize>,
+}
+
+impl ScanPollutionGenerator {
+    fn new(workload: &ScanPollutionWorkload) -> Self {
+        Self {
+            rng: SplitMix64::new(workload.seed),
+            num_requests: 0,
+            num_hot_reads: 0,
+            num_scans: 0,
+            scan: 0..0,
+            workload: workload.clone(),
+        }
+    }
+
+    /// Starts the next scan after the hot set.
+    fn start_scan(&mut self) {
+        let len = self.workload.scan_length;
+        let offset = if self.workload.loop_scans {
+            0
+        } else {
+            self.num_scans * len
+        };
+        let start = self.workload.num_keys + offset;
+        self.scan = start..(start + len);
+        self.num_scans += 1;
+        self.num_hot_reads = 0;
+    }
+}
+
+impl Iterator for ScanPollutionGenerator {
+    type Item = TraceEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.num_requests >= self.workload.num_requests {
+            return None;
+        }
+        if self.scan.is_empty() && self.num_hot_reads >= self.workload.scan_interval {
+            self.start_scan();
+        }
+        let line_number = self.num_requests;
+        self.num_requests += 1;
+
+        let (key, class) = match self.scan.next() {
+            Some(key) => (key, ScanPollutionWorkload::SCAN_CLASS),
+            None => {
+                self.num_hot_reads += 1;
+                let key = self.workload.zipf.sample(&mut self.rng);
+                (key, ScanPollutionWorkload::HOT_CLASS)
+            }
+        };
+        Some(TraceEntry::new(key..(key + 1), line_number).with_class(class))
+    }
+}
+
+/// Rounds of reads of `keys_per_round` keys, which all clients read at once, in
+/// the same order, by `get_with` (or `entry().or_insert_with` with `--entry-api`).
+/// Each round reads new keys, so they are all missing and only one caller per key
+/// should run the init closure (the slow loader of `--insertion-delay`) while the
+/// others wait for its value.
+///
+/// The workload is not run through the producers like the others. See the `herd`
+/// module.
+#[derive(Clone, Debug)]
+pub struct ThunderingHerdWorkload {
+    pub(crate) keys_per_round: usize,
+    pub(crate) num_rounds: usize,
+}
+
+impl ThunderingHerdWorkload {
+    pub const DEFAULT_KEYS_PER_ROUND: usize = 8;
+    pub const DEFAULT_NUM_ROUNDS: usize = 100;
+    /// The time the init closure takes to load a value, unless `--insertion-delay`
+    /// is given.
+    pub const DEFAULT_LOADER_DELAY_MICROS: u64 = 1_000;
+
+    /// Creates a workload of `num_rounds` rounds, each reading `keys_per_round` new
+    /// keys. The keys of the round `r` are `r * keys_per_round` to
+    /// `(r + 1) * keys_per_round - 1`.
+    pub fn new(keys_per_round: usize, num_rounds: usize) -> anyhow::Result<Self> {
+        if keys_per_round == 0 || num_rounds == 0 {
+            anyhow::bail!("The number of keys per round and the number of rounds must be positive");
+        }
+        Ok(Self {
+            keys_per_round,
+            num_rounds,
+        })
+    }
+}
+
+/// Draws integers in `0..n` from a Zipf distribution by the algorithm of Gray et
+/// al., "Quickly Generating Billion-Record Synthetic Databases" (SIGMOD 1994),
+/// which YCSB also uses.
+#[derive(Clone)]
+pub(crate) struct Zipf {
+    n: usize,
+    theta: f64,
+    alpha: f64,
+    zeta_n: f64,
+    eta: f64,
+}
+
+impl Zipf {
+    pub(crate) fn new(theta: f64, n: usize) -> anyhow::Result<Self> {
+        if !(0.0..1.0).contains(&theta) {
+            anyhow::bail!("The Zipf skew (theta) must be in [0, 1), but got {}", theta);
+        }
+        if n == 0 {
+            anyhow::bail!("The number of keys must be positive");
+        }
+        let zeta_n = zeta(n, theta);
+        Ok(Self {
+            n,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta_n,
+            eta: eta(n, theta, zeta_n),
+        })
+    }
+
+    /// Grows the range of the distribution to `0..n`, as YCSB does when keys are
+    /// inserted. The harmonic number is updated incrementally.
+    pub(crate) fn grow(&mut self, n: usize) {
+        if n <= self.n {
+            return;
+        }
+        self.zeta_n += ((self.n + 1)..=n)
+            .map(|i| 1.0 / (i as f64).powf(self.theta))
+            .sum::<f64>();
+        self.n = n;
+        self.eta = eta(n, self.theta, self.zeta_n);
+    }
+
+    pub(crate) fn sample(&self, rng: &mut SplitMix64) -> usize {
+        let u = rng.next_f64();
+        let uz = u * self.zeta_n;
+        if uz < 1.0 || self.n == 1 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) || self.n == 2 {
+            1
+        } else {
+            let k = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+            (k as usize).min(self.n - 1)
+        }
+    }
+}
+
+impl fmt::Debug for Zipf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Zipf")
+            .field("n", &self.n)
+            .field("theta", &self.theta)
+            .finish()
+    }
+}
+
+fn eta(n: usize, theta: f64, zeta_n: f64) -> f64 {
+    if n <= 2 {
+        0.0
+    } else {
+        (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2, theta) / zeta_n)
+    }
+}
+
+/// Returns the generalized harmonic number `sum(1 / i^theta)` for `i` in `1..=n`.
+fn zeta(n: usize, theta: f64) -> f64 {
+    (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum()
+}
+
+/// A small and fast pseudo-random number generator (SplitMix64). It is used
+/// instead of an external crate so that the same seed always generates the same
+/// workload.
+#[derive(Clone, Debug)]
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Returns the generator that has already generated `n` numbers from `seed`.
+    /// It jumps there in constant time, so the numbers can be generated out of
+    /// order.
+    pub(crate) fn skip_to(seed: u64, n: u64) -> Self {
+        Self(seed.wrapping_add(n.wrapping_mul(Self::GAMMA)))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(Self::GAMMA);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `[0, 1)`.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(workload: Workload) -> Vec<TraceEntry> {
+        workload.entries().map(Result::unwrap).collect()
+    }
+
+    fn zipf(theta: f64, seed: u64) -> Vec<usize> {
+        let workload = ZipfWorkload::new(theta, 1_000, 100_000, seed).unwrap();
+        entries(Workload::Zipf(workload))
+            .iter()
+            .map(|entry| entry.range().start)
+            .collect()
+    }
+
+    fn ycsb(kind: YcsbKind, seed: u64) -> Vec<TraceEntry> {
+        entries(Workload::Ycsb(
+            YcsbWorkload::new(kind, 0.99, 1_000, 10_000, seed).unwrap(),
+        ))
+    }
+
+    fn summary(entries: &[TraceEntry]) -> Vec<(std::ops::Range<usize>, Option<TraceOperation>)> {
+        entries.iter().map(|e| (e.range(), e.operation())).collect()
+    }
+
+    #[test]
+    fn split_mix_64() {
+        // The first numbers of the reference implementation with seed 0.
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+
+        let mut rng = SplitMix64::new(7);
+        let numbers = (0..100).map(|_| rng.next_u64()).collect::<Vec<_>>();
+        for (n, number) in numbers.iter().enumerate() {
+            assert_eq!(SplitMix64::skip_to(7, n as u64).next_u64(), *number);
+        }
+        assert!((0..1_000).all(|_| (0.0..1.0).contains(&rng.next_f64())));
+    }
+
+    #[test]
+    fn zipf_keys() {
+        let keys = zipf(0.99, 1);
+        assert_eq!(keys.len(), 100_000);
+        assert!(keys.iter().all(|&key| key < 1_000));
+        // The same seed generates the same keys.
+        assert_eq!(keys[..8], [37, 146, 804, 14, 14, 167, 397, 26]);
+        assert_eq!(zipf(0.99, 1), keys);
+        assert_ne!(zipf(0.99, 2), keys);
+
+        // Key 0 is read with the probability `1 / zeta(n, theta)`, which is about
+        // 13% for 1,000 keys.
+        let expected = 100_000.0 / zeta(1_000, 0.99);
+        let hottest = keys.iter().filter(|&&key| key == 0).count() as f64;
+        assert!((hottest - expected).abs() < expected * 0.05, "{hottest}");
+        let count = |key| keys.iter().filter(|&&k| k == key).count();
+        assert!(count(0) > count(1) && count(1) > count(10) && count(10) > count(500));
+
+        // Theta 0 is uniform.
+        let keys = zipf(0.0, 1);
+        let hottest = (0..1_000).map(|key| keys.iter().filter(|&&k| k == key).count());
+        assert!(hottest.max().unwrap() < 200);
+
+        assert!(ZipfWorkload::new(1.0, 1_000, 1, 1).is_err());
+        assert!(ZipfWorkload::new(0.5, 0, 1, 1).is_err());
+    }
+
+    #[test]
+    fn zipf_grow() {
+        let mut grown = Zipf::new(0.99, 100).unwrap();
+        grown.grow(1_000);
+        let zipf = Zipf::new(0.99, 1_000).unwrap();
+        assert!((grown.zeta_n - zipf.zeta_n).abs() < 1e-9);
+        assert!((grown.eta - zipf.eta).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ycsb_is_deterministic() {
+        for kind in ["a", "b", "c", "d", "e", "f"] {
+            let kind = kind.parse().unwrap();
+            let entries = ycsb(kind, 1);
+            if kind == YcsbKind::A {
+                use TraceOperation::{Read, Write};
+                let first = [(146, Write), (14, Write), (167, Read), (26, Write)];
+                let first = first.map(|(key, op)| (key..(key + 1), Some(op)));
+                assert_eq!(summary(&entries[..4]), first);
+            }
+            assert_eq!(summary(&ycsb(kind, 1)), summary(&entries));
+            assert_ne!(summary(&ycsb(kind, 2)), summary(&entries));
+        }
+    }
+
+    #[test]
+    fn ycsb_operations() {
+        let reads = |entries: &[TraceEntry]| {
+            entries
+                .iter()
+                .filter(|e| e.operation() == Some(TraceOperation::Read))
+                .count()
+        };
+
+        let a = ycsb(YcsbKind::A, 1);
+        assert_eq!(a.len(), 10_000);
+        assert!((4_700..5_300).contains(&reads(&a)));
+        assert_eq!(reads(&ycsb(YcsbKind::C, 1)), 10_000);
+
+        // The inserts of D and E are new keys in order.
+        for kind in [YcsbKind::D, YcsbKind::E] {
+            let entries = ycsb(kind, 1);
+            let inserts = entries
+                .iter()
+                .filter(|e| e.operation() == Some(TraceOperation::Write))
+                .map(|e| e.range().start)
+                .collect::<Vec<_>>();
+            assert!(!inserts.is_empty());
+            assert_eq!(
+                inserts,
+                (1_000..(1_000 + inserts.len())).collect::<Vec<_>>()
+            );
+        }
+
+        // The scans of E read existing keys.
+        let mut num_keys = 1_000;
+        for entry in ycsb(YcsbKind::E, 1) {
+            match entry.operation() {
+                Some(TraceOperation::Write) => num_keys += 1,
+                _ => {
+                    let range = entry.range();
+                    assert!(!range.is_empty() && range.len() <= YcsbWorkload::MAX_SCAN_LEN);
+                    assert!(range.end <= num_keys);
+                }
+            }
+        }
+
+        // An update of F follows the read of the same key.
+        let f = ycsb(YcsbKind::F, 1);
+        assert_eq!(reads(&f), 10_000);
+        assert!(f.len() > 14_000);
+        for (i, entry) in f.iter().enumerate() {
+            if entry.operation() == Some(TraceOperation::Write) {
+                assert_eq!(f[i - 1].operation(), Some(TraceOperation::Read));
+                assert_eq!(f[i - 1].range(), entry.range());
+            }
+        }
+    }
+}
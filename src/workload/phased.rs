@@ -0,0 +1,503 @@
+//! A workload of a sequence of phases, described in a TOML file. Each phase has
+//! its own key distribution, key range, op mix and number of clients, so that the
+//! hot set can be moved in a controlled way:
+//!
+//! ```toml
+//! name = "shifting-hot-set"
+//! seed = 1
+//!
+//! [[phase]]
+//! name = "hot-a"
+//! requests = 2_000_000
+//! distribution = "zipf"
+//! theta = 0.99
+//! key_range = [0, 100_000]
+//!
+//! [[phase]]
+//! name = "hot-b"
+//! duration_secs = 5.0
+//! distribution = "zipf"
+//! key_range = [100_000, 200_000]
+//! op_mix = "update=5%"
+//! clients = 8
+//! ```
+//!
+//! The phases run one after another against the same cache, and each of them is
+//! reported in its own row.
+
+use std::{fmt, ops::Range, path::Path, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use super::{SplitMix64, Zipf, ZipfWorkload};
+use crate::{
+    config::Config,
+    op_mix::OpMix,
+    parser::{TraceEntries, TraceEntry},
+    TraceDescriptor,
+};
+
+/// A workload of phases run one after another.
+#[derive(Clone, Debug)]
+pub struct PhasedWorkload {
+    name: String,
+    phases: Vec<Phase>,
+}
+
+/// A phase of a `PhasedWorkload`.
+#[derive(Clone)]
+pub struct Phase {
+    pub(crate) name: String,
+    /// The number of requests of the phase. If `None`, the phase runs until its
+    /// duration has elapsed, or until the end of a scan.
+    pub(crate) num_requests: Option<usize>,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) distribution: KeyDistribution,
+    pub(crate) key_range: Range<usize>,
+    /// The operations to inject. If `None`, the ones of the run.
+    pub(crate) op_mix: Option<OpMix>,
+    /// The number of clients. If `None`, the one of the run.
+    pub(crate) num_clients: Option<u16>,
+    seed: u64,
+}
+
+/// The distribution of the keys of a phase.
+#[derive(Clone)]
+pub(crate) enum KeyDistribution {
+    Uniform,
+    Zipf(Zipf),
+    /// Reads the keys of the range in order, each once.
+    Scan,
+    /// Reads the keys of the range in order, over and over.
+    Loop,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhasesFile {
+    name: Option<String>,
+    seed: Option<u64>,
+    #[serde(default, rename = "phase")]
+    phases: Vec<PhaseEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhaseEntry {
+    name: Option<String>,
+    requests: Option<usize>,
+    duration_secs: Option<f64>,
+    distribution: DistributionName,
+    theta: Option<f64>,
+    key_range: [usize; 2],
+    op_mix: Option<String>,
+    clients: Option<u16>,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum DistributionName {
+    Uniform,
+    Zipf,
+    Scan,
+    Loop,
+}
+
+impl PhasedWorkload {
+    /// Loads the workload from the TOML file at `path`. The name of the workload
+    /// is the `name` in the file, or the file name without the extension.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read workload file {}", path.display()))?;
+        let default_name = path
+            .file_stem()
+            .map_or_else(|| "phases".into(), |s| s.to_string_lossy());
+        Self::parse(&contents, &default_name)
+            .with_context(|| format!("Invalid workload file {}", path.display()))
+    }
+
+    fn parse(contents: &str, default_name: &str) -> anyhow::Result<Self> {
+        let file: PhasesFile = toml::from_str(contents)?;
+        if file.phases.is_empty() {
+            anyhow::bail!("The workload has no phases");
+        }
+        let phases = file
+            .phases
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let name = entry
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("phase-{}", i + 1));
+                Phase::new(name.clone(), entry).with_context(|| format!(r#"Phase "{name}""#))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut workload = Self {
+            name: file.name.unwrap_or_else(|| default_name.to_string()),
+            phases,
+        };
+        workload.set_seed(file.seed.unwrap_or_default());
+        Ok(workload)
+    }
+
+    /// Sets the seed of the pseudo-random numbers of the phases, replacing the one
+    /// in the file. Each phase draws its keys from its own sequence derived from
+    /// the seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        for (i, phase) in self.phases.iter_mut().enumerate() {
+            phase.seed = SplitMix64::skip_to(seed, i as u64).next_u64();
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    /// The number of keys up to the end of the highest key range.
+    pub(crate) fn num_keys(&self) -> usize {
+        self.phases
+            .iter()
+            .map(|phase| phase.key_range.end)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns the entries of the phases one after another. A phase with a duration
+    /// and no number of requests never ends, so the phases are usually generated
+    /// one by one by `phase_runs`.
+    pub(crate) fn entries(&self) -> TraceEntries {
+        let phases = self.phases.clone();
+        Box::new(phases.into_iter().flat_map(|phase| phase.entries()))
+    }
+}
+
+impl Phase {
+    fn new(name: String, entry: PhaseEntry) -> anyhow::Result<Self> {
+        let [start, end] = entry.key_range;
+        if start >= end {
+            anyhow::bail!("key_range [{start}, {end}] must have the start less than the end");
+        }
+        if entry.theta.is_some() && entry.distribution != DistributionName::Zipf {
+            anyhow::bail!(r#"theta is only for distribution "zipf""#);
+        }
+        let distribution = match entry.distribution {
+            DistributionName::Uniform => KeyDistribution::Uniform,
+            DistributionName::Zipf => KeyDistribution::Zipf(Zipf::new(
+                entry.theta.unwrap_or(ZipfWorkload::DEFAULT_THETA),
+                end - start,
+            )?),
+            DistributionName::Scan => KeyDistribution::Scan,
+            DistributionName::Loop => KeyDistribution::Loop,
+        };
+
+        if entry.requests == Some(0) {
+            anyhow::bail!("requests must be positive");
+        }
+        let duration = match entry.duration_secs {
+            None => None,
+            Some(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+            Some(secs) => anyhow::bail!("duration_secs {secs} must be positive"),
+        };
+        if entry.requests.is_none()
+            && duration.is_none()
+            && !matches!(distribution, KeyDistribution::Scan)
+        {
+            anyhow::bail!("Either requests or duration_secs is required");
+        }
+        if entry.clients == Some(0) {
+            anyhow::bail!("clients must be positive");
+        }
+        let op_mix = match entry.op_mix {
+            None => None,
+            Some(v) => Some(
+                v.parse::<OpMix>()
+                    .with_context(|| format!(r#"Cannot parse op_mix "{v}""#))?,
+            ),
+        };
+
+        Ok(Self {
+            name,
+            num_requests: entry.requests,
+            duration,
+            distribution,
+            key_range: start..end,
+            op_mix,
+            num_clients: entry.clients,
+            seed: 0,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the entries of the phase. They stop at the number of requests, or
+    /// at the end of a scan; otherwise they never end.
+    fn entries(&self) -> TraceEntries {
+        let Range { start, end } = self.key_range;
+        let mut rng = SplitMix64::new(self.seed);
+        let keys: Box<dyn Iterator<Item = usize> + Send> = match &self.distribution {
+            KeyDistribution::Uniform => {
+                let len = (end - start) as u64;
+                Box::new(std::iter::repeat_with(move || {
+                    start + (rng.next_u64() % len) as usize
+                }))
+            }
+            KeyDistribution::Zipf(zipf) => {
+                let zipf = zipf.clone();
+                Box::new(std::iter::repeat_with(move || {
+                    start + zipf.sample(&mut rng)
+                }))
+            }
+            KeyDistribution::Scan => Box::new(start..end),
+            KeyDistribution::Loop => Box::new((start..end).cycle()),
+        };
+        let keys = match self.num_requests {
+            Some(n) => Box::new(keys.take(n)),
+            None => keys,
+        };
+        Box::new(
+            keys.enumerate()
+                .map(|(i, key)| Ok(TraceEntry::new(key..(key + 1), i))),
+        )
+    }
+}
+
+impl fmt::Debug for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Phase")
+            .field("name", &self.name)
+            .field("num_requests", &self.num_requests)
+            .field("duration", &self.duration)
+            .field("distribution", &self.distribution)
+            .field("key_range", &self.key_range)
+            .field("op_mix", &self.op_mix.as_ref().map(ToString::to_string))
+            .field("num_clients", &self.num_clients)
+            .finish()
+    }
+}
+
+impl fmt::Debug for KeyDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uniform => f.write_str("Uniform"),
+            Self::Zipf(zipf) => write!(f, "Zipf({})", zipf.theta),
+            Self::Scan => f.write_str("Scan"),
+            Self::Loop => f.write_str("Loop"),
+        }
+    }
+}
+
+/// A phase of a phased workload to run, with the config to run it with.
+pub(crate) struct PhaseRun {
+    pub(crate) name: String,
+    pub(crate) config: Config,
+    pub(crate) num_clients: u16,
+    /// The time to stop the clients at, if the phase has a duration.
+    pub(crate) duration: Option<Duration>,
+}
+
+/// Returns the phases of the phased workload of the config, each with a copy of
+/// the config to generate only its requests. `num_clients` is the number of
+/// clients of the phases that do not give it. A phase with a duration is always
+/// streamed, as the requests are generated until the clients stop.
+pub(crate) fn phase_runs(config: &Config, num_clients: u16) -> Vec<PhaseRun> {
+    config
+        .trace_file
+        .phases()
+        .iter()
+        .map(|phase| {
+            let workload = PhasedWorkload {
+                name: format!("{}/{}", config.trace_file.name, phase.name),
+                phases: vec![phase.clone()],
+            };
+            let mut phase_config = config.clone();
+            phase_config.trace_file = TraceDescriptor::synthetic(super::Workload::Phased(workload));
+            if phase.op_mix.is_some() {
+                phase_config.set_op_mix(phase.op_mix.clone());
+            }
+            if phase.duration.is_some() {
+                phase_config.set_streaming(true);
+            }
+            PhaseRun {
+                name: phase.name.clone(),
+                config: phase_config,
+                num_clients: phase.num_clients.unwrap_or(num_clients),
+                duration: phase.duration,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workload::Workload;
+
+    const TWO_PHASES: &str = r#"
+        name = "shifting"
+        seed = 3
+
+        [[phase]]
+        name = "hot-a"
+        requests = 1_000
+        distribution = "zipf"
+        theta = 0.9
+        key_range = [0, 100]
+
+        [[phase]]
+        duration_secs = 0.5
+        distribution = "uniform"
+        key_range = [100, 200]
+        op_mix = "update=5%"
+        clients = 4
+    "#;
+
+    fn parse_err(contents: &str) -> String {
+        let err = PhasedWorkload::parse(contents, "test").expect_err("An error");
+        format!("{err:#}")
+    }
+
+    #[test]
+    fn parse_phases() -> anyhow::Result<()> {
+        let workload = PhasedWorkload::parse(TWO_PHASES, "default")?;
+        assert_eq!(workload.name(), "shifting");
+        assert_eq!(workload.num_keys(), 200);
+
+        let [hot_a, hot_b] = workload.phases() else {
+            panic!("Two phases expected");
+        };
+        assert_eq!(hot_a.name(), "hot-a");
+        assert_eq!(hot_a.num_requests, Some(1_000));
+        assert_eq!(hot_a.duration, None);
+        assert!(matches!(&hot_a.distribution, KeyDistribution::Zipf(zipf) if zipf.theta == 0.9));
+        assert_eq!(hot_a.key_range, 0..100);
+        assert!(hot_a.op_mix.is_none() && hot_a.num_clients.is_none());
+
+        assert_eq!(hot_b.name(), "phase-2");
+        assert_eq!(hot_b.num_requests, None);
+        assert_eq!(hot_b.duration, Some(Duration::from_millis(500)));
+        assert!(matches!(hot_b.distribution, KeyDistribution::Uniform));
+        assert_eq!(hot_b.key_range, 100..200);
+        assert!(hot_b.op_mix.is_some());
+        assert_eq!(hot_b.num_clients, Some(4));
+        // Each phase draws its keys from its own sequence.
+        assert_ne!(hot_a.seed, hot_b.seed);
+
+        let keys = hot_a
+            .entries()
+            .map(|entry| entry.map(|e| e.range()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(keys.len(), 1_000);
+        assert!(keys
+            .iter()
+            .all(|range| range.len() == 1 && range.end <= 100));
+        Ok(())
+    }
+
+    #[test]
+    fn scan_ends_without_requests() -> anyhow::Result<()> {
+        let workload = PhasedWorkload::parse(
+            r#"
+            [[phase]]
+            distribution = "scan"
+            key_range = [10, 15]
+            "#,
+            "scan",
+        )?;
+        assert_eq!(workload.name(), "scan");
+        let keys = workload
+            .entries()
+            .map(|entry| entry.map(|e| e.range().start))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(keys, [10, 11, 12, 13, 14]);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_phases() {
+        let phase = |fields: &str| format!("[[phase]]\nname = \"p\"\n{fields}");
+
+        assert!(parse_err("").contains("no phases"));
+        assert!(parse_err(&phase(
+            "requests = 1\ndistribution = \"uniform\"\nkey_range = [0, 1]\nsize = 2"
+        ))
+        .contains("unknown field"));
+        assert!(parse_err(&phase("requests = 1\ndistribution = \"zipf\""))
+            .contains("missing field `key_range`"));
+        assert!(parse_err(&phase(
+            "requests = 1\ndistribution = \"pareto\"\nkey_range = [0, 1]"
+        ))
+        .contains("unknown variant"));
+
+        let cases = [
+            (
+                "requests = 1\ndistribution = \"uniform\"\nkey_range = [5, 5]",
+                "key_range [5, 5] must have the start less than the end",
+            ),
+            (
+                "requests = 1\ndistribution = \"uniform\"\ntheta = 0.5\nkey_range = [0, 10]",
+                r#"theta is only for distribution "zipf""#,
+            ),
+            (
+                "requests = 0\ndistribution = \"uniform\"\nkey_range = [0, 10]",
+                "requests must be positive",
+            ),
+            (
+                "duration_secs = -1.0\ndistribution = \"uniform\"\nkey_range = [0, 10]",
+                "duration_secs -1 must be positive",
+            ),
+            (
+                "distribution = \"loop\"\nkey_range = [0, 10]",
+                "Either requests or duration_secs is required",
+            ),
+            (
+                "requests = 1\ndistribution = \"uniform\"\nkey_range = [0, 10]\nclients = 0",
+                "clients must be positive",
+            ),
+            (
+                "requests = 1\ndistribution = \"uniform\"\nkey_range = [0, 10]\nop_mix = \"x=1\"",
+                r#"Cannot parse op_mix "x=1""#,
+            ),
+        ];
+        for (fields, message) in cases {
+            let err = parse_err(&phase(fields));
+            assert!(err.starts_with(r#"Phase "p": "#), "{err}");
+            assert!(err.contains(message), "{err}");
+        }
+    }
+
+    #[test]
+    fn stream_phases_with_duration() -> anyhow::Result<()> {
+        let workload = PhasedWorkload::parse(TWO_PHASES, "default")?;
+        let trace_file = TraceDescriptor::synthetic(Workload::Phased(workload));
+        let config = Config::new(trace_file, None, None, None, None, None);
+        assert!(!config.streaming);
+
+        let runs = phase_runs(&config, 2);
+        assert_eq!(runs.len(), 2);
+
+        assert_eq!(runs[0].name, "hot-a");
+        assert_eq!(runs[0].num_clients, 2);
+        assert_eq!(runs[0].duration, None);
+        assert!(!runs[0].config.streaming);
+        assert!(runs[0].config.op_mix_spec.is_none());
+        assert_eq!(runs[0].config.trace_file.name, "shifting/hot-a");
+
+        // The phase with a duration is streamed, as it has no end.
+        assert_eq!(runs[1].name, "phase-2");
+        assert_eq!(runs[1].num_clients, 4);
+        assert_eq!(runs[1].duration, Some(Duration::from_millis(500)));
+        assert!(runs[1].config.streaming);
+        assert!(runs[1].config.op_mix_spec.is_some());
+        Ok(())
+    }
+}